@@ -3,14 +3,19 @@ use log::{error, info};
 use serde::Deserialize;
 use std::{collections::HashMap, fs};
 
-use crate::auxiliary_data::AuxiliaryManifest;
+use crate::{auxiliary_data::AuxiliaryManifest, frame::FRAME_DATA_VERSION};
 
-use super::ShadersConfig;
+use super::{
+    parameters::{is_wgsl_identifier, ParameterManifest},
+    shader::preprocess_shader,
+    ShadersConfig,
+};
 
 #[derive(Clone, Debug)]
 pub struct AnimationManifest {
     pub shader: String,
     pub auxiliaries: Option<Vec<AuxiliaryManifest>>,
+    pub parameters: Option<Vec<ParameterManifest>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -18,13 +23,23 @@ pub struct ShaderManifest {
     pub id: String,
     pub shader: String,
     pub auxiliaries: Option<Vec<AuxiliaryManifest>>,
+    pub parameters: Option<Vec<ParameterManifest>>,
+    // Prepend the generated pipeline prelude, defaults to true
+    pub prelude: Option<bool>,
+    // Version of the `FrameData` layout the shader was written for, defaults to the latest
+    pub frame_data_version: Option<u32>,
 }
 
 impl AnimationManifest {
-    fn new(shader: String, auxiliaries: Option<Vec<AuxiliaryManifest>>) -> AnimationManifest {
+    fn new(
+        shader: String,
+        auxiliaries: Option<Vec<AuxiliaryManifest>>,
+        parameters: Option<Vec<ParameterManifest>>,
+    ) -> AnimationManifest {
         AnimationManifest {
             shader,
             auxiliaries,
+            parameters,
         }
     }
 }
@@ -45,12 +60,13 @@ impl AnimationRegistry {
         id: String,
         shader: String,
         auxiliaries: Option<Vec<AuxiliaryManifest>>,
+        parameters: Option<Vec<ParameterManifest>>,
     ) -> Result<(), AnimationRegistryRegisterError> {
         if self.animations.contains_key(&id) {
             return Err(AnimationRegistryRegisterError::AnimationExistsWithId);
         }
 
-        let manifest = AnimationManifest::new(shader, auxiliaries);
+        let manifest = AnimationManifest::new(shader, auxiliaries, parameters);
         self.animations.insert(id, manifest);
 
         Ok(())
@@ -109,6 +125,21 @@ fn register_animations_from_config(config: &ShadersConfig, registry: &mut Animat
             }
             let shader_manifest = shader_manifest.unwrap();
 
+            // Parameter ids are field names in the generated WGSL, so would otherwise only fail once the shader is compiled
+            let invalid_parameter = shader_manifest
+                .parameters
+                .iter()
+                .flatten()
+                .map(|parameter| parameter.get_id())
+                .find(|id| !is_wgsl_identifier(id));
+            if let Some(id) = invalid_parameter {
+                error!(
+                    "Cannot register shader {}, parameter id {} is not a valid WGSL identifier",
+                    shader_manifest.id, id
+                );
+                continue;
+            }
+
             let mut shader_path = shader_manifest_path.clone();
             shader_path.pop();
             shader_path.push(shader_manifest.shader);
@@ -118,6 +149,24 @@ fn register_animations_from_config(config: &ShadersConfig, registry: &mut Animat
                 error!("Unable to read shader {}", shader_path.display());
                 continue;
             }
+            let shader = preprocess_shader(
+                &shader.unwrap(),
+                &shader_path,
+                &config.include_folders,
+                shader_manifest.prelude.unwrap_or(true),
+                &shader_manifest.parameters.clone().unwrap_or_default(),
+                shader_manifest
+                    .frame_data_version
+                    .unwrap_or(FRAME_DATA_VERSION),
+            );
+            if let Err(err) = shader {
+                error!(
+                    "Unable to preprocess shader {} ({:?})",
+                    shader_path.display(),
+                    err
+                );
+                continue;
+            }
             let shader = shader.unwrap();
 
             info!("Registering shader {}", shader_manifest.id);
@@ -125,6 +174,7 @@ fn register_animations_from_config(config: &ShadersConfig, registry: &mut Animat
                 shader_manifest.id.clone(),
                 shader,
                 shader_manifest.auxiliaries,
+                shader_manifest.parameters,
             ) {
                 error!("Error registering shader {}, {:?}", shader_manifest.id, err);
             }
@@ -1,11 +1,13 @@
 use std::collections::HashMap;
+use std::fmt::{self, Display};
 use std::sync::Mutex;
 
-use crate::id::AnimationId;
+use crate::{auxiliary_data::ManifestValue, id::AnimationId};
 
 use super::{
     factory::{get_animation_registry, AnimationRegistry},
-    Animation, AnimationTargetType, ShadersConfig,
+    time::AnimationTimeControls,
+    Animation, AnimationParameterError, AnimationTargetType, ShadersConfig,
 };
 
 pub struct AnimationManager {
@@ -16,6 +18,22 @@ pub struct AnimationManager {
 #[derive(Debug)]
 pub enum AnimationManagerError {
     AnimationIsNotRegistered,
+    AnimationDoesNotExist(AnimationId),
+    Parameter(AnimationParameterError),
+}
+
+impl Display for AnimationManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationManagerError::AnimationIsNotRegistered => {
+                write!(f, "No shader is registered with that id")
+            }
+            AnimationManagerError::AnimationDoesNotExist(id) => {
+                write!(f, "Animation {} does not exist", id)
+            }
+            AnimationManagerError::Parameter(err) => write!(f, "{}", err),
+        }
+    }
 }
 
 pub trait RecvAnimationManagerState: Send + Sync {
@@ -56,8 +74,47 @@ impl AnimationManager {
         Ok(id)
     }
 
-    pub fn destroy_animation(&self, _id: &AnimationId) {
-        // TODO
+    /// Removes the animation, the pipeline unloads its shader when it next receives the context.
+    pub fn destroy_animation(&self, id: &AnimationId) -> Result<(), AnimationManagerError> {
+        self.animations
+            .lock()
+            .unwrap()
+            .remove(id)
+            .map(|_| ())
+            .ok_or_else(|| AnimationManagerError::AnimationDoesNotExist(id.clone()))
+    }
+
+    /// Sets a per-animation parameter, the value is converted to the type declared in the shader manifest.
+    pub fn set_parameter(
+        &self,
+        animation_id: &AnimationId,
+        name: &str,
+        value: ManifestValue,
+    ) -> Result<(), AnimationManagerError> {
+        let mut animations = self.animations.lock().unwrap();
+        let animation = animations
+            .get_mut(animation_id)
+            .ok_or_else(|| AnimationManagerError::AnimationDoesNotExist(animation_id.clone()))?;
+
+        animation
+            .set_parameter(name, &value)
+            .map_err(AnimationManagerError::Parameter)
+    }
+
+    /// Sets how the animation's time runs, e.g. to slow it down or pause it.
+    pub fn set_time_controls(
+        &self,
+        animation_id: &AnimationId,
+        time_controls: AnimationTimeControls,
+    ) -> Result<(), AnimationManagerError> {
+        let mut animations = self.animations.lock().unwrap();
+        let animation = animations
+            .get_mut(animation_id)
+            .ok_or_else(|| AnimationManagerError::AnimationDoesNotExist(animation_id.clone()))?;
+
+        animation.set_time_controls(time_controls);
+
+        Ok(())
     }
 
     pub fn get_animation(&self, id: &AnimationId) -> Option<Animation> {
@@ -1,21 +1,52 @@
+use std::fmt::{self, Display};
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    auxiliary_data::{AuxiliaryDataTypeConsumer, AuxiliaryManifest},
+    auxiliary_data::{AuxiliaryDataTypeConsumer, AuxiliaryManifest, ManifestValue},
     id::{AnimationId, FixtureId, GroupId, InstallationId},
     world::{context::WorldContext, Coord},
 };
 
-use self::factory::AnimationManifest;
+use self::{
+    factory::AnimationManifest,
+    parameters::{pack_parameters, ParameterManifest, ParameterValue},
+    time::AnimationTimeControls,
+};
 
 pub mod factory;
 pub mod manager;
+pub mod parameters;
+pub mod shader;
+pub mod time;
 
 #[derive(Clone, Debug)]
 pub struct Animation {
     id: AnimationId,
     manifest: AnimationManifest,
     pub target: AnimationTargetType,
+    // Ordered as the parameters in the manifest
+    parameter_values: Vec<ParameterValue>,
+    time_controls: AnimationTimeControls,
+}
+
+#[derive(Debug)]
+pub enum AnimationParameterError {
+    ParameterDoesNotExist(String),
+    IncompatibleValue(String),
+}
+
+impl Display for AnimationParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnimationParameterError::ParameterDoesNotExist(name) => {
+                write!(f, "No parameter named {}", name)
+            }
+            AnimationParameterError::IncompatibleValue(name) => {
+                write!(f, "Value doesn't match the type of parameter {}", name)
+            }
+        }
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
@@ -28,6 +59,9 @@ pub enum AnimationTargetType {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ShadersConfig {
     shader_folders: Vec<String>,
+    // Folders searched for `#include`d library shaders
+    #[serde(default = "default_include_folders")]
+    include_folders: Vec<String>,
 }
 
 pub trait AnimationTarget {
@@ -37,10 +71,20 @@ pub trait AnimationTarget {
 
 impl Animation {
     pub fn new(manifest: AnimationManifest, target: AnimationTargetType) -> Animation {
+        let parameter_values = manifest
+            .parameters
+            .clone()
+            .unwrap_or_default()
+            .iter()
+            .map(|parameter| parameter.get_default_value())
+            .collect();
+
         Animation {
             id: AnimationId::new(),
             manifest,
             target,
+            parameter_values,
+            time_controls: AnimationTimeControls::default(),
         }
     }
 
@@ -66,16 +110,65 @@ impl Animation {
             .clone()
             .map(|auxiliaries| auxiliaries.iter().map(|aux| aux.get_aux_type()).collect())
     }
+
+    pub fn get_parameters(&self) -> Vec<ParameterManifest> {
+        self.manifest.parameters.clone().unwrap_or_default()
+    }
+
+    pub fn get_parameter_value(&self, name: &str) -> Option<ParameterValue> {
+        let index = self
+            .get_parameters()
+            .iter()
+            .position(|parameter| parameter.get_id() == name)?;
+        self.parameter_values.get(index).cloned()
+    }
+
+    pub fn set_parameter(
+        &mut self,
+        name: &str,
+        value: &ManifestValue,
+    ) -> Result<(), AnimationParameterError> {
+        let parameters = self.get_parameters();
+        let index = parameters
+            .iter()
+            .position(|parameter| parameter.get_id() == name)
+            .ok_or_else(|| AnimationParameterError::ParameterDoesNotExist(name.to_string()))?;
+
+        let value = parameters[index]
+            .value_from(value)
+            .ok_or_else(|| AnimationParameterError::IncompatibleValue(name.to_string()))?;
+        self.parameter_values[index] = value;
+
+        Ok(())
+    }
+
+    pub fn get_time_controls(&self) -> &AnimationTimeControls {
+        &self.time_controls
+    }
+
+    pub fn set_time_controls(&mut self, time_controls: AnimationTimeControls) {
+        self.time_controls = time_controls;
+    }
+
+    /// Parameter values laid out as the WGSL `Parameters` uniform struct.
+    pub fn get_parameters_buffer(&self) -> Vec<u8> {
+        pack_parameters(&self.get_parameters(), &self.parameter_values)
+    }
 }
 
 impl Default for ShadersConfig {
     fn default() -> Self {
         Self {
             shader_folders: vec!["shaders".to_string()],
+            include_folders: default_include_folders(),
         }
     }
 }
 
+fn default_include_folders() -> Vec<String> {
+    vec!["shaders/lib".to_string()]
+}
+
 impl From<AnimationTargetType> for String {
     fn from(target: AnimationTargetType) -> Self {
         match target {
@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+
+use crate::auxiliary_data::{ManifestControl, ManifestValue};
+
+/// A value owned by a single animation instance, bound to the shader as a field of a uniform buffer.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ParameterManifest {
+    // Used as the field name in the generated WGSL `Parameters` struct
+    id: String,
+    name: String,
+    description: Option<String>,
+    #[serde(rename = "type")]
+    param_type: ParameterType,
+    #[serde(flatten)]
+    control: ManifestControl,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ParameterType {
+    F32,
+    I32,
+    U32,
+    Bool,
+    Vec2,
+    Vec3,
+    Vec4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterValue {
+    F32(f32),
+    I32(i32),
+    U32(u32),
+    Bool(bool),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+}
+
+impl ParameterManifest {
+    pub fn get_id(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn get_default_value(&self) -> ParameterValue {
+        self.control
+            .default
+            .as_ref()
+            .and_then(|default| self.value_from(default))
+            .unwrap_or_else(|| self.param_type.zero())
+    }
+
+    /// Converts an untyped value into the type declared in the manifest, clamping it to the declared range.
+    pub fn value_from(&self, value: &ManifestValue) -> Option<ParameterValue> {
+        let clamp = |value: f32| self.control.clamp(value);
+
+        match (&self.param_type, value) {
+            (ParameterType::F32, ManifestValue::Number(value)) => {
+                Some(ParameterValue::F32(clamp(*value)))
+            }
+            (ParameterType::I32, ManifestValue::Number(value)) => {
+                Some(ParameterValue::I32(clamp(*value).round() as i32))
+            }
+            (ParameterType::U32, ManifestValue::Number(value)) => {
+                Some(ParameterValue::U32(clamp(*value).round().max(0.0) as u32))
+            }
+            (ParameterType::Bool, ManifestValue::Bool(value)) => Some(ParameterValue::Bool(*value)),
+            (ParameterType::Bool, ManifestValue::Number(value)) => {
+                Some(ParameterValue::Bool(*value != 0.0))
+            }
+            (ParameterType::Vec2, ManifestValue::List(values)) if values.len() == 2 => {
+                Some(ParameterValue::Vec2([clamp(values[0]), clamp(values[1])]))
+            }
+            (ParameterType::Vec3, ManifestValue::List(values)) if values.len() == 3 => {
+                Some(ParameterValue::Vec3([
+                    clamp(values[0]),
+                    clamp(values[1]),
+                    clamp(values[2]),
+                ]))
+            }
+            (ParameterType::Vec4, ManifestValue::List(values)) if values.len() == 4 => {
+                Some(ParameterValue::Vec4([
+                    clamp(values[0]),
+                    clamp(values[1]),
+                    clamp(values[2]),
+                    clamp(values[3]),
+                ]))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ParameterType {
+    fn zero(&self) -> ParameterValue {
+        match self {
+            ParameterType::F32 => ParameterValue::F32(0.0),
+            ParameterType::I32 => ParameterValue::I32(0),
+            ParameterType::U32 => ParameterValue::U32(0),
+            ParameterType::Bool => ParameterValue::Bool(false),
+            ParameterType::Vec2 => ParameterValue::Vec2([0.0; 2]),
+            ParameterType::Vec3 => ParameterValue::Vec3([0.0; 3]),
+            ParameterType::Vec4 => ParameterValue::Vec4([0.0; 4]),
+        }
+    }
+
+    pub fn wgsl_type(&self) -> &'static str {
+        match self {
+            ParameterType::F32 => "f32",
+            ParameterType::I32 => "i32",
+            // WGSL booleans cannot be stored in buffers
+            ParameterType::U32 | ParameterType::Bool => "u32",
+            ParameterType::Vec2 => "vec2<f32>",
+            ParameterType::Vec3 => "vec3<f32>",
+            ParameterType::Vec4 => "vec4<f32>",
+        }
+    }
+
+    /// Alignment in bytes of this type inside a WGSL uniform buffer.
+    fn alignment(&self) -> usize {
+        match self {
+            ParameterType::F32 | ParameterType::I32 | ParameterType::U32 | ParameterType::Bool => 4,
+            ParameterType::Vec2 => 8,
+            ParameterType::Vec3 | ParameterType::Vec4 => 16,
+        }
+    }
+}
+
+impl ParameterValue {
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            ParameterValue::F32(val) => val.to_le_bytes().to_vec(),
+            ParameterValue::I32(val) => val.to_le_bytes().to_vec(),
+            ParameterValue::U32(val) => val.to_le_bytes().to_vec(),
+            ParameterValue::Bool(val) => u32::from(*val).to_le_bytes().to_vec(),
+            ParameterValue::Vec2(val) => bytemuck::cast_slice(val).to_vec(),
+            ParameterValue::Vec3(val) => bytemuck::cast_slice(val).to_vec(),
+            ParameterValue::Vec4(val) => bytemuck::cast_slice(val).to_vec(),
+        }
+    }
+}
+
+/// Packs parameter values following the WGSL uniform buffer layout rules for a struct of the given parameters.
+pub fn pack_parameters(manifests: &[ParameterManifest], values: &[ParameterValue]) -> Vec<u8> {
+    let mut buffer: Vec<u8> = vec![];
+
+    for (index, manifest) in manifests.iter().enumerate() {
+        let alignment = manifest.param_type.alignment();
+        buffer.resize(align_to(buffer.len(), alignment), 0);
+
+        let value = values
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| manifest.get_default_value());
+        buffer.extend(value.to_bytes());
+    }
+
+    // Uniform buffer structs are rounded up to a multiple of 16 bytes
+    buffer.resize(align_to(buffer.len().max(1), 16), 0);
+
+    buffer
+}
+
+/// WGSL declaration of the `Parameters` struct matching `pack_parameters`.
+pub fn parameters_wgsl_struct(manifests: &[ParameterManifest]) -> String {
+    let fields: String = manifests
+        .iter()
+        .map(|manifest| {
+            format!(
+                "    {}: {};\n",
+                manifest.id,
+                manifest.param_type.wgsl_type()
+            )
+        })
+        .collect();
+
+    format!("struct Parameters {{\n{}}};\n", fields)
+}
+
+// Words WGSL reserves, which can't be used as parameter ids
+const WGSL_KEYWORDS: &[&str] = &[
+    "array",
+    "atomic",
+    "bitcast",
+    "bool",
+    "break",
+    "case",
+    "const",
+    "continue",
+    "continuing",
+    "default",
+    "discard",
+    "else",
+    "enable",
+    "f32",
+    "fallthrough",
+    "false",
+    "fn",
+    "for",
+    "function",
+    "i32",
+    "if",
+    "let",
+    "loop",
+    "mat2x2",
+    "mat2x3",
+    "mat2x4",
+    "mat3x2",
+    "mat3x3",
+    "mat3x4",
+    "mat4x2",
+    "mat4x3",
+    "mat4x4",
+    "override",
+    "private",
+    "ptr",
+    "read",
+    "read_write",
+    "return",
+    "sampler",
+    "storage",
+    "struct",
+    "switch",
+    "true",
+    "type",
+    "u32",
+    "uniform",
+    "var",
+    "vec2",
+    "vec3",
+    "vec4",
+    "while",
+    "workgroup",
+    "write",
+];
+
+/// Whether `id` can be used as a field name in the generated `Parameters` struct.
+pub fn is_wgsl_identifier(id: &str) -> bool {
+    let mut chars = id.chars();
+    let starts_validly = matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_');
+
+    starts_validly
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        // `_` alone and names starting with `__` are reserved
+        && id != "_"
+        && !id.starts_with("__")
+        && !WGSL_KEYWORDS.contains(&id)
+}
+
+fn align_to(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameter_ids_must_be_wgsl_identifiers() {
+        for id in ["speed", "_offset", "colour_2", "Width"] {
+            assert!(is_wgsl_identifier(id), "{} should be allowed", id);
+        }
+        for id in [
+            "",
+            "2speed",
+            "fade-time",
+            "fade time",
+            "_",
+            "__width",
+            "loop",
+            "vec3",
+        ] {
+            assert!(!is_wgsl_identifier(id), "{} should not be allowed", id);
+        }
+    }
+}
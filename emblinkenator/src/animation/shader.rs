@@ -0,0 +1,129 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{frame::FRAME_DATA_VERSION, pipeline::prelude::get_shader_prelude};
+
+use super::parameters::ParameterManifest;
+
+const INCLUDE_DIRECTIVE: &str = "#include";
+
+#[derive(Debug, PartialEq)]
+pub enum ShaderPreprocessError {
+    MalformedInclude(String),
+    IncludeNotFound(String),
+    UnreadableInclude(PathBuf),
+    UnsupportedFrameDataVersion(u32),
+}
+
+/// Resolves `#include "file.wgsl"` directives and optionally prepends the generated pipeline prelude.
+///
+/// Includes are resolved relative to the including file first, then against each of the include folders.
+/// Each file is only included once, so libraries may safely include each other.
+pub fn preprocess_shader(
+    source: &str,
+    shader_path: &Path,
+    include_folders: &[String],
+    with_prelude: bool,
+    parameters: &[ParameterManifest],
+    frame_data_version: u32,
+) -> Result<String, ShaderPreprocessError> {
+    if frame_data_version == 0 || frame_data_version > FRAME_DATA_VERSION {
+        return Err(ShaderPreprocessError::UnsupportedFrameDataVersion(
+            frame_data_version,
+        ));
+    }
+
+    let mut included: HashSet<PathBuf> = HashSet::new();
+    if let Ok(canonical_path) = shader_path.canonicalize() {
+        included.insert(canonical_path);
+    }
+
+    let body = resolve_includes(source, shader_path, include_folders, &mut included)?;
+
+    if with_prelude {
+        Ok(format!(
+            "{}{}",
+            get_shader_prelude(parameters, frame_data_version),
+            body
+        ))
+    } else {
+        Ok(body)
+    }
+}
+
+fn resolve_includes(
+    source: &str,
+    file_path: &Path,
+    include_folders: &[String],
+    included: &mut HashSet<PathBuf>,
+) -> Result<String, ShaderPreprocessError> {
+    let mut output = String::with_capacity(source.len());
+
+    for line in source.lines() {
+        let trimmed = line.trim();
+        if !trimmed.starts_with(INCLUDE_DIRECTIVE) {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        }
+
+        let include_name = parse_include(trimmed)?;
+        let include_path = find_include(&include_name, file_path, include_folders)
+            .ok_or_else(|| ShaderPreprocessError::IncludeNotFound(include_name.clone()))?;
+
+        let canonical_path = include_path
+            .canonicalize()
+            .map_err(|_| ShaderPreprocessError::UnreadableInclude(include_path.clone()))?;
+        if !included.insert(canonical_path) {
+            // Already included, skip it
+            continue;
+        }
+
+        let include_source = fs::read_to_string(&include_path)
+            .map_err(|_| ShaderPreprocessError::UnreadableInclude(include_path.clone()))?;
+
+        output.push_str(&format!("// ---- Begin include {} ----\n", include_name));
+        output.push_str(&resolve_includes(
+            &include_source,
+            &include_path,
+            include_folders,
+            included,
+        )?);
+        output.push_str(&format!("// ---- End include {} ----\n", include_name));
+    }
+
+    Ok(output)
+}
+
+fn parse_include(line: &str) -> Result<String, ShaderPreprocessError> {
+    let name = line[INCLUDE_DIRECTIVE.len()..].trim();
+
+    if name.len() < 2 {
+        return Err(ShaderPreprocessError::MalformedInclude(line.to_string()));
+    }
+
+    let quoted = (name.starts_with('"') && name.ends_with('"'))
+        || (name.starts_with('<') && name.ends_with('>'));
+    if !quoted {
+        return Err(ShaderPreprocessError::MalformedInclude(line.to_string()));
+    }
+
+    Ok(name[1..name.len() - 1].to_string())
+}
+
+fn find_include(name: &str, file_path: &Path, include_folders: &[String]) -> Option<PathBuf> {
+    let mut candidates: Vec<PathBuf> = vec![];
+
+    if let Some(parent) = file_path.parent() {
+        candidates.push(parent.join(name));
+    }
+
+    for folder in include_folders {
+        candidates.push(Path::new(folder).join(name));
+    }
+
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::frame::FrameData;
+
+/// How an animation's time runs relative to the frame clock.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct AnimationTimeControls {
+    // Multiplier on the rate time passes at
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    // Time stops where it is while paused
+    #[serde(default)]
+    pub paused: bool,
+    // Seconds added to the animation's time
+    #[serde(default)]
+    pub offset: f64,
+    // Time runs backwards, stopping at zero
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+/// Follows the frame clock and gives each frame the animation's own time.
+/// Changing the controls takes effect from the current time, so time never jumps unless the offset changes.
+pub struct AnimationClock {
+    // Frame clock and animation seconds at the last change of rate
+    anchor_seconds: f64,
+    anchor_animation_seconds: f64,
+    rate: f64,
+}
+
+impl AnimationTimeControls {
+    fn rate(&self) -> f64 {
+        if self.paused {
+            0.0
+        } else if self.reverse {
+            -self.speed
+        } else {
+            self.speed
+        }
+    }
+}
+
+impl Default for AnimationTimeControls {
+    fn default() -> Self {
+        AnimationTimeControls {
+            speed: default_speed(),
+            paused: false,
+            offset: 0.0,
+            reverse: false,
+        }
+    }
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl AnimationClock {
+    pub fn new() -> Self {
+        AnimationClock {
+            anchor_seconds: 0.0,
+            anchor_animation_seconds: 0.0,
+            rate: 1.0,
+        }
+    }
+
+    /// Frame data for the animation, unchanged while the controls are the defaults.
+    pub fn frame_data(
+        &mut self,
+        frame_data: &FrameData,
+        controls: &AnimationTimeControls,
+    ) -> FrameData {
+        let seconds = frame_data.precise_seconds_elapsed();
+        let rate = controls.rate();
+
+        if rate != self.rate {
+            self.anchor_animation_seconds = self.animation_seconds(seconds);
+            self.anchor_seconds = seconds;
+            self.rate = rate;
+        }
+
+        // Avoids rounding through f64 when the animation runs on the frame clock's time
+        if self.rate == 1.0
+            && self.anchor_seconds == self.anchor_animation_seconds
+            && controls.offset == 0.0
+        {
+            return frame_data.clone();
+        }
+
+        let animation_seconds = self.animation_seconds(seconds) + controls.offset;
+        frame_data.with_elapsed(Duration::from_secs_f64(animation_seconds.max(0.0)))
+    }
+
+    fn animation_seconds(&self, seconds: f64) -> f64 {
+        let animation_seconds =
+            self.anchor_animation_seconds + (seconds - self.anchor_seconds) * self.rate;
+        animation_seconds.max(0.0)
+    }
+}
@@ -0,0 +1,568 @@
+use std::{
+    fmt::{self, Display},
+    iter::Peekable,
+    str::CharIndices,
+};
+
+use crate::id::AuxiliaryId;
+
+/// Expression computing a derived auxiliary from other auxiliaries, e.g. `0.5 + 0.5 * sin(t * bpm / 60) * audio_level`.
+/// `t` is the number of seconds elapsed, any other name is the value of the auxiliary with that id.
+#[derive(Clone, Debug)]
+pub enum Expression {
+    Number(f32),
+    Time,
+    Auxiliary(AuxiliaryId),
+    Negate(Box<Expression>),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    Call(Function, Vec<Expression>),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Abs,
+    Floor,
+    Ceil,
+    Fract,
+    Sqrt,
+    Exp,
+    Log,
+    Min,
+    Max,
+    Pow,
+    Clamp,
+    Mix,
+    Step,
+    Smoothstep,
+}
+
+#[derive(Debug)]
+pub enum ExpressionError {
+    UnexpectedCharacter(usize, char),
+    UnexpectedToken(usize, String),
+    UnexpectedEnd,
+    UnknownFunction(String),
+    WrongNumberOfArguments {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpressionError::UnexpectedCharacter(position, c) => {
+                write!(f, "Unexpected '{}' at character {}", c, position)
+            }
+            ExpressionError::UnexpectedToken(position, token) => {
+                write!(f, "Unexpected {} at character {}", token, position)
+            }
+            ExpressionError::UnexpectedEnd => write!(f, "Unexpected end of expression"),
+            ExpressionError::UnknownFunction(name) => write!(f, "Unknown function {}", name),
+            ExpressionError::WrongNumberOfArguments {
+                function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} arguments but was given {}",
+                function, expected, found
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Operator(&'static str),
+    OpenParen,
+    CloseParen,
+    Comma,
+}
+
+// Longest first, so `<=` isn't read as `<`
+const OPERATORS: [&str; 12] = [
+    "<=", ">=", "==", "!=", "+", "-", "*", "/", "%", "^", "<", ">",
+];
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        let function = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "abs" => Function::Abs,
+            "floor" => Function::Floor,
+            "ceil" => Function::Ceil,
+            "fract" => Function::Fract,
+            "sqrt" => Function::Sqrt,
+            "exp" => Function::Exp,
+            "log" => Function::Log,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "pow" => Function::Pow,
+            "clamp" => Function::Clamp,
+            "mix" => Function::Mix,
+            "step" => Function::Step,
+            "smoothstep" => Function::Smoothstep,
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    fn number_of_arguments(&self) -> usize {
+        match self {
+            Function::Min | Function::Max | Function::Pow | Function::Step => 2,
+            Function::Clamp | Function::Mix | Function::Smoothstep => 3,
+            _ => 1,
+        }
+    }
+
+    fn apply(&self, args: &[f32]) -> f32 {
+        match (self, args) {
+            (Function::Sin, [x]) => x.sin(),
+            (Function::Cos, [x]) => x.cos(),
+            (Function::Tan, [x]) => x.tan(),
+            (Function::Abs, [x]) => x.abs(),
+            (Function::Floor, [x]) => x.floor(),
+            (Function::Ceil, [x]) => x.ceil(),
+            (Function::Fract, [x]) => x - x.floor(),
+            (Function::Sqrt, [x]) => x.sqrt(),
+            (Function::Exp, [x]) => x.exp(),
+            (Function::Log, [x]) => x.ln(),
+            (Function::Min, [a, b]) => a.min(*b),
+            (Function::Max, [a, b]) => a.max(*b),
+            (Function::Pow, [a, b]) => a.powf(*b),
+            (Function::Clamp, [x, low, high]) => x.max(*low).min(*high),
+            (Function::Mix, [a, b, amount]) => a + (b - a) * amount,
+            (Function::Step, [edge, x]) => f32::from(u8::from(x >= edge)),
+            (Function::Smoothstep, [low, high, x]) => {
+                let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+            _ => f32::NAN, // Argument counts are checked when parsing
+        }
+    }
+}
+
+impl BinaryOperator {
+    fn from_token(token: &Token) -> Option<Self> {
+        let operator = match token {
+            Token::Operator("+") => BinaryOperator::Add,
+            Token::Operator("-") => BinaryOperator::Subtract,
+            Token::Operator("*") => BinaryOperator::Multiply,
+            Token::Operator("/") => BinaryOperator::Divide,
+            Token::Operator("%") => BinaryOperator::Remainder,
+            Token::Operator("^") => BinaryOperator::Power,
+            Token::Operator("<") => BinaryOperator::Less,
+            Token::Operator("<=") => BinaryOperator::LessEqual,
+            Token::Operator(">") => BinaryOperator::Greater,
+            Token::Operator(">=") => BinaryOperator::GreaterEqual,
+            Token::Operator("==") => BinaryOperator::Equal,
+            Token::Operator("!=") => BinaryOperator::NotEqual,
+            _ => return None,
+        };
+        Some(operator)
+    }
+
+    /// Binding strength, higher binds tighter.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Less
+            | BinaryOperator::LessEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual
+            | BinaryOperator::Equal
+            | BinaryOperator::NotEqual => 1,
+            BinaryOperator::Add | BinaryOperator::Subtract => 2,
+            BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => 3,
+            BinaryOperator::Power => 5,
+        }
+    }
+
+    fn apply(&self, a: f32, b: f32) -> f32 {
+        match self {
+            BinaryOperator::Add => a + b,
+            BinaryOperator::Subtract => a - b,
+            BinaryOperator::Multiply => a * b,
+            BinaryOperator::Divide => a / b,
+            // Truncated like WGSL, so the result has the sign of `a`
+            BinaryOperator::Remainder => a % b,
+            BinaryOperator::Power => a.powf(b),
+            // Comparisons give 1.0 if true and 0.0 if false
+            BinaryOperator::Less => f32::from(u8::from(a < b)),
+            BinaryOperator::LessEqual => f32::from(u8::from(a <= b)),
+            BinaryOperator::Greater => f32::from(u8::from(a > b)),
+            BinaryOperator::GreaterEqual => f32::from(u8::from(a >= b)),
+            BinaryOperator::Equal => f32::from(u8::from(a == b)),
+            BinaryOperator::NotEqual => f32::from(u8::from(a != b)),
+        }
+    }
+}
+
+// Unary minus binds tighter than everything except powers, so -2^2 is -4
+const NEGATE_PRECEDENCE: u8 = 4;
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let tokens = tokenise(source)?;
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+        };
+
+        let expression = parser.parse_expression(0)?;
+        match parser.tokens.next() {
+            Some((position, token)) => Err(ExpressionError::UnexpectedToken(
+                position,
+                format!("{:?}", token),
+            )),
+            None => Ok(expression),
+        }
+    }
+
+    /// Evaluates the expression, `None` if an auxiliary it uses has no single value.
+    pub fn evaluate<F>(&self, seconds_elapsed: f32, auxiliary_value: &F) -> Option<f32>
+    where
+        F: Fn(&AuxiliaryId) -> Option<f32>,
+    {
+        let value = match self {
+            Expression::Number(value) => *value,
+            Expression::Time => seconds_elapsed,
+            Expression::Auxiliary(aux_id) => auxiliary_value(aux_id)?,
+            Expression::Negate(expression) => {
+                -expression.evaluate(seconds_elapsed, auxiliary_value)?
+            }
+            Expression::Binary(operator, a, b) => operator.apply(
+                a.evaluate(seconds_elapsed, auxiliary_value)?,
+                b.evaluate(seconds_elapsed, auxiliary_value)?,
+            ),
+            Expression::Call(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(seconds_elapsed, auxiliary_value))
+                    .collect::<Option<Vec<f32>>>()?;
+                function.apply(&args)
+            }
+        };
+
+        Some(value)
+    }
+}
+
+fn tokenise(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = vec![];
+    let mut chars: Peekable<CharIndices> = source.char_indices().peekable();
+
+    while let Some(&(position, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = position;
+            while let Some(&(index, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = index + c.len_utf8();
+                chars.next();
+            }
+            let number = source[position..end]
+                .parse()
+                .map_err(|_| ExpressionError::UnexpectedCharacter(position, c))?;
+            tokens.push((position, Token::Number(number)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = position;
+            while let Some(&(index, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = index + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((position, Token::Name(source[position..end].to_string())));
+        } else if c == '(' || c == ')' || c == ',' {
+            chars.next();
+            let token = match c {
+                '(' => Token::OpenParen,
+                ')' => Token::CloseParen,
+                _ => Token::Comma,
+            };
+            tokens.push((position, token));
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| source[position..].starts_with(*operator))
+                .ok_or(ExpressionError::UnexpectedCharacter(position, c))?;
+            for _ in 0..operator.len() {
+                chars.next();
+            }
+            tokens.push((position, Token::Operator(operator)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<(usize, Token)>>,
+}
+
+impl Parser {
+    /// Precedence climbing, parses operators that bind at least as tightly as `min_precedence`.
+    fn parse_expression(&mut self, min_precedence: u8) -> Result<Expression, ExpressionError> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some((_, token)) = self.tokens.peek() {
+            let operator = match BinaryOperator::from_token(token) {
+                Some(operator) if operator.precedence() >= min_precedence => operator,
+                _ => break,
+            };
+            self.tokens.next();
+
+            // Powers are right associative
+            let next_precedence = match operator {
+                BinaryOperator::Power => operator.precedence(),
+                _ => operator.precedence() + 1,
+            };
+            let rhs = self.parse_expression(next_precedence)?;
+            lhs = Expression::Binary(operator, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, ExpressionError> {
+        match self.tokens.peek() {
+            Some((_, Token::Operator("-"))) => {
+                self.tokens.next();
+                let expression = self.parse_expression(NEGATE_PRECEDENCE)?;
+                Ok(Expression::Negate(Box::new(expression)))
+            }
+            Some((_, Token::Operator("+"))) => {
+                self.tokens.next();
+                self.parse_expression(NEGATE_PRECEDENCE)
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, ExpressionError> {
+        let (position, token) = self.tokens.next().ok_or(ExpressionError::UnexpectedEnd)?;
+
+        match token {
+            Token::Number(value) => Ok(Expression::Number(value)),
+            Token::OpenParen => {
+                let expression = self.parse_expression(0)?;
+                self.expect(Token::CloseParen)?;
+                Ok(expression)
+            }
+            Token::Name(name) => {
+                if matches!(self.tokens.peek(), Some((_, Token::OpenParen))) {
+                    self.tokens.next();
+                    return self.parse_call(name);
+                }
+
+                Ok(match name.as_str() {
+                    "t" => Expression::Time,
+                    "pi" => Expression::Number(std::f32::consts::PI),
+                    "tau" => Expression::Number(std::f32::consts::TAU),
+                    _ => Expression::Auxiliary(AuxiliaryId::new_from(name)),
+                })
+            }
+            token => Err(ExpressionError::UnexpectedToken(
+                position,
+                format!("{:?}", token),
+            )),
+        }
+    }
+
+    fn parse_call(&mut self, name: String) -> Result<Expression, ExpressionError> {
+        let function = Function::from_name(&name)
+            .ok_or_else(|| ExpressionError::UnknownFunction(name.clone()))?;
+
+        let mut args = vec![];
+        if matches!(self.tokens.peek(), Some((_, Token::CloseParen))) {
+            self.tokens.next();
+        } else {
+            loop {
+                args.push(self.parse_expression(0)?);
+                match self.tokens.next() {
+                    Some((_, Token::Comma)) => continue,
+                    Some((_, Token::CloseParen)) => break,
+                    Some((position, token)) => {
+                        return Err(ExpressionError::UnexpectedToken(
+                            position,
+                            format!("{:?}", token),
+                        ))
+                    }
+                    None => return Err(ExpressionError::UnexpectedEnd),
+                }
+            }
+        }
+
+        if args.len() != function.number_of_arguments() {
+            return Err(ExpressionError::WrongNumberOfArguments {
+                function: name,
+                expected: function.number_of_arguments(),
+                found: args.len(),
+            });
+        }
+
+        Ok(Expression::Call(function, args))
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ExpressionError> {
+        match self.tokens.next() {
+            Some((_, token)) if token == expected => Ok(()),
+            Some((position, token)) => Err(ExpressionError::UnexpectedToken(
+                position,
+                format!("{:?}", token),
+            )),
+            None => Err(ExpressionError::UnexpectedEnd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str) -> f32 {
+        evaluate_with(source, 0.0, &|_| None)
+    }
+
+    fn evaluate_with<F>(source: &str, seconds_elapsed: f32, auxiliary_value: &F) -> f32
+    where
+        F: Fn(&AuxiliaryId) -> Option<f32>,
+    {
+        Expression::parse(source)
+            .unwrap_or_else(|err| panic!("Cannot parse {} ({})", source, err))
+            .evaluate(seconds_elapsed, auxiliary_value)
+            .unwrap()
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        assert_eq!(evaluate("1 + 2 * 3"), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3"), 9.0);
+        assert_eq!(evaluate("10 - 4 - 3"), 3.0);
+        assert_eq!(evaluate("12 / 3 / 2"), 2.0);
+        assert_eq!(evaluate("1 + 2 < 4"), 1.0);
+        assert_eq!(evaluate("2 * 3 == 6"), 1.0);
+    }
+
+    #[test]
+    fn powers_are_right_associative_and_bind_tighter_than_negation() {
+        assert_eq!(evaluate("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(evaluate("-2 ^ 2"), -4.0);
+        assert_eq!(evaluate("(-2) ^ 2"), 4.0);
+        assert_eq!(evaluate("2 * -3"), -6.0);
+        assert_eq!(evaluate("+3"), 3.0);
+    }
+
+    #[test]
+    fn remainder_has_the_sign_of_the_dividend() {
+        assert_eq!(evaluate("7 % 3"), 1.0);
+        assert_eq!(evaluate("-7 % 3"), -1.0);
+        assert_eq!(evaluate("7 % -3"), 1.0);
+        assert_eq!(evaluate("5.5 % 2"), 1.5);
+    }
+
+    #[test]
+    fn comparisons_give_one_or_zero() {
+        assert_eq!(evaluate("1 < 2"), 1.0);
+        assert_eq!(evaluate("2 <= 2"), 1.0);
+        assert_eq!(evaluate("1 > 2"), 0.0);
+        assert_eq!(evaluate("2 >= 3"), 0.0);
+        assert_eq!(evaluate("1 != 1"), 0.0);
+    }
+
+    #[test]
+    fn functions_match_wgsl() {
+        assert_eq!(evaluate("fract(-1.25)"), 0.75);
+        assert_eq!(evaluate("clamp(5, 0, 1)"), 1.0);
+        assert_eq!(evaluate("mix(2, 4, 0.25)"), 2.5);
+        assert_eq!(evaluate("step(0.5, 0.4)"), 0.0);
+        assert_eq!(evaluate("smoothstep(0, 1, 0.5)"), 0.5);
+        assert_eq!(evaluate("min(3, max(1, 2))"), 2.0);
+        assert_eq!(evaluate("pow(2, 10)"), 1024.0);
+        assert!((evaluate("sin(pi / 2)") - 1.0).abs() < 1e-6);
+        assert!((evaluate("cos(tau)") - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn names_are_time_or_auxiliaries() {
+        let auxiliary_value = |aux_id: &AuxiliaryId| match aux_id.unprotect().as_str() {
+            "bpm" => Some(120.0),
+            "audio_level" => Some(0.5),
+            _ => None,
+        };
+
+        assert_eq!(evaluate_with("t * 2", 1.5, &auxiliary_value), 3.0);
+        assert_eq!(
+            evaluate_with("bpm / 60 * audio_level", 0.0, &auxiliary_value),
+            1.0
+        );
+        assert!(Expression::parse("missing + 1")
+            .unwrap()
+            .evaluate(0.0, &auxiliary_value)
+            .is_none());
+    }
+
+    #[test]
+    fn parse_errors_are_reported() {
+        assert!(matches!(
+            Expression::parse("1 +"),
+            Err(ExpressionError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            Expression::parse("(1 + 2"),
+            Err(ExpressionError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            Expression::parse("1 2"),
+            Err(ExpressionError::UnexpectedToken(2, _))
+        ));
+        assert!(matches!(
+            Expression::parse("1 $ 2"),
+            Err(ExpressionError::UnexpectedCharacter(2, '$'))
+        ));
+        assert!(matches!(
+            Expression::parse("wobble(1)"),
+            Err(ExpressionError::UnknownFunction(_))
+        ));
+        assert!(matches!(
+            Expression::parse("clamp(1, 2)"),
+            Err(ExpressionError::WrongNumberOfArguments {
+                expected: 3,
+                found: 2,
+                ..
+            })
+        ));
+        assert_eq!(
+            Expression::parse("min(1)").unwrap_err().to_string(),
+            "min takes 2 arguments but was given 1"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use super::AuxiliaryDataType;
+
+// Stepping smoothing filters more often than this only adds rounding error
+const MIN_FILTER_STEP: Duration = Duration::from_millis(1);
+
+/// Range and smoothing applied to every update an auxiliary receives.
+pub enum AuxiliaryConstraint {
+    F32(F32Filter),
+    I32 { min_value: i32, max_value: i32 },
+    U32 { min_value: u32, max_value: u32 },
+    // Names of each option, values are the index of the selected option
+    Enum { options: Vec<String> },
+}
+
+pub struct F32Filter {
+    min_value: f32,
+    max_value: f32,
+    // Maximum change in units per second
+    slew_rate: Option<f32>,
+    // Time constant in seconds of an exponential moving average
+    smoothing: Option<f32>,
+    target: f32,
+    last_step: Instant,
+}
+
+impl AuxiliaryConstraint {
+    /// Constrains a newly received value.
+    /// Returns `None` if the value should not be applied directly, smoothed values are applied by `step`.
+    pub fn apply(&mut self, data: AuxiliaryDataType) -> Option<AuxiliaryDataType> {
+        match (self, data) {
+            (AuxiliaryConstraint::F32(filter), AuxiliaryDataType::F32(value)) => {
+                filter.target = filter.clamp(value);
+                if filter.is_smoothed() {
+                    None
+                } else {
+                    Some(AuxiliaryDataType::F32(filter.target))
+                }
+            }
+            (
+                AuxiliaryConstraint::I32 {
+                    min_value,
+                    max_value,
+                },
+                AuxiliaryDataType::I32(value),
+            ) => Some(AuxiliaryDataType::I32(value.clamp(*min_value, *max_value))),
+            (
+                AuxiliaryConstraint::U32 {
+                    min_value,
+                    max_value,
+                },
+                AuxiliaryDataType::U32(value),
+            ) => Some(AuxiliaryDataType::U32(value.clamp(*min_value, *max_value))),
+            (AuxiliaryConstraint::Enum { options }, AuxiliaryDataType::Enum(index)) => Some(
+                AuxiliaryDataType::Enum(index.min(options.len().saturating_sub(1) as u32)),
+            ),
+            (_, data) => Some(data),
+        }
+    }
+
+    /// Moves a smoothed value towards its target, returns `None` if there is nothing to update.
+    pub fn step(&mut self, current: &AuxiliaryDataType, now: Instant) -> Option<AuxiliaryDataType> {
+        match (self, current) {
+            (AuxiliaryConstraint::F32(filter), AuxiliaryDataType::F32(value))
+                if filter.is_smoothed() =>
+            {
+                filter.step(*value, now).map(AuxiliaryDataType::F32)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl F32Filter {
+    pub fn new(
+        initial_value: f32,
+        min_value: f32,
+        max_value: f32,
+        slew_rate: Option<f32>,
+        smoothing: Option<f32>,
+    ) -> Self {
+        Self {
+            min_value,
+            max_value,
+            slew_rate: slew_rate.filter(|rate| *rate > 0.0),
+            smoothing: smoothing.filter(|time_constant| *time_constant > 0.0),
+            target: initial_value.clamp(min_value, max_value),
+            last_step: Instant::now(),
+        }
+    }
+
+    pub fn clamp(&self, value: f32) -> f32 {
+        value.clamp(self.min_value, self.max_value)
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    fn is_smoothed(&self) -> bool {
+        self.slew_rate.is_some() || self.smoothing.is_some()
+    }
+
+    fn step(&mut self, current: f32, now: Instant) -> Option<f32> {
+        let elapsed = now.duration_since(self.last_step);
+        if elapsed < MIN_FILTER_STEP {
+            return None;
+        }
+
+        if current == self.target {
+            self.last_step = now;
+            return None;
+        }
+
+        let dt = elapsed.as_secs_f32();
+        let mut next = match self.smoothing {
+            Some(time_constant) => {
+                current + (self.target - current) * (1.0 - (-dt / time_constant).exp())
+            }
+            None => self.target,
+        };
+
+        if let Some(slew_rate) = self.slew_rate {
+            let max_change = slew_rate * dt;
+            next = current + (next - current).clamp(-max_change, max_change);
+        }
+
+        // If the change is lost to rounding, let time accumulate until the next step
+        if next == current {
+            return None;
+        }
+        self.last_step = now;
+
+        Some(next)
+    }
+}
@@ -1,32 +1,103 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    time::Instant,
+};
 
 use log::{debug, error, warn};
 use parking_lot::RwLock;
-use tokio::sync::broadcast::{channel, Receiver};
+use tokio::sync::broadcast::{channel, Receiver, Sender};
 
 use crate::devices::auxiliary_data::AuxDeviceData;
+use crate::frame::FrameData;
 use crate::state::WantsDeviceState;
 use crate::{
     id::{AnimationId, AuxiliaryId, DeviceId},
     state::ThreadedObject,
 };
 
-use super::{AuxiliaryData, AuxiliaryDataType, AuxiliaryDataTypeConsumer};
+use super::expression::{Expression, ExpressionError};
+use super::filter::{AuxiliaryConstraint, F32Filter};
+use super::recording::AuxiliaryRecorder;
+use super::{
+    aux_data_is_compatible, aux_data_to_consumer_type, AuxColour, AuxiliaryData, AuxiliaryDataType,
+    AuxiliaryDataTypeConsumer,
+};
+
+// Number of updates a device can send before the manager's next tick without being lagged
+const AUXILIARY_DEVICE_BUFFER_SIZE: usize = 64;
+// Number of applied updates a device reacting to auxiliaries can fall behind by
+const AUXILIARY_UPDATE_BUFFER_SIZE: usize = 256;
 
 #[derive(Debug)]
 pub enum AddAuxiliaryError {
     AuxiliaryExists(AuxiliaryId),
+    InvalidRange(AuxiliaryId, String),
+    InvalidExpression(AuxiliaryId, ExpressionError),
+}
+
+#[derive(Debug)]
+pub enum AddModulationError {
+    AuxiliaryDoesNotExist(AuxiliaryId),
+    // Only F32 auxiliaries can be modulated
+    DestinationNotF32(AuxiliaryId),
+}
+
+impl Display for AddAuxiliaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddAuxiliaryError::AuxiliaryExists(aux_id) => {
+                write!(f, "Auxiliary {} already exists", aux_id)
+            }
+            AddAuxiliaryError::InvalidRange(aux_id, range) => {
+                write!(f, "Auxiliary {} has an invalid range ({})", aux_id, range)
+            }
+            AddAuxiliaryError::InvalidExpression(aux_id, err) => {
+                write!(f, "Invalid expression for auxiliary {}: {}", aux_id, err)
+            }
+        }
+    }
+}
+
+impl Display for AddModulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddModulationError::AuxiliaryDoesNotExist(aux_id) => {
+                write!(f, "Auxiliary {} does not exist", aux_id)
+            }
+            AddModulationError::DestinationNotF32(aux_id) => {
+                write!(f, "Auxiliary {} is not an F32 auxiliary", aux_id)
+            }
+        }
+    }
 }
 
 struct AuxiliaryMetadata {
     name: String,
 }
 
+/// An auxiliary whose value is offset by other auxiliaries.
+struct ModulatedAuxiliary {
+    // Value set by devices or an expression, before modulation
+    base_value: f32,
+    // Source -> Depth
+    sources: Vec<(AuxiliaryId, f32)>,
+}
+
 pub struct AuxiliaryDataManager {
     animation_auxiliary_sources: RwLock<HashMap<AnimationId, Vec<AuxiliaryId>>>,
     auxiliary_data_buffers: RwLock<HashMap<DeviceId, Receiver<AuxDeviceData>>>,
     auxiliary_data: RwLock<HashMap<AuxiliaryId, AuxiliaryData>>,
     auxiliary_metadata: RwLock<HashMap<AuxiliaryId, AuxiliaryMetadata>>,
+    auxiliary_constraints: RwLock<HashMap<AuxiliaryId, AuxiliaryConstraint>>,
+    auxiliary_updates: Sender<AuxDeviceData>,
+    // Evaluated in the order they were added
+    derived_auxiliaries: RwLock<Vec<(AuxiliaryId, Expression)>>,
+    modulated_auxiliaries: RwLock<HashMap<AuxiliaryId, ModulatedAuxiliary>>,
+    next_frame_data_buffer: Option<Receiver<FrameData>>,
+    frame: u32,
+    seconds_elapsed: f32,
+    recorder: Option<AuxiliaryRecorder>,
 }
 
 impl AuxiliaryDataManager {
@@ -36,6 +107,14 @@ impl AuxiliaryDataManager {
             auxiliary_data_buffers: RwLock::new(HashMap::new()),
             auxiliary_data: RwLock::new(HashMap::new()),
             auxiliary_metadata: RwLock::new(HashMap::new()),
+            auxiliary_constraints: RwLock::new(HashMap::new()),
+            auxiliary_updates: channel(AUXILIARY_UPDATE_BUFFER_SIZE).0,
+            derived_auxiliaries: RwLock::new(vec![]),
+            modulated_auxiliaries: RwLock::new(HashMap::new()),
+            next_frame_data_buffer: None,
+            frame: 0,
+            seconds_elapsed: 0.0,
+            recorder: None,
         }
     }
 
@@ -52,21 +131,102 @@ impl AuxiliaryDataManager {
         }
 
         let mut default_value = aux_type.default_aux_value();
+        let mut constraint: Option<AuxiliaryConstraint> = None;
+        let mut derived: Option<Expression> = None;
         match params {
             AuxiliaryConfigParams::Empty => {}
             AuxiliaryConfigParams::F32 {
                 initial_value,
                 min_value,
                 max_value,
+                slew_rate,
+                smoothing,
+                expression,
             } => {
-                if let AuxiliaryDataType::F32(val) = &mut default_value {
-                    *val = initial_value
+                if min_value > max_value || min_value.is_nan() || max_value.is_nan() {
+                    return Err(AddAuxiliaryError::InvalidRange(
+                        aux_id,
+                        format!("min_value {} to max_value {}", min_value, max_value),
+                    ));
+                }
+                if let Some(expression) = expression {
+                    match Expression::parse(&expression) {
+                        Ok(expression) => derived = Some(expression),
+                        Err(err) => return Err(AddAuxiliaryError::InvalidExpression(aux_id, err)),
+                    }
                 }
+                let filter =
+                    F32Filter::new(initial_value, min_value, max_value, slew_rate, smoothing);
+                default_value = AuxiliaryDataType::F32(filter.target());
+                constraint = Some(AuxiliaryConstraint::F32(filter));
             }
             AuxiliaryConfigParams::F32Vec => {}
             AuxiliaryConfigParams::F32Vec2 => {}
             AuxiliaryConfigParams::F32Vec3 => {}
             AuxiliaryConfigParams::F32Vec4 => {}
+            AuxiliaryConfigParams::Colour { initial_value } => {
+                default_value = AuxiliaryDataType::Colour(initial_value)
+            }
+            AuxiliaryConfigParams::I32 {
+                initial_value,
+                min_value,
+                max_value,
+            } => {
+                if min_value > max_value {
+                    return Err(AddAuxiliaryError::InvalidRange(
+                        aux_id,
+                        format!("min_value {} to max_value {}", min_value, max_value),
+                    ));
+                }
+                default_value = AuxiliaryDataType::I32(initial_value.clamp(min_value, max_value));
+                constraint = Some(AuxiliaryConstraint::I32 {
+                    min_value,
+                    max_value,
+                });
+            }
+            AuxiliaryConfigParams::U32 {
+                initial_value,
+                min_value,
+                max_value,
+            } => {
+                if min_value > max_value {
+                    return Err(AddAuxiliaryError::InvalidRange(
+                        aux_id,
+                        format!("min_value {} to max_value {}", min_value, max_value),
+                    ));
+                }
+                default_value = AuxiliaryDataType::U32(initial_value.clamp(min_value, max_value));
+                constraint = Some(AuxiliaryConstraint::U32 {
+                    min_value,
+                    max_value,
+                });
+            }
+            AuxiliaryConfigParams::Bool { initial_value } => {
+                default_value = AuxiliaryDataType::Bool(initial_value)
+            }
+            AuxiliaryConfigParams::Vec2 { initial_value } => {
+                default_value = AuxiliaryDataType::Vec2(initial_value)
+            }
+            AuxiliaryConfigParams::Vec3 { initial_value } => {
+                default_value = AuxiliaryDataType::Vec3(initial_value)
+            }
+            AuxiliaryConfigParams::Vec4 { initial_value } => {
+                default_value = AuxiliaryDataType::Vec4(initial_value)
+            }
+            AuxiliaryConfigParams::Enum {
+                initial_value,
+                options,
+            } => {
+                if options.is_empty() {
+                    return Err(AddAuxiliaryError::InvalidRange(
+                        aux_id,
+                        "no options".to_string(),
+                    ));
+                }
+                let last_option = options.len() as u32 - 1;
+                default_value = AuxiliaryDataType::Enum(initial_value.min(last_option));
+                constraint = Some(AuxiliaryConstraint::Enum { options });
+            }
         }
         let size = default_value.get_number_of_values();
 
@@ -77,6 +237,16 @@ impl AuxiliaryDataManager {
                 size,
             },
         );
+        if let Some(constraint) = constraint {
+            self.auxiliary_constraints
+                .write()
+                .insert(aux_id.clone(), constraint);
+        }
+        if let Some(expression) = derived {
+            self.derived_auxiliaries
+                .write()
+                .push((aux_id.clone(), expression));
+        }
         self.auxiliary_metadata
             .write()
             .insert(aux_id, AuxiliaryMetadata { name: aux_name });
@@ -92,6 +262,18 @@ impl AuxiliaryDataManager {
         self.auxiliary_data.read().clone()
     }
 
+    pub fn get_auxiliary_value(&self, aux_id: &AuxiliaryId) -> Option<AuxiliaryDataType> {
+        self.auxiliary_data
+            .read()
+            .get(aux_id)
+            .map(|auxiliary| auxiliary.data.clone())
+    }
+
+    /// Sets an auxiliary's value as if it had been sent by a device, e.g. from a cue.
+    pub fn set_auxiliary(&self, aux_id: AuxiliaryId, data: AuxiliaryDataType) {
+        self.update_auxiliary(AuxDeviceData { aux_id, data });
+    }
+
     pub fn get_animation_auxiliary_ids(&self) -> HashMap<AnimationId, Vec<AuxiliaryId>> {
         self.animation_auxiliary_sources.read().clone()
     }
@@ -108,6 +290,186 @@ impl AuxiliaryDataManager {
             .insert(animation_id, sources);
     }
 
+    /// Adds `depth` times the value of `source` to `destination`, on top of the value it's given by devices.
+    pub fn add_modulation(
+        &self,
+        source: AuxiliaryId,
+        destination: AuxiliaryId,
+        depth: f32,
+    ) -> Result<(), AddModulationError> {
+        let auxiliaries = self.auxiliary_data.read();
+        if !auxiliaries.contains_key(&source) {
+            return Err(AddModulationError::AuxiliaryDoesNotExist(source));
+        }
+        let base_value = match auxiliaries.get(&destination) {
+            Some(AuxiliaryData {
+                data: AuxiliaryDataType::F32(value),
+                ..
+            }) => *value,
+            Some(_) => return Err(AddModulationError::DestinationNotF32(destination)),
+            None => return Err(AddModulationError::AuxiliaryDoesNotExist(destination)),
+        };
+
+        self.modulated_auxiliaries
+            .write()
+            .entry(destination)
+            .or_insert(ModulatedAuxiliary {
+                base_value,
+                sources: vec![],
+            })
+            .sources
+            .push((source, depth));
+
+        Ok(())
+    }
+
+    pub fn receive_next_frame_data_buffer(&mut self, buffer: Receiver<FrameData>) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    /// Records every update received from devices, for replaying with a playback device.
+    pub fn record_to(&mut self, recorder: AuxiliaryRecorder) {
+        self.recorder.replace(recorder);
+    }
+
+    fn update_auxiliary(&self, data: AuxDeviceData) {
+        let aux_type = match self.auxiliary_data.read().get(&data.aux_id) {
+            Some(auxiliary) => aux_data_to_consumer_type(&auxiliary.data),
+            None => {
+                debug!(
+                    "Recieved data for auxiliary {} which doesn't exist",
+                    data.aux_id
+                );
+                return;
+            }
+        };
+
+        if !aux_data_is_compatible(&data.data, &aux_type) {
+            warn!(
+                "Received {} data for auxiliary {} which has type {}",
+                aux_data_to_consumer_type(&data.data),
+                data.aux_id,
+                aux_type
+            );
+            return;
+        }
+
+        // Modulated values are applied once per frame by `apply_modulations`
+        if let AuxiliaryDataType::F32(value) = data.data {
+            if let Some(modulated) = self.modulated_auxiliaries.write().get_mut(&data.aux_id) {
+                modulated.base_value = value;
+                return;
+            }
+        }
+
+        self.set_auxiliary_value(data.aux_id, data.data);
+    }
+
+    fn set_auxiliary_value(&self, aux_id: AuxiliaryId, data: AuxiliaryDataType) {
+        // Lists can change size between updates, the pipeline grows the auxiliary's buffer to fit
+        let size = data.get_number_of_values();
+        let value = match self.auxiliary_constraints.write().get_mut(&aux_id) {
+            Some(constraint) => match constraint.apply(data) {
+                Some(value) => value,
+                None => return, // Applied by the smoothing filter
+            },
+            None => data,
+        };
+        self.publish_update(&aux_id, &value);
+        self.auxiliary_data
+            .write()
+            .insert(aux_id, AuxiliaryData { data: value, size });
+    }
+
+    fn auxiliary_value(&self, aux_id: &AuxiliaryId) -> Option<f32> {
+        self.auxiliary_data.read().get(aux_id)?.data.as_f32()
+    }
+
+    /// Evaluates derived auxiliaries, those that use later ones see the previous frame's value.
+    fn evaluate_derived_auxiliaries(&self) {
+        let derived_auxiliaries = self.derived_auxiliaries.read().clone();
+
+        for (aux_id, expression) in derived_auxiliaries {
+            match expression.evaluate(self.seconds_elapsed, &|aux_id| self.auxiliary_value(aux_id)) {
+                Some(value) => self.update_auxiliary(AuxDeviceData {
+                    aux_id,
+                    data: AuxiliaryDataType::F32(value),
+                }),
+                None => debug!(
+                    "Could not evaluate derived auxiliary {}, an auxiliary it uses doesn't exist or isn't a single value",
+                    aux_id
+                ),
+            }
+        }
+    }
+
+    fn apply_modulations(&self) {
+        let modulated_values: Vec<(AuxiliaryId, f32)> = self
+            .modulated_auxiliaries
+            .read()
+            .iter()
+            .map(|(aux_id, modulated)| {
+                let modulation: f32 = modulated
+                    .sources
+                    .iter()
+                    .filter_map(|(source, depth)| Some(self.auxiliary_value(source)? * depth))
+                    .sum();
+                (aux_id.clone(), modulated.base_value + modulation)
+            })
+            .collect();
+
+        for (aux_id, value) in modulated_values {
+            self.set_auxiliary_value(aux_id, AuxiliaryDataType::F32(value));
+        }
+    }
+
+    /// Returns true if there is a new frame to compute derived values for.
+    fn receive_next_frame_data(&mut self) -> bool {
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return false,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                self.frame = next_frame_data.frame;
+                self.seconds_elapsed = next_frame_data.seconds_elapsed;
+                true
+            }
+            Err(tokio::sync::broadcast::error::TryRecvError::Empty) => false,
+            Err(tokio::sync::broadcast::error::TryRecvError::Closed) => {
+                error!("Auxiliary manager had next frame data buffer closed");
+                self.next_frame_data_buffer.take();
+                false
+            }
+            Err(tokio::sync::broadcast::error::TryRecvError::Lagged(_)) => false,
+        }
+    }
+
+    fn publish_update(&self, aux_id: &AuxiliaryId, data: &AuxiliaryDataType) {
+        // Nothing is listening if no device reacts to auxiliaries
+        self.auxiliary_updates
+            .send(AuxDeviceData {
+                aux_id: aux_id.clone(),
+                data: data.clone(),
+            })
+            .ok();
+    }
+
+    fn step_auxiliary_filters(&self) {
+        let now = Instant::now();
+        let mut auxiliaries = self.auxiliary_data.write();
+
+        for (aux_id, constraint) in self.auxiliary_constraints.write().iter_mut() {
+            if let Some(auxiliary) = auxiliaries.get_mut(aux_id) {
+                if let Some(value) = constraint.step(&auxiliary.data, now) {
+                    self.publish_update(aux_id, &value);
+                    auxiliary.data = value;
+                }
+            }
+        }
+    }
+
     fn read_aux_data_from(&mut self, device_id: DeviceId, receiver: Receiver<AuxDeviceData>) {
         self.auxiliary_data_buffers
             .write()
@@ -117,42 +479,53 @@ impl AuxiliaryDataManager {
 
 impl ThreadedObject for AuxiliaryDataManager {
     fn tick(&mut self) {
-        for (aux_id, data_buffer) in self.auxiliary_data_buffers.write().iter_mut() {
-            match data_buffer.try_recv() {
-                Ok(data) => {
-                    debug!("Received aux data from {}", aux_id);
-                    // TODO: If size has changed, we need to recreate the auxiliary
-                    let size = data.data.get_number_of_values();
-                    if !self.auxiliary_data.read().contains_key(&data.aux_id) {
-                        debug!("Recieved data for auxiliary {} which doesn't exist", aux_id);
-                        continue;
+        for (device_id, data_buffer) in self.auxiliary_data_buffers.write().iter_mut() {
+            // Devices such as OSC may send several values between ticks
+            loop {
+                match data_buffer.try_recv() {
+                    Ok(data) => {
+                        debug!("Received aux data from {}", device_id);
+                        if let Some(recorder) = self.recorder.as_mut() {
+                            if let Err(err) = recorder.record(self.frame, &data) {
+                                error!("Stopped recording auxiliaries ({})", err);
+                                self.recorder.take();
+                            }
+                        }
+                        self.update_auxiliary(data);
                     }
-                    self.auxiliary_data.write().insert(
-                        data.aux_id.clone(),
-                        AuxiliaryData {
-                            data: data.data,
-                            size,
-                        },
-                    );
+                    Err(err) => match err {
+                        tokio::sync::broadcast::error::TryRecvError::Empty => break,
+                        tokio::sync::broadcast::error::TryRecvError::Closed => {
+                            error!(
+                                "Data channel for auxiliary {:?} has been closed",
+                                device_id.clone()
+                            );
+                            break;
+                        }
+                        tokio::sync::broadcast::error::TryRecvError::Lagged(messages) => {
+                            warn!(
+                                "Lagged behind auxiliary device {:?} by {} frames",
+                                device_id.clone(),
+                                messages
+                            )
+                        }
+                    },
+                }
+            }
+        }
+
+        if self.receive_next_frame_data() {
+            if let Some(recorder) = self.recorder.as_mut() {
+                if let Err(err) = recorder.finish_frames_before(self.frame) {
+                    error!("Stopped recording auxiliaries ({})", err);
+                    self.recorder.take();
                 }
-                Err(err) => match err {
-                    tokio::sync::broadcast::error::TryRecvError::Empty => {}
-                    tokio::sync::broadcast::error::TryRecvError::Closed => {
-                        error!(
-                            "Data channel for auxiliary {:?} has been closed",
-                            aux_id.clone()
-                        )
-                    }
-                    tokio::sync::broadcast::error::TryRecvError::Lagged(messages) => {
-                        warn!(
-                            "Lagged behind auxiliary device {:?} by {} frames",
-                            aux_id.clone(),
-                            messages
-                        )
-                    }
-                },
             }
+            self.evaluate_derived_auxiliaries();
+            self.apply_modulations();
         }
+
+        self.step_auxiliary_filters();
     }
 }
 
@@ -162,8 +535,9 @@ impl WantsDeviceState for AuxiliaryDataManager {
             match &mut *device.write() {
                 crate::devices::manager::ThreadedDeviceType::LEDDataOutput(_) => {} // Nothing to do
                 crate::devices::manager::ThreadedDeviceType::AuxiliaryData(aux_device) => {
-                    let (sender, receiver) = channel(1);
+                    let (sender, receiver) = channel(AUXILIARY_DEVICE_BUFFER_SIZE);
                     aux_device.send_into_buffer(sender);
+                    aux_device.receive_auxiliary_updates(self.auxiliary_updates.subscribe());
                     self.read_aux_data_from(device_id, receiver);
                 }
             }
@@ -177,22 +551,53 @@ pub enum AuxiliaryConfigParams {
         initial_value: f32,
         min_value: f32,
         max_value: f32,
+        // Units per second
+        slew_rate: Option<f32>,
+        // Seconds
+        smoothing: Option<f32>,
+        // Computes the value from other auxiliaries every frame
+        expression: Option<String>,
     },
     F32Vec,
     F32Vec2,
     F32Vec3,
     F32Vec4,
+    Colour {
+        initial_value: AuxColour,
+    },
+    I32 {
+        initial_value: i32,
+        min_value: i32,
+        max_value: i32,
+    },
+    U32 {
+        initial_value: u32,
+        min_value: u32,
+        max_value: u32,
+    },
+    Bool {
+        initial_value: bool,
+    },
+    Vec2 {
+        initial_value: [f32; 2],
+    },
+    Vec3 {
+        initial_value: [f32; 3],
+    },
+    Vec4 {
+        initial_value: [f32; 4],
+    },
+    Enum {
+        initial_value: u32,
+        options: Vec<String>,
+    },
 }
 
 impl AuxiliaryConfigParams {
     fn is_compatible(&self, aux_type: AuxiliaryDataTypeConsumer) -> bool {
         match self {
             AuxiliaryConfigParams::Empty => matches!(aux_type, AuxiliaryDataTypeConsumer::Empty),
-            AuxiliaryConfigParams::F32 {
-                initial_value,
-                min_value,
-                max_value,
-            } => matches!(aux_type, AuxiliaryDataTypeConsumer::F32),
+            AuxiliaryConfigParams::F32 { .. } => matches!(aux_type, AuxiliaryDataTypeConsumer::F32),
             AuxiliaryConfigParams::F32Vec => {
                 matches!(aux_type, AuxiliaryDataTypeConsumer::F32Vec)
             }
@@ -205,6 +610,26 @@ impl AuxiliaryConfigParams {
             AuxiliaryConfigParams::F32Vec4 => {
                 matches!(aux_type, AuxiliaryDataTypeConsumer::F32Vec4)
             }
+            AuxiliaryConfigParams::Colour { .. } => {
+                matches!(aux_type, AuxiliaryDataTypeConsumer::Colour)
+            }
+            AuxiliaryConfigParams::I32 { .. } => matches!(aux_type, AuxiliaryDataTypeConsumer::I32),
+            AuxiliaryConfigParams::U32 { .. } => matches!(aux_type, AuxiliaryDataTypeConsumer::U32),
+            AuxiliaryConfigParams::Bool { .. } => {
+                matches!(aux_type, AuxiliaryDataTypeConsumer::Bool)
+            }
+            AuxiliaryConfigParams::Vec2 { .. } => {
+                matches!(aux_type, AuxiliaryDataTypeConsumer::Vec2)
+            }
+            AuxiliaryConfigParams::Vec3 { .. } => {
+                matches!(aux_type, AuxiliaryDataTypeConsumer::Vec3)
+            }
+            AuxiliaryConfigParams::Vec4 { .. } => {
+                matches!(aux_type, AuxiliaryDataTypeConsumer::Vec4)
+            }
+            AuxiliaryConfigParams::Enum { .. } => {
+                matches!(aux_type, AuxiliaryDataTypeConsumer::Enum)
+            }
         }
     }
 }
@@ -1,12 +1,15 @@
+pub mod expression;
+pub mod filter;
 pub mod manager;
+pub mod recording;
 
-use serde::Deserialize;
-use std::convert::TryFrom;
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
 use std::fmt::Display;
 use std::mem;
 use strum_macros::EnumIter;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum AuxiliaryDataType {
     Empty,
     F32(f32),
@@ -14,6 +17,45 @@ pub enum AuxiliaryDataType {
     F32Vec2(AuxDataF32Vec2),
     F32Vec3(AuxDataF32Vec3),
     F32Vec4(AuxDataF32Vec4),
+    Colour(AuxColour),
+    I32(i32),
+    U32(u32),
+    Bool(bool),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    // Index of the selected option
+    Enum(u32),
+}
+
+/// A colour given in either colour space, shaders always receive it as RGB.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum AuxColour {
+    Rgb([f32; 3]),
+    Hsv([f32; 3]),
+}
+
+impl AuxColour {
+    pub fn to_rgb(&self) -> [f32; 3] {
+        match self {
+            AuxColour::Rgb(rgb) => *rgb,
+            AuxColour::Hsv([h, s, v]) => {
+                let h = (h.fract() + 1.0).fract() * 6.0;
+                let chroma = v * s;
+                let x = chroma * (1.0 - ((h % 2.0) - 1.0).abs());
+                let m = v - chroma;
+                let (r, g, b) = match h as u32 {
+                    0 => (chroma, x, 0.0),
+                    1 => (x, chroma, 0.0),
+                    2 => (0.0, chroma, x),
+                    3 => (0.0, x, chroma),
+                    4 => (x, 0.0, chroma),
+                    _ => (chroma, 0.0, x),
+                };
+                [r + m, g + m, b + m]
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -21,7 +63,7 @@ pub enum AuxDataError {
     IncorrectDimensions,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuxDataF32Vec {
     data: Vec<f32>,
     size_dimension_1: u32,
@@ -45,7 +87,7 @@ impl TryFrom<(Vec<f32>, usize)> for AuxDataF32Vec {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuxDataF32Vec2 {
     data: Vec<f32>,
     size_dimension_1: u32,
@@ -73,7 +115,7 @@ impl TryFrom<(Vec<f32>, usize, usize)> for AuxDataF32Vec2 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuxDataF32Vec3 {
     data: Vec<f32>,
     size_dimension_1: u32,
@@ -104,7 +146,7 @@ impl TryFrom<(Vec<f32>, usize, usize, usize)> for AuxDataF32Vec3 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuxDataF32Vec4 {
     data: Vec<f32>,
     size_dimension_1: u32,
@@ -144,7 +186,7 @@ pub struct AuxiliaryData {
     pub size: u32,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Hash, EnumIter)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash, EnumIter)]
 pub enum AuxiliaryDataTypeConsumer {
     Empty,
     F32,
@@ -152,6 +194,14 @@ pub enum AuxiliaryDataTypeConsumer {
     F32Vec2,
     F32Vec3,
     F32Vec4,
+    Colour,
+    I32,
+    U32,
+    Bool,
+    Vec2,
+    Vec3,
+    Vec4,
+    Enum,
 }
 
 impl AuxiliaryDataTypeConsumer {
@@ -181,6 +231,16 @@ impl AuxiliaryDataTypeConsumer {
                 size_dimension_3: 0,
                 size_dimension_4: 0,
             }),
+            AuxiliaryDataTypeConsumer::Colour => {
+                AuxiliaryDataType::Colour(AuxColour::Rgb([0.0; 3]))
+            }
+            AuxiliaryDataTypeConsumer::I32 => AuxiliaryDataType::I32(0),
+            AuxiliaryDataTypeConsumer::U32 => AuxiliaryDataType::U32(0),
+            AuxiliaryDataTypeConsumer::Bool => AuxiliaryDataType::Bool(false),
+            AuxiliaryDataTypeConsumer::Vec2 => AuxiliaryDataType::Vec2([0.0; 2]),
+            AuxiliaryDataTypeConsumer::Vec3 => AuxiliaryDataType::Vec3([0.0; 3]),
+            AuxiliaryDataTypeConsumer::Vec4 => AuxiliaryDataType::Vec4([0.0; 4]),
+            AuxiliaryDataTypeConsumer::Enum => AuxiliaryDataType::Enum(0),
         }
     }
 }
@@ -224,6 +284,21 @@ impl AuxiliaryDataType {
                 bytemuck::cast_slice(&val.data).to_vec(),
             ]
             .concat(),
+            // vec3<f32> has an alignment of 16 bytes, so is padded with a fourth value
+            AuxiliaryDataType::Colour(val) => {
+                let [r, g, b] = val.to_rgb();
+                bytemuck::cast_slice(&[r, g, b, 0.0]).to_vec()
+            }
+            AuxiliaryDataType::I32(val) => val.to_le_bytes().to_vec(),
+            AuxiliaryDataType::U32(val) => val.to_le_bytes().to_vec(),
+            // WGSL booleans cannot be stored in buffers, so are passed as a u32
+            AuxiliaryDataType::Bool(val) => u32::from(*val).to_le_bytes().to_vec(),
+            AuxiliaryDataType::Vec2(val) => bytemuck::cast_slice(val).to_vec(),
+            AuxiliaryDataType::Vec3(val) => {
+                bytemuck::cast_slice(&[val[0], val[1], val[2], 0.0]).to_vec()
+            }
+            AuxiliaryDataType::Vec4(val) => bytemuck::cast_slice(val).to_vec(),
+            AuxiliaryDataType::Enum(val) => val.to_le_bytes().to_vec(),
         }
     }
 
@@ -246,6 +321,26 @@ impl AuxiliaryDataType {
                     .max(1)
                     + 4
             }
+            AuxiliaryDataType::Colour(_) => 4,
+            AuxiliaryDataType::I32(_) => 1,
+            AuxiliaryDataType::U32(_) => 1,
+            AuxiliaryDataType::Bool(_) => 1,
+            AuxiliaryDataType::Vec2(_) => 2,
+            AuxiliaryDataType::Vec3(_) => 4,
+            AuxiliaryDataType::Vec4(_) => 4,
+            AuxiliaryDataType::Enum(_) => 1,
+        }
+    }
+
+    /// The value of a single number auxiliary, `None` for lists, vectors and colours.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            AuxiliaryDataType::F32(val) => Some(*val),
+            AuxiliaryDataType::I32(val) => Some(*val as f32),
+            AuxiliaryDataType::U32(val) => Some(*val as f32),
+            AuxiliaryDataType::Bool(val) => Some(f32::from(u8::from(*val))),
+            AuxiliaryDataType::Enum(val) => Some(*val as f32),
+            _ => None,
         }
     }
 }
@@ -259,6 +354,14 @@ impl AuxiliaryDataTypeConsumer {
             AuxiliaryDataTypeConsumer::F32Vec2 => mem::size_of::<f32>() as u64,
             AuxiliaryDataTypeConsumer::F32Vec3 => mem::size_of::<f32>() as u64,
             AuxiliaryDataTypeConsumer::F32Vec4 => mem::size_of::<f32>() as u64,
+            AuxiliaryDataTypeConsumer::Colour => mem::size_of::<f32>() as u64,
+            AuxiliaryDataTypeConsumer::I32 => mem::size_of::<i32>() as u64,
+            AuxiliaryDataTypeConsumer::U32 => mem::size_of::<u32>() as u64,
+            AuxiliaryDataTypeConsumer::Bool => mem::size_of::<u32>() as u64,
+            AuxiliaryDataTypeConsumer::Vec2 => mem::size_of::<f32>() as u64,
+            AuxiliaryDataTypeConsumer::Vec3 => mem::size_of::<f32>() as u64,
+            AuxiliaryDataTypeConsumer::Vec4 => mem::size_of::<f32>() as u64,
+            AuxiliaryDataTypeConsumer::Enum => mem::size_of::<u32>() as u64,
         }
     }
 
@@ -304,6 +407,61 @@ impl AuxiliaryDataTypeConsumer {
                 ]
                 .concat()
             }
+            AuxiliaryDataTypeConsumer::Colour
+            | AuxiliaryDataTypeConsumer::I32
+            | AuxiliaryDataTypeConsumer::U32
+            | AuxiliaryDataTypeConsumer::Bool
+            | AuxiliaryDataTypeConsumer::Vec2
+            | AuxiliaryDataTypeConsumer::Vec3
+            | AuxiliaryDataTypeConsumer::Vec4
+            | AuxiliaryDataTypeConsumer::Enum => self.default_aux_value().to_data_buffer(), // Default value: 0
+        }
+    }
+
+    /// Builds a value of this type from a flat list of numbers.
+    /// Lists are treated as a single row, higher dimensions are left at 1.
+    pub fn value_from_f32s(&self, values: Vec<f32>) -> Option<AuxiliaryDataType> {
+        let len = values.len();
+
+        match self {
+            AuxiliaryDataTypeConsumer::Empty => None,
+            AuxiliaryDataTypeConsumer::F32 => {
+                values.first().map(|value| AuxiliaryDataType::F32(*value))
+            }
+            AuxiliaryDataTypeConsumer::F32Vec => {
+                (values, len).try_into().ok().map(AuxiliaryDataType::F32Vec)
+            }
+            AuxiliaryDataTypeConsumer::F32Vec2 => (values, len, 1)
+                .try_into()
+                .ok()
+                .map(AuxiliaryDataType::F32Vec2),
+            AuxiliaryDataTypeConsumer::F32Vec3 => (values, len, 1, 1)
+                .try_into()
+                .ok()
+                .map(AuxiliaryDataType::F32Vec3),
+            AuxiliaryDataTypeConsumer::F32Vec4 => (values, len, 1, 1, 1)
+                .try_into()
+                .ok()
+                .map(AuxiliaryDataType::F32Vec4),
+            AuxiliaryDataTypeConsumer::Colour => {
+                let rgb: [f32; 3] = values.try_into().ok()?;
+                Some(AuxiliaryDataType::Colour(AuxColour::Rgb(rgb)))
+            }
+            AuxiliaryDataTypeConsumer::I32 => values
+                .first()
+                .map(|value| AuxiliaryDataType::I32(value.round() as i32)),
+            AuxiliaryDataTypeConsumer::U32 => values
+                .first()
+                .map(|value| AuxiliaryDataType::U32(value.round().max(0.0) as u32)),
+            AuxiliaryDataTypeConsumer::Bool => values
+                .first()
+                .map(|value| AuxiliaryDataType::Bool(*value != 0.0)),
+            AuxiliaryDataTypeConsumer::Vec2 => values.try_into().ok().map(AuxiliaryDataType::Vec2),
+            AuxiliaryDataTypeConsumer::Vec3 => values.try_into().ok().map(AuxiliaryDataType::Vec3),
+            AuxiliaryDataTypeConsumer::Vec4 => values.try_into().ok().map(AuxiliaryDataType::Vec4),
+            AuxiliaryDataTypeConsumer::Enum => values
+                .first()
+                .map(|value| AuxiliaryDataType::Enum(value.round().max(0.0) as u32)),
         }
     }
 }
@@ -317,16 +475,54 @@ impl Display for AuxiliaryDataTypeConsumer {
             AuxiliaryDataTypeConsumer::F32Vec2 => write!(f, "F32Vec2"),
             AuxiliaryDataTypeConsumer::F32Vec3 => write!(f, "F32Vec3"),
             AuxiliaryDataTypeConsumer::F32Vec4 => write!(f, "F32Vec4"),
+            AuxiliaryDataTypeConsumer::Colour => write!(f, "Colour"),
+            AuxiliaryDataTypeConsumer::I32 => write!(f, "I32"),
+            AuxiliaryDataTypeConsumer::U32 => write!(f, "U32"),
+            AuxiliaryDataTypeConsumer::Bool => write!(f, "Bool"),
+            AuxiliaryDataTypeConsumer::Vec2 => write!(f, "Vec2"),
+            AuxiliaryDataTypeConsumer::Vec3 => write!(f, "Vec3"),
+            AuxiliaryDataTypeConsumer::Vec4 => write!(f, "Vec4"),
+            AuxiliaryDataTypeConsumer::Enum => write!(f, "Enum"),
         }
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuxiliaryManifest {
     name: String,
     description: String,
     #[serde(rename = "type")]
     aux_type: AuxiliaryDataTypeConsumer,
+    #[serde(flatten)]
+    control: ManifestControl,
+}
+
+/// Describes how a value should be presented and constrained by a control surface.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ManifestControl {
+    pub default: Option<ManifestValue>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub step: Option<f32>,
+    pub units: Option<String>,
+    pub widget: Option<ManifestWidget>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ManifestValue {
+    Bool(bool),
+    Number(f32),
+    List(Vec<f32>),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ManifestWidget {
+    Slider,
+    ColourPicker,
+    Toggle,
+    // Names of each option, the selected value is the index of the option
+    Enum(Vec<String>),
 }
 
 impl AuxiliaryManifest {
@@ -335,6 +531,7 @@ impl AuxiliaryManifest {
             name,
             description,
             aux_type,
+            control: ManifestControl::default(),
         }
     }
 
@@ -343,12 +540,57 @@ impl AuxiliaryManifest {
     }
 
     pub fn get_description(&self) -> String {
-        self.name.clone()
+        self.description.clone()
     }
 
     pub fn get_aux_type(&self) -> AuxiliaryDataTypeConsumer {
         self.aux_type.clone()
     }
+
+    /// The value used when no auxiliary is mapped to this slot.
+    pub fn get_default_value(&self) -> AuxiliaryDataType {
+        self.control
+            .default_value_for(&self.aux_type)
+            .unwrap_or_else(|| self.aux_type.default_aux_value())
+    }
+}
+
+impl ManifestControl {
+    pub fn clamp(&self, value: f32) -> f32 {
+        let value = self.min.map_or(value, |min| value.max(min));
+        self.max.map_or(value, |max| value.min(max))
+    }
+
+    fn default_value_for(&self, aux_type: &AuxiliaryDataTypeConsumer) -> Option<AuxiliaryDataType> {
+        if let (AuxiliaryDataTypeConsumer::Bool, ManifestValue::Bool(value)) =
+            (aux_type, self.default.as_ref()?)
+        {
+            return Some(AuxiliaryDataType::Bool(*value));
+        }
+
+        let values: Vec<f32> = match self.default.as_ref()? {
+            ManifestValue::Bool(value) => vec![f32::from(u8::from(*value))],
+            ManifestValue::Number(value) => vec![self.clamp(*value)],
+            ManifestValue::List(values) => values.iter().map(|value| self.clamp(*value)).collect(),
+        };
+
+        match aux_type.value_from_f32s(values)? {
+            AuxiliaryDataType::Enum(index) => {
+                Some(AuxiliaryDataType::Enum(self.clamp_enum_index(index)))
+            }
+            value => Some(value),
+        }
+    }
+
+    /// Limits an enum index to the options listed by an `Enum` widget, if there is one.
+    pub fn clamp_enum_index(&self, index: u32) -> u32 {
+        match &self.widget {
+            Some(ManifestWidget::Enum(options)) if !options.is_empty() => {
+                index.min(options.len() as u32 - 1)
+            }
+            _ => index,
+        }
+    }
 }
 
 pub fn aux_data_is_compatible(
@@ -362,6 +604,14 @@ pub fn aux_data_is_compatible(
         AuxiliaryDataTypeConsumer::F32Vec2 => matches!(data, AuxiliaryDataType::F32Vec2(_)),
         AuxiliaryDataTypeConsumer::F32Vec3 => matches!(data, AuxiliaryDataType::F32Vec3(_)),
         AuxiliaryDataTypeConsumer::F32Vec4 => matches!(data, AuxiliaryDataType::F32Vec4(_)),
+        AuxiliaryDataTypeConsumer::Colour => matches!(data, AuxiliaryDataType::Colour(_)),
+        AuxiliaryDataTypeConsumer::I32 => matches!(data, AuxiliaryDataType::I32(_)),
+        AuxiliaryDataTypeConsumer::U32 => matches!(data, AuxiliaryDataType::U32(_)),
+        AuxiliaryDataTypeConsumer::Bool => matches!(data, AuxiliaryDataType::Bool(_)),
+        AuxiliaryDataTypeConsumer::Vec2 => matches!(data, AuxiliaryDataType::Vec2(_)),
+        AuxiliaryDataTypeConsumer::Vec3 => matches!(data, AuxiliaryDataType::Vec3(_)),
+        AuxiliaryDataTypeConsumer::Vec4 => matches!(data, AuxiliaryDataType::Vec4(_)),
+        AuxiliaryDataTypeConsumer::Enum => matches!(data, AuxiliaryDataType::Enum(_)),
     }
 }
 
@@ -381,5 +631,13 @@ pub fn aux_data_to_consumer_type(data: &AuxiliaryDataType) -> AuxiliaryDataTypeC
         AuxiliaryDataType::F32Vec2(_) => AuxiliaryDataTypeConsumer::F32Vec2,
         AuxiliaryDataType::F32Vec3(_) => AuxiliaryDataTypeConsumer::F32Vec3,
         AuxiliaryDataType::F32Vec4(_) => AuxiliaryDataTypeConsumer::F32Vec4,
+        AuxiliaryDataType::Colour(_) => AuxiliaryDataTypeConsumer::Colour,
+        AuxiliaryDataType::I32(_) => AuxiliaryDataTypeConsumer::I32,
+        AuxiliaryDataType::U32(_) => AuxiliaryDataTypeConsumer::U32,
+        AuxiliaryDataType::Bool(_) => AuxiliaryDataTypeConsumer::Bool,
+        AuxiliaryDataType::Vec2(_) => AuxiliaryDataTypeConsumer::Vec2,
+        AuxiliaryDataType::Vec3(_) => AuxiliaryDataTypeConsumer::Vec3,
+        AuxiliaryDataType::Vec4(_) => AuxiliaryDataTypeConsumer::Vec4,
+        AuxiliaryDataType::Enum(_) => AuxiliaryDataTypeConsumer::Enum,
     }
 }
@@ -0,0 +1,176 @@
+use std::{
+    fmt::{self, Display},
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::devices::auxiliary_data::AuxDeviceData;
+
+use super::AuxiliaryDataType;
+
+// Increased whenever the layout of a recording changes
+const RECORDING_VERSION: u32 = 1;
+
+/// Written once at the start of a recording.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub frame_numerator: u32,
+    pub frame_denominator: u32,
+}
+
+/// Every update received during a frame, frames without updates are not written.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordedFrame {
+    // Frames since the recording started
+    pub frame: u32,
+    // Aux Id -> Data, in the order they were received
+    pub updates: Vec<(String, AuxiliaryDataType)>,
+}
+
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub frames: Vec<RecordedFrame>,
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(std::io::Error),
+    Encoding(bincode::Error),
+    UnsupportedVersion(u32),
+}
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(err) => write!(f, "{}", err),
+            RecordingError::Encoding(err) => write!(f, "{}", err),
+            RecordingError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported recording version {}", version)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for RecordingError {
+    fn from(err: std::io::Error) -> Self {
+        RecordingError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for RecordingError {
+    fn from(err: bincode::Error) -> Self {
+        RecordingError::Encoding(err)
+    }
+}
+
+pub struct AuxiliaryRecorder {
+    writer: BufWriter<File>,
+    // Frame number the recording started at, recorded frames are relative to it
+    start_frame: Option<u32>,
+    current_frame: RecordedFrame,
+}
+
+impl AuxiliaryRecorder {
+    pub fn create(
+        path: &Path,
+        frame_numerator: u32,
+        frame_denominator: u32,
+    ) -> Result<Self, RecordingError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(
+            &mut writer,
+            &RecordingHeader {
+                version: RECORDING_VERSION,
+                frame_numerator,
+                frame_denominator,
+            },
+        )?;
+        writer.flush()?;
+
+        Ok(AuxiliaryRecorder {
+            writer,
+            start_frame: None,
+            current_frame: RecordedFrame {
+                frame: 0,
+                updates: vec![],
+            },
+        })
+    }
+
+    /// Records an update received during `frame`.
+    pub fn record(&mut self, frame: u32, data: &AuxDeviceData) -> Result<(), RecordingError> {
+        let start_frame = *self.start_frame.get_or_insert(frame);
+        let frame = frame.saturating_sub(start_frame);
+
+        if frame != self.current_frame.frame {
+            self.write_current_frame()?;
+            self.current_frame.frame = frame;
+        }
+
+        self.current_frame
+            .updates
+            .push((data.aux_id.unprotect(), data.data.clone()));
+
+        Ok(())
+    }
+
+    /// Writes the frame being recorded once `frame` has moved past it, so a recording is never
+    /// more than a frame behind and the last frame isn't lost when no more updates arrive.
+    /// The recording starts at the first frame seen, even if nothing was updated during it.
+    pub fn finish_frames_before(&mut self, frame: u32) -> Result<(), RecordingError> {
+        let start_frame = *self.start_frame.get_or_insert(frame);
+
+        if frame.saturating_sub(start_frame) > self.current_frame.frame {
+            self.write_current_frame()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_current_frame(&mut self) -> Result<(), RecordingError> {
+        if self.current_frame.updates.is_empty() {
+            return Ok(());
+        }
+
+        bincode::serialize_into(&mut self.writer, &self.current_frame)?;
+        self.writer.flush()?;
+        self.current_frame.updates.clear();
+
+        Ok(())
+    }
+}
+
+impl Drop for AuxiliaryRecorder {
+    fn drop(&mut self) {
+        self.write_current_frame().ok();
+    }
+}
+
+pub fn read_recording(path: &Path) -> Result<Recording, RecordingError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let header: RecordingHeader = bincode::deserialize_from(&mut reader)?;
+    if header.version != RECORDING_VERSION {
+        return Err(RecordingError::UnsupportedVersion(header.version));
+    }
+
+    let mut frames: Vec<RecordedFrame> = vec![];
+    loop {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(frame) => frames.push(frame),
+            Err(err) => match *err {
+                // A recording ends wherever the recorder stopped, including part way through a frame
+                bincode::ErrorKind::Io(ref io_err) if io_err.kind() == ErrorKind::UnexpectedEof => {
+                    break
+                }
+                _ => return Err(err.into()),
+            },
+        }
+    }
+
+    Ok(Recording { header, frames })
+}
@@ -3,10 +3,16 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    animation::ShadersConfig,
-    auxiliary_data::{manager::AuxiliaryConfigParams, AuxiliaryDataTypeConsumer},
+    animation::{time::AnimationTimeControls, AnimationTargetType, ShadersConfig},
+    auxiliary_data::{
+        manager::AuxiliaryConfigParams, AuxColour, AuxiliaryDataTypeConsumer, ManifestValue,
+    },
+    cue::CueListConfig,
     devices::manager::DeviceConfigType,
-    id::AuxiliaryId,
+    id::{AuxiliaryId, FixtureId, GroupId, InstallationId},
+    schedule::ScheduleConfig,
+    tempo::TempoConfig,
+    timecode::TimecodeConfig,
     world::Coord,
 };
 
@@ -17,6 +23,14 @@ pub struct EmblinkenatorConfig {
     pub frame_denominator: u32,
     pub leds_per_compute_group: u32,
     pub shaders: ShadersConfig,
+    // Frames rendered ahead of the current frame and held until their scheduled time, 0 renders each frame when it is due
+    #[serde(default)]
+    pub render_ahead: u32,
+    #[serde(default)]
+    pub tempo: TempoConfig,
+    // External timecode the frame clock's time follows
+    #[serde(default)]
+    pub timecode: Option<TimecodeConfig>,
 }
 
 // Temporary solution for adding some devices before a UI comes into existence
@@ -29,6 +43,24 @@ pub struct StartupConfig {
     pub fixtures_to_device: HashMap<String, String>,
     // AnimationId -> [AuxiliaryId]
     pub animation_auxiliary_sources: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    pub modulations: Vec<StartupModulation>,
+    #[serde(default)]
+    pub cue_lists: Vec<CueListConfig>,
+    #[serde(default)]
+    pub schedule: Option<ScheduleConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct StartupModulation {
+    pub source: String,
+    pub destination: String,
+    #[serde(default = "default_modulation_depth")]
+    pub depth: f32,
+}
+
+fn default_modulation_depth() -> f32 {
+    1.0
 }
 
 #[derive(Deserialize)]
@@ -43,6 +75,9 @@ pub struct StartupAnimations {
     pub id: String,
     pub shader_id: String,
     pub target_id: StartupAnimationTargetType,
+    // Parameter Id -> Value
+    pub parameters: Option<HashMap<String, ManifestValue>>,
+    pub time: Option<AnimationTimeControls>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -54,6 +89,12 @@ pub enum StartupAuxiliaries {
         initial_value: Option<f32>,
         max_value: Option<f32>,
         min_value: Option<f32>,
+        // Maximum change in units per second
+        slew_rate: Option<f32>,
+        // Time constant in seconds
+        smoothing: Option<f32>,
+        // e.g. "0.5 + 0.5 * sin(t) * audio_level"
+        expression: Option<String>,
     },
     F32Vec {
         id: String,
@@ -71,6 +112,51 @@ pub enum StartupAuxiliaries {
         id: String,
         name: String,
     },
+    Colour {
+        id: String,
+        name: String,
+        initial_value: Option<AuxColour>,
+    },
+    I32 {
+        id: String,
+        name: String,
+        initial_value: Option<i32>,
+        max_value: Option<i32>,
+        min_value: Option<i32>,
+    },
+    U32 {
+        id: String,
+        name: String,
+        initial_value: Option<u32>,
+        max_value: Option<u32>,
+        min_value: Option<u32>,
+    },
+    Bool {
+        id: String,
+        name: String,
+        initial_value: Option<bool>,
+    },
+    Vec2 {
+        id: String,
+        name: String,
+        initial_value: Option<[f32; 2]>,
+    },
+    Vec3 {
+        id: String,
+        name: String,
+        initial_value: Option<[f32; 3]>,
+    },
+    Vec4 {
+        id: String,
+        name: String,
+        initial_value: Option<[f32; 4]>,
+    },
+    Enum {
+        id: String,
+        name: String,
+        options: Vec<String>,
+        initial_value: Option<u32>,
+    },
 }
 
 impl StartupAuxiliaries {
@@ -82,11 +168,22 @@ impl StartupAuxiliaries {
                 initial_value: _,
                 max_value: _,
                 min_value: _,
+                slew_rate: _,
+                smoothing: _,
+                expression: _,
             } => name.clone(),
             StartupAuxiliaries::F32Vec { id: _, name } => name.clone(),
             StartupAuxiliaries::F32Vec2 { id: _, name } => name.clone(),
             StartupAuxiliaries::F32Vec3 { id: _, name } => name.clone(),
             StartupAuxiliaries::F32Vec4 { id: _, name } => name.clone(),
+            StartupAuxiliaries::Colour { id: _, name, .. } => name.clone(),
+            StartupAuxiliaries::I32 { id: _, name, .. } => name.clone(),
+            StartupAuxiliaries::U32 { id: _, name, .. } => name.clone(),
+            StartupAuxiliaries::Bool { id: _, name, .. } => name.clone(),
+            StartupAuxiliaries::Vec2 { id: _, name, .. } => name.clone(),
+            StartupAuxiliaries::Vec3 { id: _, name, .. } => name.clone(),
+            StartupAuxiliaries::Vec4 { id: _, name, .. } => name.clone(),
+            StartupAuxiliaries::Enum { id: _, name, .. } => name.clone(),
         }
     }
 }
@@ -100,11 +197,22 @@ impl From<StartupAuxiliaries> for AuxiliaryId {
                 initial_value: _,
                 max_value: _,
                 min_value: _,
+                slew_rate: _,
+                smoothing: _,
+                expression: _,
             } => AuxiliaryId::new_from(id),
             StartupAuxiliaries::F32Vec { id, name: _ } => AuxiliaryId::new_from(id),
             StartupAuxiliaries::F32Vec2 { id, name: _ } => AuxiliaryId::new_from(id),
             StartupAuxiliaries::F32Vec3 { id, name: _ } => AuxiliaryId::new_from(id),
             StartupAuxiliaries::F32Vec4 { id, name: _ } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::Colour { id, .. } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::I32 { id, .. } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::U32 { id, .. } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::Bool { id, .. } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::Vec2 { id, .. } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::Vec3 { id, .. } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::Vec4 { id, .. } => AuxiliaryId::new_from(id),
+            StartupAuxiliaries::Enum { id, .. } => AuxiliaryId::new_from(id),
         }
     }
 }
@@ -118,11 +226,22 @@ impl From<StartupAuxiliaries> for AuxiliaryDataTypeConsumer {
                 initial_value: _,
                 max_value: _,
                 min_value: _,
+                slew_rate: _,
+                smoothing: _,
+                expression: _,
             } => AuxiliaryDataTypeConsumer::F32,
             StartupAuxiliaries::F32Vec { id: _, name: _ } => AuxiliaryDataTypeConsumer::F32Vec,
             StartupAuxiliaries::F32Vec2 { id: _, name: _ } => AuxiliaryDataTypeConsumer::F32Vec2,
             StartupAuxiliaries::F32Vec3 { id: _, name: _ } => AuxiliaryDataTypeConsumer::F32Vec3,
             StartupAuxiliaries::F32Vec4 { id: _, name: _ } => AuxiliaryDataTypeConsumer::F32Vec4,
+            StartupAuxiliaries::Colour { .. } => AuxiliaryDataTypeConsumer::Colour,
+            StartupAuxiliaries::I32 { .. } => AuxiliaryDataTypeConsumer::I32,
+            StartupAuxiliaries::U32 { .. } => AuxiliaryDataTypeConsumer::U32,
+            StartupAuxiliaries::Bool { .. } => AuxiliaryDataTypeConsumer::Bool,
+            StartupAuxiliaries::Vec2 { .. } => AuxiliaryDataTypeConsumer::Vec2,
+            StartupAuxiliaries::Vec3 { .. } => AuxiliaryDataTypeConsumer::Vec3,
+            StartupAuxiliaries::Vec4 { .. } => AuxiliaryDataTypeConsumer::Vec4,
+            StartupAuxiliaries::Enum { .. } => AuxiliaryDataTypeConsumer::Enum,
         }
     }
 }
@@ -136,15 +255,87 @@ impl From<StartupAuxiliaries> for AuxiliaryConfigParams {
                 initial_value,
                 max_value,
                 min_value,
+                slew_rate,
+                smoothing,
+                expression,
             } => AuxiliaryConfigParams::F32 {
                 initial_value: initial_value.unwrap_or(0.0),
-                max_value: max_value.unwrap_or(1.0).clamp(0.0, 1.0),
-                min_value: min_value.unwrap_or(0.0).clamp(0.0, 1.0),
+                max_value: max_value.unwrap_or(f32::MAX),
+                min_value: min_value.unwrap_or(f32::MIN),
+                slew_rate,
+                smoothing,
+                expression,
             },
             StartupAuxiliaries::F32Vec { id: _, name: _ } => AuxiliaryConfigParams::F32Vec,
             StartupAuxiliaries::F32Vec2 { id: _, name: _ } => AuxiliaryConfigParams::F32Vec2,
             StartupAuxiliaries::F32Vec3 { id: _, name: _ } => AuxiliaryConfigParams::F32Vec3,
             StartupAuxiliaries::F32Vec4 { id: _, name: _ } => AuxiliaryConfigParams::F32Vec4,
+            StartupAuxiliaries::Colour {
+                id: _,
+                name: _,
+                initial_value,
+            } => AuxiliaryConfigParams::Colour {
+                initial_value: initial_value.unwrap_or(AuxColour::Rgb([0.0; 3])),
+            },
+            StartupAuxiliaries::I32 {
+                id: _,
+                name: _,
+                initial_value,
+                max_value,
+                min_value,
+            } => AuxiliaryConfigParams::I32 {
+                initial_value: initial_value.unwrap_or(0),
+                max_value: max_value.unwrap_or(i32::MAX),
+                min_value: min_value.unwrap_or(i32::MIN),
+            },
+            StartupAuxiliaries::U32 {
+                id: _,
+                name: _,
+                initial_value,
+                max_value,
+                min_value,
+            } => AuxiliaryConfigParams::U32 {
+                initial_value: initial_value.unwrap_or(0),
+                max_value: max_value.unwrap_or(u32::MAX),
+                min_value: min_value.unwrap_or(u32::MIN),
+            },
+            StartupAuxiliaries::Bool {
+                id: _,
+                name: _,
+                initial_value,
+            } => AuxiliaryConfigParams::Bool {
+                initial_value: initial_value.unwrap_or(false),
+            },
+            StartupAuxiliaries::Vec2 {
+                id: _,
+                name: _,
+                initial_value,
+            } => AuxiliaryConfigParams::Vec2 {
+                initial_value: initial_value.unwrap_or_default(),
+            },
+            StartupAuxiliaries::Vec3 {
+                id: _,
+                name: _,
+                initial_value,
+            } => AuxiliaryConfigParams::Vec3 {
+                initial_value: initial_value.unwrap_or_default(),
+            },
+            StartupAuxiliaries::Vec4 {
+                id: _,
+                name: _,
+                initial_value,
+            } => AuxiliaryConfigParams::Vec4 {
+                initial_value: initial_value.unwrap_or_default(),
+            },
+            StartupAuxiliaries::Enum {
+                id: _,
+                name: _,
+                options,
+                initial_value,
+            } => AuxiliaryConfigParams::Enum {
+                initial_value: initial_value.unwrap_or(0),
+                options,
+            },
         }
     }
 }
@@ -155,13 +346,29 @@ pub struct StartupDevice {
     pub config: DeviceConfigType,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Debug)]
 pub enum StartupAnimationTargetType {
     Fixture(String),
     Installation(String),
     Group(String),
 }
 
+impl From<StartupAnimationTargetType> for AnimationTargetType {
+    fn from(target: StartupAnimationTargetType) -> Self {
+        match target {
+            StartupAnimationTargetType::Fixture(id) => {
+                AnimationTargetType::Fixture(FixtureId::new_from(id))
+            }
+            StartupAnimationTargetType::Installation(id) => {
+                AnimationTargetType::Installation(InstallationId::new_from(id))
+            }
+            StartupAnimationTargetType::Group(id) => {
+                AnimationTargetType::Group(GroupId::new_from(id))
+            }
+        }
+    }
+}
+
 impl EmblinkenatorConfig {
     pub fn frame_buffer_size(&self) -> u32 {
         self.frame_buffer_size
@@ -178,6 +385,10 @@ impl EmblinkenatorConfig {
     pub fn leds_per_compute_group(&self) -> u32 {
         self.leds_per_compute_group
     }
+
+    pub fn render_ahead(&self) -> u32 {
+        self.render_ahead
+    }
 }
 
 impl Default for EmblinkenatorConfig {
@@ -188,6 +399,9 @@ impl Default for EmblinkenatorConfig {
             frame_denominator: 25,
             leds_per_compute_group: 64,
             shaders: ShadersConfig::default(),
+            render_ahead: 0,
+            tempo: TempoConfig::default(),
+            timecode: None,
         }
     }
 }
@@ -0,0 +1,528 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use log::{debug, error, info, warn};
+use parking_lot::RwLock;
+
+use crate::{
+    animation::{manager::AnimationManager, parameters::ParameterValue},
+    auxiliary_data::{manager::AuxiliaryDataManager, AuxiliaryDataType, ManifestValue},
+    devices::manager::ThreadedDeviceType,
+    frame::FrameData,
+    frame_resolver::FrameResolver,
+    id::{AnimationId, AuxiliaryId, DeviceId, FixtureId},
+    state::{EmblinkenatorState, ThreadedObject, WantsDeviceState},
+};
+
+use super::{CueAction, CueConfig, CueEvent, CueListConfig, CueTrigger};
+
+struct CueList {
+    id: String,
+    cues: Vec<CueConfig>,
+    // Index of the cue waiting for its trigger
+    next_cue: usize,
+    last_fired: Option<Instant>,
+}
+
+#[derive(PartialEq)]
+enum FadeTarget {
+    Auxiliary(AuxiliaryId),
+    Parameter(AnimationId, String),
+    Brightness,
+}
+
+/// A value moving from one cue's value to the next.
+struct Fade {
+    target: FadeTarget,
+    from: f32,
+    to: f32,
+    started: Instant,
+    duration: Duration,
+}
+
+/// Plays cue lists, changing animations, auxiliaries, fixture routing, brightness and the frame rate while running.
+pub struct CueManager {
+    animation_manager: Arc<RwLock<AnimationManager>>,
+    auxiliary_data_manager: Arc<RwLock<AuxiliaryDataManager>>,
+    frame_resolver: Arc<RwLock<FrameResolver>>,
+    cue_lists: Vec<CueList>,
+    fades: Vec<Fade>,
+    cue_sender: Sender<CueEvent>,
+    cue_receiver: Receiver<CueEvent>,
+    next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
+    next_frame_data: Option<FrameData>,
+    // (frame_numerator, frame_denominator)
+    frame_rate_sender: Option<Sender<(u32, u32)>>,
+}
+
+impl CueManager {
+    pub fn new(
+        animation_manager: Arc<RwLock<AnimationManager>>,
+        auxiliary_data_manager: Arc<RwLock<AuxiliaryDataManager>>,
+        frame_resolver: Arc<RwLock<FrameResolver>>,
+    ) -> Self {
+        let (cue_sender, cue_receiver) = unbounded();
+
+        CueManager {
+            animation_manager,
+            auxiliary_data_manager,
+            frame_resolver,
+            cue_lists: vec![],
+            fades: vec![],
+            cue_sender,
+            cue_receiver,
+            next_frame_data_buffer: None,
+            next_frame_data: None,
+            frame_rate_sender: None,
+        }
+    }
+
+    pub fn add_cue_list(&mut self, config: CueListConfig) {
+        if self.cue_lists.iter().any(|list| list.id == config.id) {
+            warn!("Cue list {} already exists, ignoring it", config.id);
+            return;
+        }
+
+        self.cue_lists.push(CueList {
+            id: config.id,
+            cues: config.cues,
+            next_cue: 0,
+            last_fired: None,
+        });
+    }
+
+    pub fn receive_next_frame_data_buffer(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    /// Frame rate changes made by cues are sent to the frame time keeper through here.
+    pub fn send_frame_rate_changes_to(&mut self, sender: Sender<(u32, u32)>) {
+        self.frame_rate_sender.replace(sender);
+    }
+
+    /// Devices that trigger cues send GO events here.
+    pub fn cue_sender(&self) -> Sender<CueEvent> {
+        self.cue_sender.clone()
+    }
+
+    /// Fires the next cue of `list`, or of every list whose next cue is waiting for a GO.
+    pub fn go(&mut self, list: Option<&str>) {
+        let now = Instant::now();
+
+        for list_index in 0..self.cue_lists.len() {
+            let cue_list = &self.cue_lists[list_index];
+            let cue_index = cue_list.next_cue;
+            let next_cue = match cue_list.cues.get(cue_index) {
+                Some(next_cue) => next_cue,
+                None => continue,
+            };
+
+            let fire = match list {
+                Some(list) => cue_list.id == list,
+                None => matches!(next_cue.trigger, CueTrigger::Go),
+            };
+            if fire {
+                self.fire_cue(list_index, cue_index, now);
+            }
+        }
+
+        if let Some(list) = list {
+            if !self.cue_lists.iter().any(|cue_list| cue_list.id == list) {
+                warn!("Cannot GO on cue list {} which doesn't exist", list);
+            }
+        }
+    }
+
+    /// Fires a cue whatever its trigger, the list carries on from it.
+    pub fn go_to(&mut self, list: &str, cue: &str) {
+        let list_index = match self
+            .cue_lists
+            .iter()
+            .position(|cue_list| cue_list.id == list)
+        {
+            Some(list_index) => list_index,
+            None => {
+                warn!(
+                    "Cannot go to a cue in cue list {} which doesn't exist",
+                    list
+                );
+                return;
+            }
+        };
+
+        match self.cue_lists[list_index]
+            .cues
+            .iter()
+            .position(|cue_config| cue_config.id == cue)
+        {
+            Some(cue_index) => self.fire_cue(list_index, cue_index, Instant::now()),
+            None => warn!("Cue {} does not exist in cue list {}", cue, list),
+        }
+    }
+
+    fn fire_triggered_cues(&mut self, now: Instant) {
+        for list_index in 0..self.cue_lists.len() {
+            // Cues that trigger straight after each other all fire now
+            while let Some(cue_index) = self.triggered_cue(list_index, now) {
+                self.fire_cue(list_index, cue_index, now);
+            }
+        }
+    }
+
+    fn triggered_cue(&self, list_index: usize, now: Instant) -> Option<usize> {
+        let cue_list = &self.cue_lists[list_index];
+        let next_cue = cue_list.cues.get(cue_list.next_cue)?;
+
+        let triggered = match next_cue.trigger {
+            CueTrigger::Go => false,
+            CueTrigger::Time { seconds } => self
+                .next_frame_data
+                .as_ref()
+                .map(|frame_data| frame_data.precise_seconds_elapsed() >= seconds)
+                .unwrap_or(false),
+            CueTrigger::Frame { frame } => self
+                .next_frame_data
+                .as_ref()
+                .map(|frame_data| frame_data.frame >= frame)
+                .unwrap_or(false),
+            CueTrigger::Follow { wait } => match cue_list.last_fired {
+                Some(last_fired) => now.saturating_duration_since(last_fired).as_secs_f64() >= wait,
+                None => false,
+            },
+        };
+
+        if triggered {
+            Some(cue_list.next_cue)
+        } else {
+            None
+        }
+    }
+
+    fn fire_cue(&mut self, list_index: usize, cue_index: usize, now: Instant) {
+        let cue_list = &mut self.cue_lists[list_index];
+        let cue = cue_list.cues[cue_index].clone();
+        cue_list.next_cue = cue_index + 1;
+        cue_list.last_fired = Some(now);
+        let cue_name = format!("{} in {}", cue.id, cue_list.id);
+
+        info!("Firing cue {}", cue_name);
+
+        self.apply_actions(&cue_name, cue.actions, cue.fade, now);
+    }
+
+    /// Makes a cue's changes, `name` is only used for logging.
+    pub fn apply_actions(&mut self, name: &str, actions: Vec<CueAction>, fade: f64, now: Instant) {
+        let fade = Duration::from_secs_f64(fade.max(0.0));
+        for action in actions {
+            self.apply_action(action, fade, name, now);
+        }
+    }
+
+    fn apply_action(&mut self, action: CueAction, fade: Duration, cue_name: &str, now: Instant) {
+        match action {
+            CueAction::CreateAnimation {
+                id,
+                shader_id,
+                target_id,
+                parameters,
+                time,
+            } => {
+                let animation_id = match self.animation_manager.write().create_animation(
+                    AnimationId::new_from(id.clone()),
+                    shader_id,
+                    target_id.into(),
+                ) {
+                    Ok(animation_id) => animation_id,
+                    Err(err) => {
+                        error!(
+                            "Cannot create animation {} ({}) (Cue {})",
+                            id, err, cue_name
+                        );
+                        return;
+                    }
+                };
+
+                // A new animation has nothing to fade from
+                for (name, value) in parameters {
+                    self.set_parameter(&animation_id, name, value, Duration::ZERO, cue_name, now);
+                }
+                if let Some(time_controls) = time {
+                    self.animation_manager
+                        .read()
+                        .set_time_controls(&animation_id, time_controls)
+                        .ok();
+                }
+            }
+            CueAction::DestroyAnimation { id } => {
+                let animation_id = AnimationId::new_from(id);
+                self.fades.retain(|fade| {
+                    !matches!(&fade.target, FadeTarget::Parameter(fade_animation_id, _) if *fade_animation_id == animation_id)
+                });
+                if let Err(err) = self
+                    .animation_manager
+                    .write()
+                    .destroy_animation(&animation_id)
+                {
+                    error!(
+                        "Cannot destroy animation {} ({}) (Cue {})",
+                        animation_id, err, cue_name
+                    );
+                }
+            }
+            CueAction::SetParameter {
+                animation_id,
+                name,
+                value,
+            } => self.set_parameter(
+                &AnimationId::new_from(animation_id),
+                name,
+                value,
+                fade,
+                cue_name,
+                now,
+            ),
+            CueAction::SetTimeControls { animation_id, time } => {
+                let animation_id = AnimationId::new_from(animation_id);
+                if let Err(err) = self
+                    .animation_manager
+                    .read()
+                    .set_time_controls(&animation_id, time)
+                {
+                    error!(
+                        "Cannot set time controls of animation {} ({}) (Cue {})",
+                        animation_id, err, cue_name
+                    );
+                }
+            }
+            CueAction::SetAuxiliary { aux_id, value } => {
+                self.set_auxiliary(AuxiliaryId::new_from(aux_id), value, fade, now)
+            }
+            CueAction::SetAuxiliarySources {
+                animation_id,
+                aux_ids,
+            } => self
+                .auxiliary_data_manager
+                .read()
+                .set_animation_auxiliary_sources_to(
+                    AnimationId::new_from(animation_id),
+                    aux_ids.into_iter().map(AuxiliaryId::new_from).collect(),
+                ),
+            CueAction::SetFixtureDevice {
+                fixture_id,
+                device_id,
+            } => {
+                let fixture_id = FixtureId::new_from(fixture_id);
+                match device_id {
+                    Some(device_id) => self
+                        .frame_resolver
+                        .write()
+                        .set_fixture_to_device(fixture_id, DeviceId::new_from(device_id)),
+                    None => self
+                        .frame_resolver
+                        .write()
+                        .remove_fixture_to_device(&fixture_id),
+                }
+            }
+            CueAction::SetBrightness { brightness } => {
+                self.fades
+                    .retain(|fade| fade.target != FadeTarget::Brightness);
+                if fade.is_zero() {
+                    self.frame_resolver.write().set_brightness(brightness);
+                } else {
+                    self.fades.push(Fade {
+                        target: FadeTarget::Brightness,
+                        from: self.frame_resolver.read().get_brightness(),
+                        to: brightness,
+                        started: now,
+                        duration: fade,
+                    });
+                }
+            }
+            CueAction::SetBlackout { blackout } => {
+                self.frame_resolver.write().set_blackout(blackout)
+            }
+            CueAction::SetFrameRate {
+                frame_numerator,
+                frame_denominator,
+            } => match &self.frame_rate_sender {
+                Some(frame_rate_sender) => {
+                    frame_rate_sender
+                        .send((frame_numerator, frame_denominator))
+                        .ok();
+                }
+                None => warn!("Cannot change the frame rate (Cue {})", cue_name),
+            },
+        }
+    }
+
+    fn set_parameter(
+        &mut self,
+        animation_id: &AnimationId,
+        name: String,
+        value: ManifestValue,
+        fade: Duration,
+        cue_name: &str,
+        now: Instant,
+    ) {
+        let target = FadeTarget::Parameter(animation_id.clone(), name.clone());
+        self.fades.retain(|fade| fade.target != target);
+
+        // Only F32 parameters fade, others change straight away
+        let current_value = self
+            .animation_manager
+            .read()
+            .get_animation(animation_id)
+            .and_then(|animation| animation.get_parameter_value(&name));
+        if let (Some(ParameterValue::F32(from)), ManifestValue::Number(to)) =
+            (current_value, &value)
+        {
+            if !fade.is_zero() {
+                self.fades.push(Fade {
+                    target,
+                    from,
+                    to: *to,
+                    started: now,
+                    duration: fade,
+                });
+                return;
+            }
+        }
+
+        if let Err(err) = self
+            .animation_manager
+            .read()
+            .set_parameter(animation_id, &name, value)
+        {
+            error!(
+                "Cannot set parameter {} of animation {} ({}) (Cue {})",
+                name, animation_id, err, cue_name
+            );
+        }
+    }
+
+    fn set_auxiliary(
+        &mut self,
+        aux_id: AuxiliaryId,
+        value: AuxiliaryDataType,
+        fade: Duration,
+        now: Instant,
+    ) {
+        let target = FadeTarget::Auxiliary(aux_id.clone());
+        self.fades.retain(|fade| fade.target != target);
+
+        // Only F32 auxiliaries fade, others change straight away
+        let current_value = self
+            .auxiliary_data_manager
+            .read()
+            .get_auxiliary_value(&aux_id);
+        if let (Some(AuxiliaryDataType::F32(from)), AuxiliaryDataType::F32(to)) =
+            (current_value, &value)
+        {
+            if !fade.is_zero() {
+                self.fades.push(Fade {
+                    target,
+                    from,
+                    to: *to,
+                    started: now,
+                    duration: fade,
+                });
+                return;
+            }
+        }
+
+        self.auxiliary_data_manager
+            .read()
+            .set_auxiliary(aux_id, value);
+    }
+
+    fn step_fades(&mut self, now: Instant) {
+        for fade in self.fades.iter() {
+            let progress = (now.saturating_duration_since(fade.started).as_secs_f64()
+                / fade.duration.as_secs_f64())
+            .min(1.0) as f32;
+            let value = fade.from + (fade.to - fade.from) * progress;
+
+            match &fade.target {
+                FadeTarget::Auxiliary(aux_id) => self
+                    .auxiliary_data_manager
+                    .read()
+                    .set_auxiliary(aux_id.clone(), AuxiliaryDataType::F32(value)),
+                FadeTarget::Parameter(animation_id, name) => {
+                    self.animation_manager
+                        .read()
+                        .set_parameter(animation_id, name, ManifestValue::Number(value))
+                        .ok();
+                }
+                FadeTarget::Brightness => self.frame_resolver.write().set_brightness(value),
+            }
+        }
+
+        self.fades
+            .retain(|fade| now.saturating_duration_since(fade.started) < fade.duration);
+    }
+
+    fn receive_next_frame_data(&mut self) -> bool {
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return false,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                self.next_frame_data.replace(next_frame_data);
+                true
+            }
+            Err(tokio::sync::broadcast::error::TryRecvError::Empty) => false,
+            Err(tokio::sync::broadcast::error::TryRecvError::Closed) => {
+                error!("Cue manager had next frame data buffer closed");
+                self.next_frame_data_buffer.take();
+                false
+            }
+            Err(tokio::sync::broadcast::error::TryRecvError::Lagged(_)) => false,
+        }
+    }
+}
+
+impl ThreadedObject for CueManager {
+    fn tick(&mut self) {
+        let events: Vec<CueEvent> = self.cue_receiver.try_iter().collect();
+        for event in events {
+            debug!("Received cue event {:?}", event);
+            match event {
+                CueEvent::Go(list) => self.go(list.as_deref()),
+                CueEvent::GoTo { list, cue } => self.go_to(&list, &cue),
+                CueEvent::Apply {
+                    name,
+                    actions,
+                    fade,
+                } => self.apply_actions(&name, actions, fade, Instant::now()),
+            }
+        }
+
+        let now = Instant::now();
+        // Fades step once per frame rather than every tick
+        if self.receive_next_frame_data() {
+            self.step_fades(now);
+        }
+        self.fire_triggered_cues(now);
+    }
+}
+
+impl WantsDeviceState for CueManager {
+    fn on_device_added(&mut self, state: &EmblinkenatorState, device_id: DeviceId) {
+        if let Some(device) = state.get_device(&device_id) {
+            match &mut *device.write() {
+                ThreadedDeviceType::LEDDataOutput(_) => {} // Nothing to do
+                ThreadedDeviceType::AuxiliaryData(aux_device) => {
+                    aux_device.send_cues_into(self.cue_sender())
+                }
+            }
+        }
+    }
+}
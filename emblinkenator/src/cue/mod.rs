@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    animation::time::AnimationTimeControls,
+    auxiliary_data::{AuxiliaryDataType, ManifestValue},
+    config::StartupAnimationTargetType,
+};
+
+pub mod manager;
+
+/// Cues played in order, each waiting for its trigger after the previous one has fired.
+#[derive(Clone, Deserialize)]
+pub struct CueListConfig {
+    pub id: String,
+    pub cues: Vec<CueConfig>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CueConfig {
+    // Cue number or name, e.g. "12.5"
+    pub id: String,
+    #[serde(default)]
+    pub trigger: CueTrigger,
+    // Seconds that F32 auxiliaries and parameters take to fade to the cue's values
+    #[serde(default)]
+    pub fade: f64,
+    pub actions: Vec<CueAction>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "type")]
+pub enum CueTrigger {
+    // Waits for a GO from a device
+    #[default]
+    Go,
+    // Fires once `seconds_elapsed` reaches `seconds`, which follows the timecode when one is being chased
+    Time {
+        seconds: f64,
+    },
+    Frame {
+        frame: u32,
+    },
+    // Fires `wait` seconds after the previous cue in the list
+    Follow {
+        wait: f64,
+    },
+}
+
+/// A change made when a cue fires.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum CueAction {
+    CreateAnimation {
+        id: String,
+        shader_id: String,
+        target_id: StartupAnimationTargetType,
+        // Parameter Id -> Value
+        #[serde(default)]
+        parameters: HashMap<String, ManifestValue>,
+        time: Option<AnimationTimeControls>,
+    },
+    DestroyAnimation {
+        id: String,
+    },
+    SetParameter {
+        animation_id: String,
+        name: String,
+        value: ManifestValue,
+    },
+    SetTimeControls {
+        animation_id: String,
+        time: AnimationTimeControls,
+    },
+    SetAuxiliary {
+        aux_id: String,
+        value: AuxiliaryDataType,
+    },
+    SetAuxiliarySources {
+        animation_id: String,
+        aux_ids: Vec<String>,
+    },
+    // Routes a fixture's LEDs to a device, or stops sending them anywhere if there is no device
+    SetFixtureDevice {
+        fixture_id: String,
+        device_id: Option<String>,
+    },
+    // Master level of every LED, 0 - 1
+    SetBrightness {
+        brightness: f32,
+    },
+    // Turns every LED off while animations keep running
+    SetBlackout {
+        blackout: bool,
+    },
+    // Each frame lasts frame_numerator / frame_denominator milliseconds, as in config.json
+    SetFrameRate {
+        frame_numerator: u32,
+        frame_denominator: u32,
+    },
+}
+
+/// Show control events, sent by devices that trigger cues.
+#[derive(Clone, Debug)]
+pub enum CueEvent {
+    // Fires the next cue of the named list, or of every list whose next cue is waiting for a GO
+    Go(Option<String>),
+    // Fires a cue and carries on from it
+    GoTo {
+        list: String,
+        cue: String,
+    },
+    // Makes changes that aren't part of a cue list, e.g. from a schedule
+    Apply {
+        name: String,
+        actions: Vec<CueAction>,
+        fade: f64,
+    },
+}
@@ -0,0 +1,590 @@
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    io::Read,
+    process::{Command, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, warn};
+use parking_lot::Mutex;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde::Deserialize;
+
+use crate::{
+    auxiliary_data::AuxiliaryDataType,
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+};
+
+use super::{AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Deserialize)]
+pub struct AudioAnalyserConfig {
+    source: AudioSource,
+    #[serde(default = "default_fft_size")]
+    fft_size: usize,
+    #[serde(default = "default_bands")]
+    bands: usize,
+    #[serde(default = "default_min_frequency")]
+    min_frequency: f32,
+    #[serde(default = "default_max_frequency")]
+    max_frequency: f32,
+    // Spectrum values are scaled so that this level is 0.0 and 0dB is 1.0
+    #[serde(default = "default_spectrum_floor_db")]
+    spectrum_floor_db: f32,
+    // A beat is detected when the spectral flux exceeds its recent average by this factor
+    #[serde(default = "default_beat_sensitivity")]
+    beat_sensitivity: f32,
+    // Seconds for the beat pulse to fall from 1.0 to 0.0
+    #[serde(default = "default_beat_decay")]
+    beat_decay: f32,
+    rms_aux_id: Option<String>,
+    spectrum_aux_id: Option<String>,
+    beat_aux_id: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+pub enum AudioSource {
+    // Captures using `arecord`
+    Alsa {
+        device: Option<String>,
+        #[serde(default = "default_sample_rate")]
+        sample_rate: u32,
+    },
+    // Captures using `parec`, this includes PipeWire and JACK setups bridged to PulseAudio
+    PulseAudio {
+        device: Option<String>,
+        #[serde(default = "default_sample_rate")]
+        sample_rate: u32,
+    },
+    // Any command that writes mono signed 16 bit little endian samples to stdout
+    Command {
+        command: String,
+        args: Vec<String>,
+        #[serde(default = "default_sample_rate")]
+        sample_rate: u32,
+    },
+    // Plays a WAV file in time with the frame clock, looping at the end
+    File {
+        path: String,
+    },
+}
+
+fn default_fft_size() -> usize {
+    1024
+}
+
+fn default_bands() -> usize {
+    16
+}
+
+fn default_min_frequency() -> f32 {
+    40.0
+}
+
+fn default_max_frequency() -> f32 {
+    16000.0
+}
+
+fn default_spectrum_floor_db() -> f32 {
+    -60.0
+}
+
+fn default_beat_sensitivity() -> f32 {
+    1.5
+}
+
+fn default_beat_decay() -> f32 {
+    0.2
+}
+
+fn default_sample_rate() -> u32 {
+    44100
+}
+
+// Number of frames of spectral flux the beat threshold is averaged over
+const BEAT_HISTORY_LENGTH: usize = 43;
+// Shortest time in seconds between two beats
+const MIN_BEAT_INTERVAL: f32 = 0.1;
+// Captured audio kept before the newest sample, so windows can end at frames that have already been scheduled
+const CAPTURE_HISTORY: Duration = Duration::from_secs(1);
+
+enum AudioInput {
+    Capture {
+        samples: Arc<Mutex<CapturedSamples>>,
+        sample_rate: u32,
+    },
+    File {
+        samples: Vec<f32>,
+        sample_rate: u32,
+    },
+}
+
+/// Most recent samples from a capture process, the newest at the back.
+struct CapturedSamples {
+    samples: VecDeque<f32>,
+    // When the newest sample was read from the capture process
+    newest_time: Option<Instant>,
+}
+
+pub struct AudioAuxiliaryDataDevice {
+    id: DeviceId,
+    config: AudioAnalyserConfig,
+    input: Option<AudioInput>,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    previous_magnitudes: Vec<f32>,
+    flux_history: VecDeque<f32>,
+    last_beat: Option<f32>,
+    next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
+    // Frame waiting for audio to be captured up to its scheduled time
+    pending_frame_data: Option<FrameData>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+}
+
+impl AudioAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, config: AudioAnalyserConfig) -> Self {
+        let fft_size = config.fft_size.max(2);
+        let input = match &config.source {
+            AudioSource::Alsa {
+                device,
+                sample_rate,
+            } => {
+                let mut args: Vec<String> = vec![];
+                if let Some(device) = device {
+                    args.extend(["-D".to_string(), device.clone()]);
+                }
+                args.extend(
+                    ["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r"]
+                        .iter()
+                        .map(|arg| arg.to_string()),
+                );
+                args.push(sample_rate.to_string());
+                start_capture(&id, "arecord", &args, *sample_rate, fft_size)
+            }
+            AudioSource::PulseAudio {
+                device,
+                sample_rate,
+            } => {
+                let mut args: Vec<String> = vec![
+                    "--raw".to_string(),
+                    "--format=s16le".to_string(),
+                    "--channels=1".to_string(),
+                    format!("--rate={}", sample_rate),
+                ];
+                if let Some(device) = device {
+                    args.push(format!("--device={}", device));
+                }
+                start_capture(&id, "parec", &args, *sample_rate, fft_size)
+            }
+            AudioSource::Command {
+                command,
+                args,
+                sample_rate,
+            } => start_capture(&id, command, args, *sample_rate, fft_size),
+            AudioSource::File { path } => load_wav(&id, path),
+        };
+
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        // Hann window
+        let window = (0..fft_size)
+            .map(|n| {
+                0.5 - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / (fft_size - 1) as f32).cos()
+            })
+            .collect();
+
+        AudioAuxiliaryDataDevice {
+            id,
+            config: AudioAnalyserConfig { fft_size, ..config },
+            input,
+            fft,
+            window,
+            previous_magnitudes: vec![0.0; fft_size / 2],
+            flux_history: VecDeque::with_capacity(BEAT_HISTORY_LENGTH),
+            last_beat: None,
+            next_frame_data_buffer: None,
+            pending_frame_data: None,
+            data_output_buffer: None,
+        }
+    }
+
+    /// Whether audio has been captured up to the time the frame is scheduled for.
+    fn has_samples_for_frame(&self, frame_data: &FrameData) -> bool {
+        match &self.input {
+            Some(AudioInput::Capture { samples, .. }) => samples
+                .lock()
+                .newest_time
+                .is_some_and(|newest_time| newest_time >= frame_data.scheduled_time),
+            Some(AudioInput::File { .. }) => true,
+            None => false,
+        }
+    }
+
+    /// The FFT window ending at the given frame, zero padded if there aren't enough samples yet.
+    fn samples_for_frame(&self, frame_data: &FrameData) -> Option<(Vec<f32>, u32)> {
+        let fft_size = self.config.fft_size;
+
+        match self.input.as_ref()? {
+            AudioInput::Capture {
+                samples,
+                sample_rate,
+            } => {
+                let captured = samples.lock();
+                let newest_time = captured.newest_time?;
+                // Samples captured after the frame's scheduled time are left for later frames
+                let samples_after_frame = (newest_time
+                    .saturating_duration_since(frame_data.scheduled_time)
+                    .as_secs_f64()
+                    * f64::from(*sample_rate)) as usize;
+                let end = captured.samples.len().saturating_sub(samples_after_frame);
+                let start = end.saturating_sub(fft_size);
+
+                let mut window = vec![0.0; fft_size - (end - start)];
+                window.extend(captured.samples.range(start..end));
+                Some((window, *sample_rate))
+            }
+            AudioInput::File {
+                samples,
+                sample_rate,
+            } => {
+                if samples.is_empty() {
+                    return None;
+                }
+                let len = samples.len();
+                let end = (frame_data.precise_seconds_elapsed() * f64::from(*sample_rate)) as usize;
+                let start = (end % len + len - fft_size % len) % len;
+                let window = (0..fft_size)
+                    .map(|offset| samples[(start + offset) % len])
+                    .collect();
+                Some((window, *sample_rate))
+            }
+        }
+    }
+
+    fn analyse_frame(&mut self, frame_data: &FrameData) -> Vec<AuxDeviceData> {
+        let (samples, sample_rate) = match self.samples_for_frame(frame_data) {
+            Some(samples) => samples,
+            None => return vec![],
+        };
+        let mut outputs: Vec<AuxDeviceData> = vec![];
+
+        let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>()
+            / samples.len() as f32)
+            .sqrt();
+        if let Some(aux_id) = &self.config.rms_aux_id {
+            outputs.push(AuxDeviceData {
+                aux_id: AuxiliaryId::new_from(aux_id.clone()),
+                data: AuxiliaryDataType::F32(rms),
+            });
+        }
+
+        let mut spectrum: Vec<Complex<f32>> = samples
+            .iter()
+            .zip(self.window.iter())
+            .map(|(sample, window)| Complex::new(sample * window, 0.0))
+            .collect();
+        self.fft.process(&mut spectrum);
+
+        let half = self.config.fft_size / 2;
+        let magnitudes: Vec<f32> = spectrum[..half]
+            .iter()
+            .map(|bin| bin.norm() / half as f32)
+            .collect();
+
+        if let Some(aux_id) = &self.config.spectrum_aux_id {
+            let bands = self.log_bands(&magnitudes, sample_rate);
+            let len = bands.len();
+            match (bands, len).try_into() {
+                Ok(bands) => outputs.push(AuxDeviceData {
+                    aux_id: AuxiliaryId::new_from(aux_id.clone()),
+                    data: AuxiliaryDataType::F32Vec(bands),
+                }),
+                Err(err) => warn!(
+                    "Could not create spectrum auxiliary ({:?}) (Audio Device {})",
+                    err,
+                    self.id.unprotect()
+                ),
+            }
+        }
+
+        let beat = self.detect_beat(&magnitudes, frame_data.seconds_elapsed);
+        if let Some(aux_id) = &self.config.beat_aux_id {
+            outputs.push(AuxDeviceData {
+                aux_id: AuxiliaryId::new_from(aux_id.clone()),
+                data: AuxiliaryDataType::F32(beat),
+            });
+        }
+
+        self.previous_magnitudes = magnitudes;
+
+        outputs
+    }
+
+    /// Averages FFT bins into logarithmically spaced bands, scaled to 0.0 - 1.0.
+    fn log_bands(&self, magnitudes: &[f32], sample_rate: u32) -> Vec<f32> {
+        let bands = self.config.bands.max(1);
+        let bin_width = sample_rate as f32 / self.config.fft_size as f32;
+        let min_frequency = self.config.min_frequency.max(bin_width);
+        let max_frequency = self
+            .config
+            .max_frequency
+            .min(sample_rate as f32 / 2.0)
+            .max(min_frequency);
+        let ratio = max_frequency / min_frequency;
+        let floor_db = self.config.spectrum_floor_db.min(-1.0);
+
+        (0..bands)
+            .map(|band| {
+                let low = min_frequency * ratio.powf(band as f32 / bands as f32);
+                let high = min_frequency * ratio.powf((band + 1) as f32 / bands as f32);
+                let first_bin = ((low / bin_width) as usize).min(magnitudes.len() - 1);
+                let last_bin = ((high / bin_width) as usize).clamp(first_bin + 1, magnitudes.len());
+
+                let bins = &magnitudes[first_bin..last_bin];
+                let magnitude = bins.iter().sum::<f32>() / bins.len() as f32;
+                let db = 20.0 * magnitude.max(f32::MIN_POSITIVE).log10();
+
+                ((db - floor_db) / -floor_db).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+
+    /// Spectral flux onset detection, returns a pulse that decays after each beat.
+    fn detect_beat(&mut self, magnitudes: &[f32], seconds_elapsed: f32) -> f32 {
+        let flux: f32 = magnitudes
+            .iter()
+            .zip(self.previous_magnitudes.iter())
+            .map(|(magnitude, previous)| (magnitude - previous).max(0.0))
+            .sum();
+
+        let average = if self.flux_history.is_empty() {
+            f32::MAX
+        } else {
+            self.flux_history.iter().sum::<f32>() / self.flux_history.len() as f32
+        };
+
+        if self.flux_history.len() == BEAT_HISTORY_LENGTH {
+            self.flux_history.pop_front();
+        }
+        self.flux_history.push_back(flux);
+
+        let since_last_beat = self
+            .last_beat
+            .map(|last_beat| seconds_elapsed - last_beat)
+            .filter(|since_last_beat| *since_last_beat >= 0.0);
+
+        let beat_allowed = !matches!(since_last_beat, Some(since_last_beat) if since_last_beat <= MIN_BEAT_INTERVAL);
+        if flux > average * self.config.beat_sensitivity && beat_allowed {
+            debug!(
+                "Beat detected at {}s (Audio Device {})",
+                seconds_elapsed,
+                self.id.unprotect()
+            );
+            self.last_beat = Some(seconds_elapsed);
+            return 1.0;
+        }
+
+        match since_last_beat {
+            Some(since_last_beat) if self.config.beat_decay > 0.0 => {
+                (1.0 - since_last_beat / self.config.beat_decay).max(0.0)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+fn start_capture(
+    id: &DeviceId,
+    command: &str,
+    args: &[String],
+    sample_rate: u32,
+    fft_size: usize,
+) -> Option<AudioInput> {
+    let mut child = match Command::new(command)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            error!(
+                "Could not start audio capture {} ({}) (Audio Device {})",
+                command,
+                err,
+                id.unprotect()
+            );
+            return None;
+        }
+    };
+
+    let mut stdout = child.stdout.take()?;
+    let history_length =
+        fft_size + (CAPTURE_HISTORY.as_secs_f64() * f64::from(sample_rate)) as usize;
+    let samples = Arc::new(Mutex::new(CapturedSamples {
+        samples: VecDeque::with_capacity(history_length),
+        newest_time: None,
+    }));
+    let capture_samples = Arc::clone(&samples);
+    let device_id = id.clone();
+    let command = command.to_string();
+
+    // Reads from the capture process block, so are done on their own thread
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        let mut leftover: Option<u8> = None;
+
+        loop {
+            let size = match stdout.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => size,
+                Err(err) => {
+                    error!(
+                        "Could not read from audio capture {} ({}) (Audio Device {})",
+                        command,
+                        err,
+                        device_id.unprotect()
+                    );
+                    break;
+                }
+            };
+
+            let mut bytes: Vec<u8> = leftover.take().into_iter().collect();
+            bytes.extend_from_slice(&buffer[..size]);
+            if bytes.len() % 2 == 1 {
+                leftover = bytes.pop();
+            }
+
+            let mut captured = capture_samples.lock();
+            for sample in bytes.chunks_exact(2) {
+                if captured.samples.len() == history_length {
+                    captured.samples.pop_front();
+                }
+                captured
+                    .samples
+                    .push_back(f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32768.0);
+            }
+            captured.newest_time.replace(Instant::now());
+        }
+
+        warn!(
+            "Audio capture {} has stopped (Audio Device {})",
+            command,
+            device_id.unprotect()
+        );
+        child.wait().ok();
+    });
+
+    Some(AudioInput::Capture {
+        samples,
+        sample_rate,
+    })
+}
+
+fn load_wav(id: &DeviceId, path: &str) -> Option<AudioInput> {
+    let mut reader = match hound::WavReader::open(path) {
+        Ok(reader) => reader,
+        Err(err) => {
+            error!(
+                "Could not open WAV file {} ({}) (Audio Device {})",
+                path,
+                err,
+                id.unprotect()
+            );
+            return None;
+        }
+    };
+
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().filter_map(Result::ok).collect(),
+        hound::SampleFormat::Int => {
+            let scale = 2_f32.powi(i32::from(spec.bits_per_sample) - 1);
+            reader
+                .samples::<i32>()
+                .filter_map(Result::ok)
+                .map(|sample| sample as f32 / scale)
+                .collect()
+        }
+    };
+
+    // Mix down to mono
+    let channels = usize::from(spec.channels.max(1));
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Some(AudioInput::File {
+        samples,
+        sample_rate: spec.sample_rate,
+    })
+}
+
+impl AuxiliaryDataDevice for AudioAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                // A frame still waiting for audio is dropped in favour of the newer one
+                self.pending_frame_data.replace(next_frame_data);
+            }
+            Err(err) => match err {
+                tokio::sync::broadcast::error::TryRecvError::Empty => {}
+                tokio::sync::broadcast::error::TryRecvError::Closed => {
+                    debug!(
+                        "Audio auxiliary {} had next frame data buffer closed",
+                        self.id.unprotect()
+                    );
+                    self.next_frame_data_buffer.take();
+                }
+                tokio::sync::broadcast::error::TryRecvError::Lagged(num) => warn!(
+                    "Audio auxiliary device {} lagged by {} frames",
+                    self.id.unprotect(),
+                    num
+                ),
+            },
+        }
+
+        // Live audio can't be analysed until it has been captured up to the frame's time
+        let frame_data = match self.pending_frame_data.take() {
+            Some(frame_data) if self.has_samples_for_frame(&frame_data) => frame_data,
+            pending_frame_data => {
+                self.pending_frame_data = pending_frame_data;
+                return;
+            }
+        };
+        let outputs = self.analyse_frame(&frame_data);
+        if let Some(data_output_buffer) = &self.data_output_buffer {
+            for output in outputs {
+                data_output_buffer.send(output).ok();
+            }
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        warn!(
+            "Audio devices send to the auxiliaries in their config, ignoring request to send to {} (Audio Device {})",
+            aux_id,
+            self.id.unprotect()
+        );
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+}
@@ -0,0 +1,249 @@
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::{
+    auxiliary_data::AuxiliaryDataType,
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+};
+
+use super::{AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Deserialize)]
+pub struct EnvelopeConfig {
+    // Auxiliary that opens the gate while its value is above the threshold
+    trigger: String,
+    #[serde(default = "default_threshold")]
+    threshold: f32,
+    // Seconds to rise to full level
+    #[serde(default)]
+    attack: f32,
+    // Seconds to fall from full level to the sustain level
+    #[serde(default)]
+    decay: f32,
+    // Level held while the gate is open, 0.0 - 1.0
+    #[serde(default = "default_sustain")]
+    sustain: f32,
+    // Seconds to fall to 0 after the gate closes
+    #[serde(default)]
+    release: f32,
+    #[serde(default)]
+    min: f32,
+    #[serde(default = "default_max")]
+    max: f32,
+}
+
+fn default_threshold() -> f32 {
+    0.5
+}
+
+fn default_sustain() -> f32 {
+    1.0
+}
+
+fn default_max() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EnvelopeStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+pub struct EnvelopeAuxiliaryDataDevice {
+    id: DeviceId,
+    aux_id: Option<AuxiliaryId>,
+    trigger: AuxiliaryId,
+    config: EnvelopeConfig,
+    gate: bool,
+    stage: EnvelopeStage,
+    // Seconds elapsed when the current stage started, and the level it started from
+    stage_start: f32,
+    stage_start_level: f32,
+    level: f32,
+    seconds_elapsed: f32,
+    next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
+    auxiliary_updates: Option<tokio::sync::broadcast::Receiver<AuxDeviceData>>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+}
+
+impl EnvelopeAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, aux_id: Option<AuxiliaryId>, config: EnvelopeConfig) -> Self {
+        EnvelopeAuxiliaryDataDevice {
+            id,
+            aux_id,
+            trigger: AuxiliaryId::new_from(config.trigger.clone()),
+            config,
+            gate: false,
+            stage: EnvelopeStage::Idle,
+            stage_start: 0.0,
+            stage_start_level: 0.0,
+            level: 0.0,
+            seconds_elapsed: 0.0,
+            next_frame_data_buffer: None,
+            auxiliary_updates: None,
+            data_output_buffer: None,
+        }
+    }
+
+    fn set_gate(&mut self, gate: bool) {
+        if gate == self.gate {
+            return;
+        }
+        self.gate = gate;
+
+        debug!(
+            "Envelope gate {} (Envelope Device {})",
+            if gate { "opened" } else { "closed" },
+            self.id.unprotect()
+        );
+
+        let stage = if gate {
+            EnvelopeStage::Attack
+        } else {
+            EnvelopeStage::Release
+        };
+        self.enter_stage(stage, self.seconds_elapsed, self.level);
+    }
+
+    fn enter_stage(&mut self, stage: EnvelopeStage, start: f32, start_level: f32) {
+        self.stage = stage;
+        self.stage_start = start;
+        self.stage_start_level = start_level;
+    }
+
+    /// Advances through any stages that have finished by `seconds_elapsed` and returns the level.
+    fn level_at(&mut self, seconds_elapsed: f32) -> f32 {
+        loop {
+            let elapsed = (seconds_elapsed - self.stage_start).max(0.0);
+            let (duration, end_level, next_stage) = match self.stage {
+                EnvelopeStage::Idle => return 0.0,
+                EnvelopeStage::Sustain => return self.config.sustain,
+                EnvelopeStage::Attack => (self.config.attack, 1.0, EnvelopeStage::Decay),
+                EnvelopeStage::Decay => (
+                    self.config.decay,
+                    self.config.sustain,
+                    EnvelopeStage::Sustain,
+                ),
+                EnvelopeStage::Release => (self.config.release, 0.0, EnvelopeStage::Idle),
+            };
+
+            if elapsed < duration {
+                let progress = elapsed / duration;
+                return self.stage_start_level + (end_level - self.stage_start_level) * progress;
+            }
+
+            let stage_end = self.stage_start + duration.max(0.0);
+            self.enter_stage(next_stage, stage_end, end_level);
+        }
+    }
+
+    fn receive_auxiliary_updates_since_last_tick(&mut self) {
+        let mut gates: Vec<bool> = vec![];
+
+        if let Some(auxiliary_updates) = self.auxiliary_updates.as_mut() {
+            loop {
+                match auxiliary_updates.try_recv() {
+                    Ok(update) => {
+                        if update.aux_id != self.trigger {
+                            continue;
+                        }
+                        match update.data.as_f32() {
+                            Some(value) => gates.push(value > self.config.threshold),
+                            None => warn!(
+                                "Envelope trigger {} is not a single value (Envelope Device {})",
+                                self.trigger,
+                                self.id.unprotect()
+                            ),
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::TryRecvError::Empty) => break,
+                    Err(tokio::sync::broadcast::error::TryRecvError::Closed) => {
+                        self.auxiliary_updates.take();
+                        break;
+                    }
+                    Err(tokio::sync::broadcast::error::TryRecvError::Lagged(num)) => warn!(
+                        "Envelope auxiliary device {} missed {} auxiliary updates",
+                        self.id.unprotect(),
+                        num
+                    ),
+                }
+            }
+        }
+
+        for gate in gates {
+            self.set_gate(gate);
+        }
+    }
+}
+
+impl AuxiliaryDataDevice for EnvelopeAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        self.receive_auxiliary_updates_since_last_tick();
+
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                self.seconds_elapsed = next_frame_data.seconds_elapsed;
+                self.level = self.level_at(self.seconds_elapsed);
+                let value = self.config.min + self.level * (self.config.max - self.config.min);
+
+                if let (Some(data_output_buffer), Some(aux_id)) =
+                    (&self.data_output_buffer, &self.aux_id)
+                {
+                    data_output_buffer
+                        .send(AuxDeviceData {
+                            aux_id: aux_id.clone(),
+                            data: AuxiliaryDataType::F32(value),
+                        })
+                        .ok();
+                }
+            }
+            Err(err) => match err {
+                tokio::sync::broadcast::error::TryRecvError::Empty => {}
+                tokio::sync::broadcast::error::TryRecvError::Closed => {
+                    debug!(
+                        "Envelope auxiliary {} had next frame data buffer closed",
+                        self.id.unprotect()
+                    );
+                    self.next_frame_data_buffer.take();
+                }
+                tokio::sync::broadcast::error::TryRecvError::Lagged(num) => warn!(
+                    "Envelope auxiliary device {} lagged by {} frames",
+                    self.id.unprotect(),
+                    num
+                ),
+            },
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        self.aux_id.replace(aux_id);
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+
+    fn receive_auxiliary_updates(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<AuxDeviceData>,
+    ) {
+        self.auxiliary_updates.replace(buffer);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    convert::TryInto,
+    fmt::{self, Display},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use crossbeam::channel::{bounded, Receiver, TryRecvError};
+use image::{
+    codecs::gif::GifDecoder, imageops::FilterType, AnimationDecoder, DynamicImage, ImageError,
+};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+
+use crate::{
+    auxiliary_data::AuxiliaryDataType,
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+};
+
+use super::{AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Deserialize)]
+pub struct ImageSourceConfig {
+    // An image, a GIF, or a glob pattern matching an image sequence (e.g. "content/intro/*.png")
+    path: String,
+    // Frames per second of an image sequence, GIFs use their own frame timings unless this is set
+    frame_rate: Option<f32>,
+    #[serde(rename = "loop", default = "default_loop")]
+    looping: bool,
+    // Include the alpha channel, giving 4 values per pixel rather than 3
+    #[serde(default)]
+    alpha: bool,
+    // Resize to this size, LEDs rarely need the full resolution of the content
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+fn default_loop() -> bool {
+    true
+}
+
+// Used for image sequences without a frame rate
+const DEFAULT_SEQUENCE_FRAME_RATE: f32 = 25.0;
+
+struct ImageFrame {
+    data: AuxiliaryDataType,
+    // Seconds from the start of the content that this frame stops being shown
+    end_time: f32,
+}
+
+#[derive(Debug)]
+enum ImageSourceError {
+    NoImagesFound(String),
+    InvalidPattern(glob::PatternError),
+    Io(std::io::Error),
+    Decode(ImageError),
+}
+
+impl Display for ImageSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageSourceError::NoImagesFound(path) => write!(f, "No images found at {}", path),
+            ImageSourceError::InvalidPattern(err) => write!(f, "Invalid pattern: {}", err),
+            ImageSourceError::Io(err) => write!(f, "{}", err),
+            ImageSourceError::Decode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageSourceError {
+    fn from(err: std::io::Error) -> Self {
+        ImageSourceError::Io(err)
+    }
+}
+
+impl From<ImageError> for ImageSourceError {
+    fn from(err: ImageError) -> Self {
+        ImageSourceError::Decode(err)
+    }
+}
+
+pub struct ImageAuxiliaryDataDevice {
+    id: DeviceId,
+    aux_id: Option<AuxiliaryId>,
+    looping: bool,
+    frames: Vec<ImageFrame>,
+    loading: Option<Receiver<Result<Vec<ImageFrame>, ImageSourceError>>>,
+    current_frame: Option<usize>,
+    next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+}
+
+impl ImageAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, aux_id: Option<AuxiliaryId>, config: ImageSourceConfig) -> Self {
+        let (sender, receiver) = bounded(1);
+        let looping = config.looping;
+
+        // Decoding long sequences can take a while, so is done without blocking the device thread
+        thread::spawn(move || {
+            sender.send(load_frames(&config)).ok();
+        });
+
+        ImageAuxiliaryDataDevice {
+            id,
+            aux_id,
+            looping,
+            frames: vec![],
+            loading: Some(receiver),
+            current_frame: None,
+            next_frame_data_buffer: None,
+            data_output_buffer: None,
+        }
+    }
+
+    fn receive_loaded_frames(&mut self) {
+        let loading = match &self.loading {
+            Some(loading) => loading,
+            None => return,
+        };
+
+        match loading.try_recv() {
+            Ok(Ok(frames)) => {
+                info!(
+                    "Loaded {} image frames (Image Device {})",
+                    frames.len(),
+                    self.id.unprotect()
+                );
+                self.frames = frames;
+                self.loading.take();
+            }
+            Ok(Err(err)) => {
+                error!(
+                    "Could not load images ({}) (Image Device {})",
+                    err,
+                    self.id.unprotect()
+                );
+                self.loading.take();
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.loading.take();
+            }
+        }
+    }
+
+    fn frame_index_at(&self, seconds_elapsed: f32) -> Option<usize> {
+        let duration = self.frames.last()?.end_time;
+
+        let time = if self.looping && duration > 0.0 {
+            seconds_elapsed.rem_euclid(duration)
+        } else {
+            seconds_elapsed
+        };
+
+        let index = self.frames.partition_point(|frame| frame.end_time <= time);
+        // Content that doesn't loop holds its last frame
+        Some(index.min(self.frames.len() - 1))
+    }
+}
+
+impl AuxiliaryDataDevice for ImageAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        self.receive_loaded_frames();
+
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                let index = match self.frame_index_at(next_frame_data.seconds_elapsed) {
+                    Some(index) => index,
+                    None => return,
+                };
+
+                // Images are large, so are only sent when the frame changes
+                if self.current_frame == Some(index) {
+                    return;
+                }
+
+                if let (Some(data_output_buffer), Some(aux_id)) =
+                    (&self.data_output_buffer, &self.aux_id)
+                {
+                    data_output_buffer
+                        .send(AuxDeviceData {
+                            aux_id: aux_id.clone(),
+                            data: self.frames[index].data.clone(),
+                        })
+                        .ok();
+                    self.current_frame = Some(index);
+                }
+            }
+            Err(err) => match err {
+                tokio::sync::broadcast::error::TryRecvError::Empty => {}
+                tokio::sync::broadcast::error::TryRecvError::Closed => {
+                    debug!(
+                        "Image auxiliary {} had next frame data buffer closed",
+                        self.id.unprotect()
+                    );
+                    self.next_frame_data_buffer.take();
+                }
+                tokio::sync::broadcast::error::TryRecvError::Lagged(num) => warn!(
+                    "Image auxiliary device {} lagged by {} frames",
+                    self.id.unprotect(),
+                    num
+                ),
+            },
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        self.aux_id.replace(aux_id);
+        self.current_frame = None;
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+}
+
+fn load_frames(config: &ImageSourceConfig) -> Result<Vec<ImageFrame>, ImageSourceError> {
+    let path = Path::new(&config.path);
+    let is_gif = path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("gif"))
+        .unwrap_or(false);
+
+    // Images paired with how long each is shown for
+    let images: Vec<(DynamicImage, f32)> = if is_gif && path.is_file() {
+        let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+        decoder
+            .into_frames()
+            .collect_frames()?
+            .into_iter()
+            .map(|frame| {
+                let (numerator, denominator) = frame.delay().numer_denom_ms();
+                let duration = match config.frame_rate {
+                    Some(frame_rate) => 1.0 / frame_rate,
+                    None => numerator as f32 / denominator.max(1) as f32 / 1000.0,
+                };
+                (DynamicImage::ImageRgba8(frame.into_buffer()), duration)
+            })
+            .collect()
+    } else if path.is_file() {
+        vec![(image::open(path)?, 0.0)]
+    } else {
+        let mut paths: Vec<PathBuf> = glob::glob(&config.path)
+            .map_err(ImageSourceError::InvalidPattern)?
+            .filter_map(Result::ok)
+            .collect();
+        paths.sort();
+
+        let duration = 1.0 / config.frame_rate.unwrap_or(DEFAULT_SEQUENCE_FRAME_RATE);
+        paths
+            .iter()
+            .map(|path| Ok((image::open(path)?, duration)))
+            .collect::<Result<_, ImageSourceError>>()?
+    };
+
+    if images.is_empty() {
+        return Err(ImageSourceError::NoImagesFound(config.path.clone()));
+    }
+
+    let mut end_time = 0.0;
+    Ok(images
+        .into_iter()
+        .map(|(image, duration)| {
+            end_time += duration;
+            ImageFrame {
+                data: image_to_aux_data(image, config),
+                end_time,
+            }
+        })
+        .collect())
+}
+
+/// Pixels are stored column by column from the top left, so the value of channel `c` of pixel (x, y) is at `(x * height + y) * channels + c`.
+fn image_to_aux_data(image: DynamicImage, config: &ImageSourceConfig) -> AuxiliaryDataType {
+    let image = match (config.width, config.height) {
+        (None, None) => image,
+        (width, height) => image.resize_exact(
+            width.unwrap_or_else(|| image.width()),
+            height.unwrap_or_else(|| image.height()),
+            FilterType::Triangle,
+        ),
+    };
+
+    let pixels = image.to_rgba32f();
+    let (width, height) = pixels.dimensions();
+    let channels: usize = if config.alpha { 4 } else { 3 };
+
+    let mut data: Vec<f32> = Vec::with_capacity(width as usize * height as usize * channels);
+    for x in 0..width {
+        for y in 0..height {
+            data.extend_from_slice(&pixels.get_pixel(x, y).0[..channels]);
+        }
+    }
+
+    AuxiliaryDataType::F32Vec3(
+        (data, width as usize, height as usize, channels)
+            .try_into()
+            .expect("Image data should conform to requirements"),
+    )
+}
@@ -0,0 +1,160 @@
+use std::f32::consts::TAU;
+
+use log::{debug, error, warn};
+use serde::Deserialize;
+
+use crate::{
+    auxiliary_data::AuxiliaryDataType,
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+};
+
+use super::{noise::white_noise, rate::GeneratorRate, AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum LFOShape {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    // A new random value at the start of every cycle
+    RandomHold,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct LFOConfig {
+    shape: LFOShape,
+    rate: GeneratorRate,
+    // Offset into the cycle, 0.0 - 1.0
+    phase: f32,
+    min: f32,
+    max: f32,
+    // Seed for RandomHold values
+    seed: u32,
+}
+
+impl Default for LFOConfig {
+    fn default() -> Self {
+        LFOConfig {
+            shape: LFOShape::Sine,
+            rate: GeneratorRate::default(),
+            phase: 0.0,
+            min: 0.0,
+            max: 1.0,
+            seed: 0,
+        }
+    }
+}
+
+pub struct LFOAuxiliaryDataDevice {
+    id: DeviceId,
+    aux_id: Option<AuxiliaryId>,
+    config: LFOConfig,
+    period: f32,
+    next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+}
+
+impl LFOAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, aux_id: Option<AuxiliaryId>, config: LFOConfig) -> Self {
+        let period = match config.rate.period() {
+            Some(period) => period,
+            None => {
+                error!(
+                    "LFO rate {:?} is invalid, using 1Hz (LFO Device {})",
+                    config.rate,
+                    id.unprotect()
+                );
+                1.0
+            }
+        };
+
+        LFOAuxiliaryDataDevice {
+            id,
+            aux_id,
+            config,
+            period,
+            next_frame_data_buffer: None,
+            data_output_buffer: None,
+        }
+    }
+
+    fn get_value_for_frame(&self, frame_data: &FrameData) -> f32 {
+        let cycles = frame_data.precise_seconds_elapsed() / f64::from(self.period)
+            + f64::from(self.config.phase);
+        let cycle = cycles.floor();
+        let position = (cycles - cycle) as f32;
+
+        let value = match self.config.shape {
+            LFOShape::Sine => 0.5 - 0.5 * (position * TAU).cos(),
+            LFOShape::Triangle => 1.0 - (2.0 * position - 1.0).abs(),
+            LFOShape::Saw => position,
+            LFOShape::Square => {
+                if position < 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            LFOShape::RandomHold => white_noise(self.config.seed, cycle as i64 as u64, 0),
+        };
+
+        self.config.min + value * (self.config.max - self.config.min)
+    }
+}
+
+impl AuxiliaryDataDevice for LFOAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                let value = self.get_value_for_frame(&next_frame_data);
+                if let (Some(data_output_buffer), Some(aux_id)) =
+                    (&self.data_output_buffer, &self.aux_id)
+                {
+                    data_output_buffer
+                        .send(AuxDeviceData {
+                            aux_id: aux_id.clone(),
+                            data: AuxiliaryDataType::F32(value),
+                        })
+                        .ok();
+                }
+            }
+            Err(err) => match err {
+                tokio::sync::broadcast::error::TryRecvError::Empty => {}
+                tokio::sync::broadcast::error::TryRecvError::Closed => {
+                    debug!(
+                        "LFO auxiliary {} had next frame data buffer closed",
+                        self.id.unprotect()
+                    );
+                    self.next_frame_data_buffer.take();
+                }
+                tokio::sync::broadcast::error::TryRecvError::Lagged(num) => warn!(
+                    "LFO auxiliary device {} lagged by {} frames",
+                    self.id.unprotect(),
+                    num
+                ),
+            },
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        self.aux_id.replace(aux_id);
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+}
@@ -0,0 +1,533 @@
+use std::{collections::VecDeque, fs::File, io::Read, thread, time::Instant};
+
+use crossbeam::channel::{unbounded, Receiver, Sender, TryRecvError};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auxiliary_data::AuxiliaryDataType,
+    cue::CueEvent,
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+    tempo::TempoEvent,
+};
+
+use super::{AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Deserialize)]
+pub struct MIDIReceiverConfig {
+    // Raw MIDI device, e.g. /dev/snd/midiC1D0
+    device: String,
+    #[serde(default)]
+    mappings: Vec<MIDIMapping>,
+    // Auxiliaries to map to the next unmapped control that is moved
+    #[serde(default)]
+    learn: Vec<String>,
+    // Follow MIDI clock, start, continue and stop messages for the tempo
+    #[serde(default)]
+    clock: bool,
+    // Control that taps the tempo, e.g. a note
+    tap: Option<MIDISource>,
+    // Control that fires the next cue of every cue list waiting for a GO
+    go: Option<MIDISource>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct MIDIMapping {
+    source: MIDISource,
+    aux_id: String,
+    #[serde(default = "default_mapping_min")]
+    min: f32,
+    #[serde(default = "default_mapping_max")]
+    max: f32,
+}
+
+/// Channels are numbered 1 - 16, if no channel is given messages on any channel are used.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum MIDISource {
+    ControlChange { channel: Option<u8>, controller: u8 },
+    // Velocity of the note while held, 0 when released
+    NoteVelocity { channel: Option<u8>, note: u8 },
+    // 1 while the note is held, 0 when released
+    NoteGate { channel: Option<u8>, note: u8 },
+    // Centred at the midpoint of the mapping's range
+    PitchBend { channel: Option<u8> },
+}
+
+fn default_mapping_min() -> f32 {
+    0.0
+}
+
+fn default_mapping_max() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MIDIMessage {
+    NoteOff {
+        channel: u8,
+        note: u8,
+    },
+    NoteOn {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: u8,
+    },
+    PitchBend {
+        channel: u8,
+        value: u16,
+    },
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+}
+
+impl MIDIMessage {
+    fn channel(&self) -> Option<u8> {
+        match self {
+            MIDIMessage::NoteOff { channel, .. }
+            | MIDIMessage::NoteOn { channel, .. }
+            | MIDIMessage::ControlChange { channel, .. }
+            | MIDIMessage::PitchBend { channel, .. } => Some(*channel),
+            // Real-time messages aren't sent on a channel
+            MIDIMessage::TimingClock
+            | MIDIMessage::Start
+            | MIDIMessage::Continue
+            | MIDIMessage::Stop => None,
+        }
+    }
+
+    /// The source a learning mapping should use for this message.
+    fn learn_source(&self) -> Option<MIDISource> {
+        let channel = Some(self.channel()? + 1);
+        match *self {
+            MIDIMessage::NoteOff { .. } => None,
+            MIDIMessage::NoteOn { note, velocity, .. } if velocity > 0 => {
+                Some(MIDISource::NoteVelocity { channel, note })
+            }
+            MIDIMessage::NoteOn { .. } => None,
+            MIDIMessage::ControlChange { controller, .. } => Some(MIDISource::ControlChange {
+                channel,
+                controller,
+            }),
+            MIDIMessage::PitchBend { .. } => Some(MIDISource::PitchBend { channel }),
+            _ => None,
+        }
+    }
+}
+
+impl MIDISource {
+    fn matches_channel(channel: Option<u8>, message: &MIDIMessage) -> bool {
+        match channel {
+            Some(channel) => Some(channel) == message.channel().map(|channel| channel + 1),
+            None => true,
+        }
+    }
+
+    /// Value of the message between 0.0 and 1.0, if this source uses it.
+    fn normalised_value(&self, message: &MIDIMessage) -> Option<f32> {
+        match (self, *message) {
+            (
+                MIDISource::ControlChange {
+                    channel,
+                    controller,
+                },
+                MIDIMessage::ControlChange {
+                    controller: message_controller,
+                    value,
+                    ..
+                },
+            ) if *controller == message_controller
+                && MIDISource::matches_channel(*channel, message) =>
+            {
+                Some(f32::from(value) / 127.0)
+            }
+            (
+                MIDISource::NoteVelocity { channel, note } | MIDISource::NoteGate { channel, note },
+                MIDIMessage::NoteOn {
+                    note: message_note,
+                    velocity,
+                    ..
+                },
+            ) if *note == message_note && MIDISource::matches_channel(*channel, message) => {
+                match self {
+                    MIDISource::NoteGate { .. } => Some(f32::from(u8::from(velocity > 0))),
+                    _ => Some(f32::from(velocity) / 127.0),
+                }
+            }
+            (
+                MIDISource::NoteVelocity { channel, note } | MIDISource::NoteGate { channel, note },
+                MIDIMessage::NoteOff {
+                    note: message_note, ..
+                },
+            ) if *note == message_note && MIDISource::matches_channel(*channel, message) => {
+                Some(0.0)
+            }
+            (MIDISource::PitchBend { channel }, MIDIMessage::PitchBend { value, .. })
+                if MIDISource::matches_channel(*channel, message) =>
+            {
+                Some(f32::from(value) / 16383.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses a raw MIDI byte stream, handling running status and interleaved real-time messages.
+#[derive(Default)]
+struct MIDIParser {
+    running_status: Option<u8>,
+    data: Vec<u8>,
+    in_sysex: bool,
+}
+
+impl MIDIParser {
+    fn push(&mut self, byte: u8) -> Option<MIDIMessage> {
+        if byte >= 0xF8 {
+            // Real-time messages may appear anywhere and don't affect running status
+            return match byte {
+                0xF8 => Some(MIDIMessage::TimingClock),
+                0xFA => Some(MIDIMessage::Start),
+                0xFB => Some(MIDIMessage::Continue),
+                0xFC => Some(MIDIMessage::Stop),
+                _ => None,
+            };
+        }
+
+        if byte & 0x80 != 0 {
+            self.data.clear();
+            match byte {
+                0xF0 => {
+                    self.in_sysex = true;
+                    self.running_status = None;
+                }
+                0xF7 => self.in_sysex = false,
+                0xF1..=0xF6 => {
+                    // System common messages cancel running status, their data is skipped
+                    self.in_sysex = false;
+                    self.running_status = None;
+                }
+                _ => {
+                    self.in_sysex = false;
+                    self.running_status = Some(byte);
+                }
+            }
+            return None;
+        }
+
+        if self.in_sysex {
+            return None;
+        }
+
+        let status = self.running_status?;
+        self.data.push(byte);
+
+        let data_length = match status & 0xF0 {
+            0xC0 | 0xD0 => 1,
+            _ => 2,
+        };
+        if self.data.len() < data_length {
+            return None;
+        }
+
+        let channel = status & 0x0F;
+        let message = match (status & 0xF0, self.data.as_slice()) {
+            (0x80, [note, _]) => Some(MIDIMessage::NoteOff {
+                channel,
+                note: *note,
+            }),
+            (0x90, [note, 0]) => Some(MIDIMessage::NoteOff {
+                channel,
+                note: *note,
+            }),
+            (0x90, [note, velocity]) => Some(MIDIMessage::NoteOn {
+                channel,
+                note: *note,
+                velocity: *velocity,
+            }),
+            (0xB0, [controller, value]) => Some(MIDIMessage::ControlChange {
+                channel,
+                controller: *controller,
+                value: *value,
+            }),
+            (0xE0, [lsb, msb]) => Some(MIDIMessage::PitchBend {
+                channel,
+                value: (u16::from(*msb) << 7) | u16::from(*lsb),
+            }),
+            _ => None, // Aftertouch and program changes aren't mapped
+        };
+        self.data.clear();
+
+        message
+    }
+}
+
+pub struct MIDIAuxiliaryDataDevice {
+    id: DeviceId,
+    mappings: Vec<MIDIMapping>,
+    learning: VecDeque<AuxiliaryId>,
+    parser: MIDIParser,
+    // Bytes and the time they were read
+    bytes: Option<Receiver<(Instant, Vec<u8>)>>,
+    follow_clock: bool,
+    tap: Option<MIDISource>,
+    go: Option<MIDISource>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+    tempo_output_buffer: Option<Sender<TempoEvent>>,
+    cue_output_buffer: Option<Sender<CueEvent>>,
+}
+
+impl MIDIAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, config: MIDIReceiverConfig) -> Self {
+        let bytes = match File::open(&config.device) {
+            Ok(device) => {
+                let (sender, receiver) = unbounded();
+                let device_id = id.clone();
+                let device_path = config.device.clone();
+
+                // Reads from a raw MIDI device block, so are done on their own thread
+                thread::spawn(move || {
+                    let mut device = device;
+                    let mut buffer = [0u8; 256];
+                    loop {
+                        match device.read(&mut buffer) {
+                            Ok(0) => break,
+                            Ok(size) => {
+                                if sender
+                                    .send((Instant::now(), buffer[..size].to_vec()))
+                                    .is_err()
+                                {
+                                    break;
+                                }
+                            }
+                            Err(err) => {
+                                error!(
+                                    "Could not read from MIDI device {} ({}) (MIDI Device {})",
+                                    device_path,
+                                    err,
+                                    device_id.unprotect()
+                                );
+                                break;
+                            }
+                        }
+                    }
+                });
+
+                Some(receiver)
+            }
+            Err(err) => {
+                error!(
+                    "Could not open MIDI device {} ({}) (MIDI Device {})",
+                    config.device,
+                    err,
+                    id.unprotect()
+                );
+                None
+            }
+        };
+
+        MIDIAuxiliaryDataDevice {
+            id,
+            mappings: config.mappings,
+            learning: config
+                .learn
+                .into_iter()
+                .map(AuxiliaryId::new_from)
+                .collect(),
+            parser: MIDIParser::default(),
+            bytes,
+            follow_clock: config.clock,
+            tap: config.tap,
+            go: config.go,
+            data_output_buffer: None,
+            tempo_output_buffer: None,
+            cue_output_buffer: None,
+        }
+    }
+
+    fn handle_message(&mut self, message: MIDIMessage, time: Instant) {
+        if let Some(event) = self.tempo_event(&message, time) {
+            if let Some(tempo_output_buffer) = &self.tempo_output_buffer {
+                tempo_output_buffer.send(event).ok();
+            }
+        }
+
+        // Releasing the GO note or control doesn't fire a cue
+        let go_value = self
+            .go
+            .as_ref()
+            .and_then(|go| go.normalised_value(&message));
+        if matches!(go_value, Some(go_value) if go_value > 0.0) {
+            if let Some(cue_output_buffer) = &self.cue_output_buffer {
+                cue_output_buffer.send(CueEvent::Go(None)).ok();
+            }
+        }
+
+        if message.channel().is_none() {
+            // Real-time messages are only used for the tempo, and are too frequent to log
+            return;
+        }
+
+        debug!(
+            "Received MIDI message {:?} (MIDI Device {})",
+            message,
+            self.id.unprotect()
+        );
+
+        let mut mapped = false;
+        for mapping in self.mappings.iter() {
+            mapped |= self.send_mapped_value(mapping, &message);
+        }
+
+        // The tap and GO sources already have a use, so aren't learned as mappings
+        let is_tap_or_go = [&self.tap, &self.go].iter().any(
+            |source| matches!(source, Some(source) if source.normalised_value(&message).is_some()),
+        );
+        if !mapped && !is_tap_or_go && !self.learning.is_empty() {
+            if let Some(source) = message.learn_source() {
+                if self.learn(source) {
+                    if let Some(mapping) = self.mappings.last() {
+                        self.send_mapped_value(mapping, &message);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends the value of `message` to the mapping's auxiliary, if the mapping's source sent it.
+    fn send_mapped_value(&self, mapping: &MIDIMapping, message: &MIDIMessage) -> bool {
+        let value = match mapping.source.normalised_value(message) {
+            Some(value) => value,
+            None => return false,
+        };
+
+        let value = mapping.min + value * (mapping.max - mapping.min);
+        if let Some(data_output_buffer) = &self.data_output_buffer {
+            data_output_buffer
+                .send(AuxDeviceData {
+                    aux_id: AuxiliaryId::new_from(mapping.aux_id.clone()),
+                    data: AuxiliaryDataType::F32(value),
+                })
+                .ok();
+        }
+        true
+    }
+
+    fn tempo_event(&self, message: &MIDIMessage, time: Instant) -> Option<TempoEvent> {
+        if self.follow_clock {
+            match message {
+                MIDIMessage::TimingClock => return Some(TempoEvent::ClockPulse(time)),
+                MIDIMessage::Start => return Some(TempoEvent::Start(time)),
+                MIDIMessage::Continue => return Some(TempoEvent::Continue(time)),
+                MIDIMessage::Stop => return Some(TempoEvent::Stop(time)),
+                _ => {}
+            }
+        }
+
+        // Releasing a tap note or control isn't a tap
+        let tap_value = self.tap.as_ref()?.normalised_value(message)?;
+        if tap_value > 0.0 {
+            Some(TempoEvent::Tap(time))
+        } else {
+            None
+        }
+    }
+
+    /// Maps `source` to the next auxiliary waiting to be learned, returns whether there was one.
+    fn learn(&mut self, source: MIDISource) -> bool {
+        let aux_id = match self.learning.pop_front() {
+            Some(aux_id) => aux_id,
+            None => return false,
+        };
+
+        let mapping = MIDIMapping {
+            source,
+            aux_id: aux_id.unprotect(),
+            min: default_mapping_min(),
+            max: default_mapping_max(),
+        };
+
+        info!(
+            "Learned MIDI mapping for auxiliary {}, add this to the device's mappings to keep it: {} (MIDI Device {})",
+            aux_id,
+            serde_json::to_string(&mapping).unwrap_or_default(),
+            self.id.unprotect()
+        );
+
+        self.mappings.push(mapping);
+        true
+    }
+}
+
+impl AuxiliaryDataDevice for MIDIAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        let mut messages: Vec<(Instant, MIDIMessage)> = vec![];
+        let mut disconnected = false;
+
+        if let Some(bytes) = &self.bytes {
+            loop {
+                match bytes.try_recv() {
+                    Ok((time, bytes)) => {
+                        for byte in bytes {
+                            if let Some(message) = self.parser.push(byte) {
+                                messages.push((time, message));
+                            }
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        disconnected = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if disconnected {
+            warn!(
+                "MIDI device has disconnected (MIDI Device {})",
+                self.id.unprotect()
+            );
+            self.bytes.take();
+        }
+
+        for (time, message) in messages {
+            self.handle_message(message, time);
+        }
+    }
+
+    /// Maps the next unmapped control that is moved to `aux_id`.
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        info!(
+            "Waiting for a MIDI control to map to auxiliary {} (MIDI Device {})",
+            aux_id,
+            self.id.unprotect()
+        );
+        self.learning.push_back(aux_id);
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        _buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        // MIDI messages are forwarded as they arrive, frame data isn't needed
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+
+    fn send_tempo_into(&mut self, sender: Sender<TempoEvent>) {
+        self.tempo_output_buffer.replace(sender);
+    }
+
+    fn send_cues_into(&mut self, sender: Sender<CueEvent>) {
+        self.cue_output_buffer.replace(sender);
+    }
+}
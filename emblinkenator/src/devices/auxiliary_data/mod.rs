@@ -1,4 +1,14 @@
+pub mod audio;
+pub mod envelope;
+pub mod image;
+pub mod lfo;
+pub mod midi;
+pub mod mqtt;
 pub mod noise;
+pub mod osc;
+pub mod playback;
+pub mod rate;
+pub mod sequencer;
 
 use std::{
     sync::{
@@ -11,9 +21,17 @@ use std::{
 use enum_dispatch::enum_dispatch;
 use parking_lot::RwLock;
 
-use crate::{auxiliary_data::AuxiliaryDataType, frame::FrameData, id::AuxiliaryId};
+use crate::{
+    auxiliary_data::AuxiliaryDataType, cue::CueEvent, frame::FrameData, id::AuxiliaryId,
+    tempo::TempoEvent,
+};
 
-use self::noise::NoiseAuxiliaryDataDevice;
+use self::{
+    audio::AudioAuxiliaryDataDevice, envelope::EnvelopeAuxiliaryDataDevice,
+    image::ImageAuxiliaryDataDevice, lfo::LFOAuxiliaryDataDevice, midi::MIDIAuxiliaryDataDevice,
+    mqtt::MQTTAuxiliaryDataDevice, noise::NoiseAuxiliaryDataDevice, osc::OSCAuxiliaryDataDevice,
+    playback::PlaybackAuxiliaryDataDevice, sequencer::SequencerAuxiliaryDataDevice,
+};
 
 #[derive(Debug, Clone)]
 pub struct AuxDeviceData {
@@ -30,11 +48,30 @@ pub trait AuxiliaryDataDevice: Send + Sync {
         buffer: tokio::sync::broadcast::Receiver<FrameData>,
     );
     fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>);
+    /// Every value applied to an auxiliary, for devices that react to other auxiliaries.
+    fn receive_auxiliary_updates(
+        &mut self,
+        _buffer: tokio::sync::broadcast::Receiver<AuxDeviceData>,
+    ) {
+    }
+    /// Where tempo events are sent, for devices that are a tempo source.
+    fn send_tempo_into(&mut self, _sender: crossbeam::channel::Sender<TempoEvent>) {}
+    /// Where cue events are sent, for devices that trigger cues.
+    fn send_cues_into(&mut self, _sender: crossbeam::channel::Sender<CueEvent>) {}
 }
 
 #[enum_dispatch(AuxiliaryDataDevice)]
 pub enum AuxiliaryDataDeviceType {
     Noise(NoiseAuxiliaryDataDevice),
+    Osc(OSCAuxiliaryDataDevice),
+    Midi(MIDIAuxiliaryDataDevice),
+    Audio(AudioAuxiliaryDataDevice),
+    Mqtt(MQTTAuxiliaryDataDevice),
+    Lfo(LFOAuxiliaryDataDevice),
+    Envelope(EnvelopeAuxiliaryDataDevice),
+    Sequencer(SequencerAuxiliaryDataDevice),
+    Image(ImageAuxiliaryDataDevice),
+    Playback(PlaybackAuxiliaryDataDevice),
 }
 
 pub struct ThreadedAuxiliaryDeviceWrapper {
@@ -91,4 +128,33 @@ impl ThreadedAuxiliaryDeviceWrapper {
     pub fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
         self.device.write().send_into_buffer(buffer)
     }
+
+    pub fn receive_auxiliary_updates(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<AuxDeviceData>,
+    ) {
+        self.device.write().receive_auxiliary_updates(buffer)
+    }
+
+    pub fn send_tempo_into(&mut self, sender: crossbeam::channel::Sender<TempoEvent>) {
+        self.device.write().send_tempo_into(sender)
+    }
+
+    pub fn send_cues_into(&mut self, sender: crossbeam::channel::Sender<CueEvent>) {
+        self.device.write().send_cues_into(sender)
+    }
+}
+
+/// Reads bytes as packed little endian f32s, `None` if they aren't a whole number of values.
+pub fn packed_f32s_from_bytes(bytes: &[u8]) -> Option<Vec<f32>> {
+    let values = bytes.chunks_exact(4);
+    if !values.remainder().is_empty() {
+        return None;
+    }
+
+    Some(
+        values
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect(),
+    )
 }
@@ -0,0 +1,295 @@
+use std::{
+    thread::{self, sleep},
+    time::Duration,
+};
+
+use crossbeam::channel::{unbounded, Receiver, TryRecvError};
+use log::{debug, error, info, warn};
+use rumqttc::{matches, Client, Event, MqttOptions, Packet, QoS};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    auxiliary_data::{AuxiliaryDataType, AuxiliaryDataTypeConsumer},
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+};
+
+use super::{packed_f32s_from_bytes, AuxDeviceData, AuxiliaryDataDevice};
+
+// Time to wait before reconnecting after the broker connection fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Deserialize)]
+pub struct MQTTReceiverConfig {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+    subscriptions: Vec<MQTTSubscription>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct MQTTSubscription {
+    // Topic filter, may contain + and # wildcards
+    topic: String,
+    aux_id: String,
+    #[serde(default = "default_payload")]
+    payload: MQTTPayload,
+    #[serde(rename = "type", default = "default_subscription_type")]
+    aux_type: AuxiliaryDataTypeConsumer,
+}
+
+#[derive(Clone, Deserialize)]
+pub enum MQTTPayload {
+    // UTF-8 text of a number, a list of numbers separated by commas or whitespace, or true / false
+    Raw,
+    // Value at a path in a JSON document, e.g. "sensors.temperature" or "readings[2]"
+    Json { path: String },
+    // Packed little endian f32s
+    PackedF32,
+}
+
+fn default_payload() -> MQTTPayload {
+    MQTTPayload::Raw
+}
+
+fn default_subscription_type() -> AuxiliaryDataTypeConsumer {
+    AuxiliaryDataTypeConsumer::F32
+}
+
+struct MQTTMessage {
+    topic: String,
+    payload: Vec<u8>,
+}
+
+pub struct MQTTAuxiliaryDataDevice {
+    id: DeviceId,
+    subscriptions: Vec<MQTTSubscription>,
+    messages: Option<Receiver<MQTTMessage>>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+}
+
+impl MQTTAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, config: MQTTReceiverConfig) -> Self {
+        let mut mqttoptions = MqttOptions::new(id.unprotect(), config.host, config.port);
+
+        if let Some(credentials) = config.credentials {
+            mqttoptions.set_credentials(credentials.0, credentials.1);
+        }
+        mqttoptions.set_keep_alive(Duration::from_secs(10));
+
+        let (mut client, mut connection) = Client::new(mqttoptions, 10);
+        let (sender, receiver) = unbounded();
+        let device_id = id.clone();
+        let topics: Vec<String> = config
+            .subscriptions
+            .iter()
+            .map(|subscription| subscription.topic.clone())
+            .collect();
+
+        thread::spawn(move || {
+            for event in connection.iter() {
+                match event {
+                    // Subscriptions don't survive a reconnect with a clean session, so are made on every connection
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!(
+                            "Connected to MQTT broker (MQTT Device {})",
+                            device_id.unprotect()
+                        );
+                        for topic in topics.iter() {
+                            if let Err(err) = client.try_subscribe(topic.as_str(), QoS::AtMostOnce)
+                            {
+                                error!(
+                                    "Could not subscribe to MQTT topic {} ({}) (MQTT Device {})",
+                                    topic,
+                                    err,
+                                    device_id.unprotect()
+                                );
+                            }
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        let message = MQTTMessage {
+                            topic: publish.topic,
+                            payload: publish.payload.to_vec(),
+                        };
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!(
+                            "MQTT connection failed, reconnecting ({}) (MQTT Device {})",
+                            err,
+                            device_id.unprotect()
+                        );
+                        sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+
+        MQTTAuxiliaryDataDevice {
+            id,
+            subscriptions: config.subscriptions,
+            messages: Some(receiver),
+            data_output_buffer: None,
+        }
+    }
+
+    fn handle_message(&self, message: MQTTMessage) {
+        for subscription in self.subscriptions.iter() {
+            if !matches(&message.topic, &subscription.topic) {
+                continue;
+            }
+
+            match payload_to_aux_data(
+                &message.payload,
+                &subscription.payload,
+                &subscription.aux_type,
+            ) {
+                Some(data) => {
+                    if let Some(data_output_buffer) = &self.data_output_buffer {
+                        data_output_buffer
+                            .send(AuxDeviceData {
+                                aux_id: AuxiliaryId::new_from(subscription.aux_id.clone()),
+                                data,
+                            })
+                            .ok();
+                    }
+                }
+                None => warn!(
+                    "Cannot convert payload of MQTT message on {} to {} (MQTT Device {})",
+                    message.topic,
+                    subscription.aux_type,
+                    self.id.unprotect()
+                ),
+            }
+        }
+    }
+}
+
+impl AuxiliaryDataDevice for MQTTAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        let mut messages: Vec<MQTTMessage> = vec![];
+        let mut disconnected = false;
+
+        if let Some(receiver) = &self.messages {
+            loop {
+                match receiver.try_recv() {
+                    Ok(message) => messages.push(message),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        disconnected = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if disconnected {
+            warn!(
+                "MQTT connection has closed (MQTT Device {})",
+                self.id.unprotect()
+            );
+            self.messages.take();
+        }
+
+        for message in messages {
+            debug!(
+                "Received MQTT message on {} (MQTT Device {})",
+                message.topic,
+                self.id.unprotect()
+            );
+            self.handle_message(message);
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        warn!(
+            "MQTT devices route messages using their subscriptions, ignoring request to send to {} (MQTT Device {})",
+            aux_id,
+            self.id.unprotect()
+        );
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        _buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        // MQTT messages are forwarded as they arrive, frame data isn't needed
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+}
+
+fn payload_to_aux_data(
+    payload: &[u8],
+    format: &MQTTPayload,
+    aux_type: &AuxiliaryDataTypeConsumer,
+) -> Option<AuxiliaryDataType> {
+    let values = match format {
+        MQTTPayload::Raw => raw_payload_to_f32s(payload)?,
+        MQTTPayload::Json { path } => {
+            let document: Value = serde_json::from_slice(payload).ok()?;
+            json_value_to_f32s(json_path(&document, path)?)?
+        }
+        MQTTPayload::PackedF32 => packed_f32s_from_bytes(payload)?,
+    };
+
+    aux_type.value_from_f32s(values)
+}
+
+fn raw_payload_to_f32s(payload: &[u8]) -> Option<Vec<f32>> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+
+    match text.to_lowercase().as_str() {
+        "true" | "on" => return Some(vec![1.0]),
+        "false" | "off" => return Some(vec![0.0]),
+        _ => {}
+    }
+
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.parse::<f32>().ok())
+        .collect()
+}
+
+/// Follows a path of object keys separated by `.` and array indices in `[]`, an empty path is the whole document.
+fn json_path<'a>(document: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut value = document;
+
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        let (key, indices) = match segment.find('[') {
+            Some(start) => segment.split_at(start),
+            None => (segment, ""),
+        };
+
+        if !key.is_empty() {
+            value = value.get(key)?;
+        }
+
+        for index in indices.split_terminator(']') {
+            let index: usize = index.strip_prefix('[')?.parse().ok()?;
+            value = value.get(index)?;
+        }
+    }
+
+    Some(value)
+}
+
+fn json_value_to_f32s(value: &Value) -> Option<Vec<f32>> {
+    match value {
+        Value::Number(number) => Some(vec![number.as_f64()? as f32]),
+        Value::Bool(value) => Some(vec![f32::from(u8::from(*value))]),
+        Value::String(text) => raw_payload_to_f32s(text.as_bytes()),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_f64().map(|value| value as f32))
+            .collect(),
+        _ => None,
+    }
+}
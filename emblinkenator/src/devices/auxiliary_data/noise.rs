@@ -1,7 +1,8 @@
 use std::{convert::TryInto, time::Instant};
 
-use log::{debug, warn};
-use rand::Rng;
+use log::{debug, error, info, warn};
+use noise::{core::worley::ReturnType, NoiseFn, Perlin, Simplex, Value, Worley};
+use serde::Deserialize;
 
 use crate::{
     auxiliary_data::AuxiliaryDataType,
@@ -11,78 +12,237 @@ use crate::{
 
 use super::{AuxDeviceData, AuxiliaryDataDevice};
 
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum NoiseAlgorithm {
+    White,
+    Value,
+    Perlin,
+    Simplex,
+    Worley,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct NoiseConfig {
+    algorithm: NoiseAlgorithm,
+    // A random seed is chosen (and logged) if none is given
+    seed: Option<u32>,
+    // Size of each dimension of the grid, no dimensions gives a single F32
+    dimensions: Vec<usize>,
+    // Samples per second, values are held between samples
+    rate: f32,
+    // Distance in noise space between neighbouring grid points
+    scale: f64,
+    // Distance in noise space moved along the time axis per second
+    evolution: f64,
+    octaves: usize,
+    // Amplitude multiplier between octaves
+    persistence: f64,
+    // Frequency multiplier between octaves
+    lacunarity: f64,
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        NoiseConfig {
+            algorithm: NoiseAlgorithm::White,
+            seed: None,
+            dimensions: vec![10, 10, 10],
+            rate: 1.0,
+            scale: 0.1,
+            evolution: 0.25,
+            octaves: 1,
+            persistence: 0.5,
+            lacunarity: 2.0,
+        }
+    }
+}
+
 pub struct NoiseAuxiliaryDataDevice {
     id: DeviceId,
     aux_id: Option<AuxiliaryId>,
+    config: NoiseConfig,
+    seed: u32,
     next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
     data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
-    prev_time_point: Option<u32>,
+    prev_sample: Option<u64>,
     prev_values: Option<AuxiliaryDataType>,
 }
 
 impl NoiseAuxiliaryDataDevice {
-    pub fn new(id: DeviceId, aux_id: AuxiliaryId) -> Self {
+    pub fn new(id: DeviceId, aux_id: Option<AuxiliaryId>, config: NoiseConfig) -> Self {
+        let mut config = config;
+
+        if config.dimensions.len() > 3 || config.dimensions.contains(&0) {
+            error!(
+                "Noise dimensions {:?} are invalid, up to 3 non-zero sizes are supported (Noise Device {})",
+                config.dimensions,
+                id.unprotect()
+            );
+            config.dimensions = NoiseConfig::default().dimensions;
+        }
+
+        if config.rate <= 0.0 {
+            error!(
+                "Noise rate must be greater than 0, got {} (Noise Device {})",
+                config.rate,
+                id.unprotect()
+            );
+            config.rate = NoiseConfig::default().rate;
+        }
+
+        let seed = match config.seed {
+            Some(seed) => seed,
+            None => {
+                let seed = rand::random();
+                info!(
+                    "Using noise seed {}, set it in the device config to repeat this output (Noise Device {})",
+                    seed,
+                    id.unprotect()
+                );
+                seed
+            }
+        };
+
         NoiseAuxiliaryDataDevice {
             id,
-            aux_id: Some(aux_id),
+            aux_id,
+            config,
+            seed,
             next_frame_data_buffer: None,
             data_output_buffer: None,
-            prev_time_point: None,
+            prev_sample: None,
             prev_values: None,
         }
     }
 
     fn get_noise_for_frame(&mut self, frame_data: FrameData) -> AuxiliaryDataType {
-        // TODO: Allow rate to change (samples per second)
-        let time_point = frame_data.whole_seconds_elapsed;
-        if let Some(prev_time_point) = self.prev_time_point {
-            if time_point == prev_time_point {
+        let sample = (frame_data.precise_seconds_elapsed() * f64::from(self.config.rate)) as u64;
+        if let Some(prev_sample) = self.prev_sample {
+            if sample == prev_sample {
                 if let Some(previous_values) = &self.prev_values {
                     return previous_values.clone();
                 }
             }
         }
 
-        let mut rng = rand::thread_rng();
-
-        let size_x: u32 = 10;
-        let size_y: u32 = 10;
-        let size_z: u32 = 10;
-
-        let mut res: Vec<f32> =
-            Vec::with_capacity(size_x as usize * size_y as usize * size_z as usize);
+        let sizes: Vec<usize> = (0..3)
+            .map(|dimension| *self.config.dimensions.get(dimension).unwrap_or(&1))
+            .collect();
+        let time = sample as f64 / f64::from(self.config.rate) * self.config.evolution;
 
         let start = Instant::now();
 
-        for _ in 0..size_x {
-            for _ in 0..size_y {
-                for _ in 0..size_z {
-                    res.push(rng.gen());
-                }
+        let res: Vec<f32> = match self.config.algorithm {
+            NoiseAlgorithm::White => {
+                let count = sizes.iter().product::<usize>() as u64;
+                (0..count)
+                    .map(|index| white_noise(self.seed, sample, index))
+                    .collect()
             }
-        }
+            NoiseAlgorithm::Value => self.sample_grid(&sizes, time, Value::new),
+            NoiseAlgorithm::Perlin => self.sample_grid(&sizes, time, Perlin::new),
+            NoiseAlgorithm::Simplex => self.sample_grid(&sizes, time, Simplex::new),
+            NoiseAlgorithm::Worley => self.sample_grid(&sizes, time, |seed| {
+                Worley::new(seed).set_return_type(ReturnType::Distance)
+            }),
+        };
 
         let elapsed_time = Instant::now().duration_since(start).as_millis();
 
         debug!(
-            "Calculated {} noise values in {}ms",
-            u64::pow(10, 3),
-            elapsed_time
+            "Calculated {} {:?} noise values in {}ms (Noise Device {})",
+            res.len(),
+            self.config.algorithm,
+            elapsed_time,
+            self.id.unprotect()
         );
 
-        let unchecked_data = (res, size_x as usize, size_y as usize, size_z as usize);
+        let data = match self.config.dimensions.len() {
+            0 => AuxiliaryDataType::F32(res[0]),
+            1 => AuxiliaryDataType::F32Vec(
+                (res, sizes[0])
+                    .try_into()
+                    .expect("Noise data should conform to requirements"),
+            ),
+            2 => AuxiliaryDataType::F32Vec2(
+                (res, sizes[0], sizes[1])
+                    .try_into()
+                    .expect("Noise data should conform to requirements"),
+            ),
+            _ => AuxiliaryDataType::F32Vec3(
+                (res, sizes[0], sizes[1], sizes[2])
+                    .try_into()
+                    .expect("Noise data should conform to requirements"),
+            ),
+        };
 
-        let data = AuxiliaryDataType::F32Vec3(
-            unchecked_data
-                .try_into()
-                .expect("Noise data should conform to requirements"),
-        );
-
-        self.prev_time_point = Some(time_point);
+        self.prev_sample = Some(sample);
         self.prev_values = Some(data.clone());
 
         data
     }
+
+    /// Samples fractal noise at each grid point, with time as the fourth axis.
+    /// Generators are built per sample as some aren't `Send`.
+    fn sample_grid<T, F>(&self, sizes: &[usize], time: f64, generator: F) -> Vec<f32>
+    where
+        T: NoiseFn<f64, 4>,
+        F: Fn(u32) -> T,
+    {
+        let octaves: Vec<T> = (0..self.config.octaves.max(1) as u32)
+            .map(|octave| generator(self.seed.wrapping_add(octave)))
+            .collect();
+        let scale = self.config.scale;
+
+        let mut res: Vec<f32> = Vec::with_capacity(sizes.iter().product());
+
+        // Offset by half a cell, gradient noise is always 0 on integer lattice points
+        for x in 0..sizes[0] {
+            for y in 0..sizes[1] {
+                for z in 0..sizes[2] {
+                    let point = [
+                        (x as f64 + 0.5) * scale,
+                        (y as f64 + 0.5) * scale,
+                        (z as f64 + 0.5) * scale,
+                        time,
+                    ];
+                    res.push(self.fractal_noise(&octaves, point));
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Sums octaves of noise, scaled into 0.0 - 1.0.
+    fn fractal_noise<T: NoiseFn<f64, 4>>(&self, octaves: &[T], point: [f64; 4]) -> f32 {
+        let mut total = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+
+        for octave in octaves {
+            total += octave.get(point.map(|value| value * frequency)) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= self.config.persistence;
+            frequency *= self.config.lacunarity;
+        }
+
+        ((total / total_amplitude + 1.0) / 2.0).clamp(0.0, 1.0) as f32
+    }
+}
+
+/// Uniform value in 0.0 - 1.0, the same for a given seed, sample and grid index.
+pub fn white_noise(seed: u32, sample: u64, index: u64) -> f32 {
+    // SplitMix64 finaliser
+    let mut hash = (u64::from(seed) << 32) ^ sample.rotate_left(17) ^ index;
+    hash = hash.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    hash ^= hash >> 31;
+
+    (hash >> 40) as f32 / (1u64 << 24) as f32
 }
 
 impl AuxiliaryDataDevice for NoiseAuxiliaryDataDevice {
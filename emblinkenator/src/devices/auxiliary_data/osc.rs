@@ -0,0 +1,454 @@
+use std::{convert::TryInto, io::ErrorKind, net::UdpSocket, time::Instant};
+
+use crossbeam::channel::Sender;
+
+use log::{debug, error, warn};
+use rosc::{
+    address::{Matcher, OscAddress},
+    OscMessage, OscPacket, OscType,
+};
+use serde::Deserialize;
+
+use crate::{
+    auxiliary_data::{AuxColour, AuxiliaryDataType, AuxiliaryDataTypeConsumer},
+    cue::CueEvent,
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+    tempo::TempoEvent,
+};
+
+use super::{packed_f32s_from_bytes, AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Deserialize)]
+pub struct OSCReceiverConfig {
+    #[serde(default = "default_osc_host")]
+    host: String,
+    port: u16,
+    mappings: Vec<OSCMapping>,
+    // Messages to this address tap the tempo, unless their first argument is 0 (e.g. a button being released)
+    tap_address: Option<String>,
+    // Sets the tempo to the message's first argument in BPM
+    bpm_address: Option<String>,
+    // Messages to this address fire the next cue, of the cue list named by a string argument or of every list waiting for a GO
+    go_address: Option<String>,
+    // Fires the cue named by the message's second argument in the cue list named by its first
+    go_to_address: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct OSCMapping {
+    // OSC address pattern, e.g. "/1/fader1" or "/mixer/*/level"
+    address: String,
+    aux_id: String,
+    #[serde(rename = "type", default = "default_mapping_type")]
+    aux_type: AuxiliaryDataTypeConsumer,
+}
+
+fn default_osc_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_mapping_type() -> AuxiliaryDataTypeConsumer {
+    AuxiliaryDataTypeConsumer::F32
+}
+
+struct OSCRoute {
+    matcher: Matcher,
+    aux_id: AuxiliaryId,
+    aux_type: AuxiliaryDataTypeConsumer,
+}
+
+pub struct OSCAuxiliaryDataDevice {
+    id: DeviceId,
+    socket: Option<UdpSocket>,
+    routes: Vec<OSCRoute>,
+    tap_matcher: Option<Matcher>,
+    bpm_matcher: Option<Matcher>,
+    go_matcher: Option<Matcher>,
+    go_to_matcher: Option<Matcher>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+    tempo_output_buffer: Option<Sender<TempoEvent>>,
+    cue_output_buffer: Option<Sender<CueEvent>>,
+}
+
+impl OSCAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, config: OSCReceiverConfig) -> Self {
+        let address = format!("{}:{}", config.host, config.port);
+        let socket = match UdpSocket::bind(&address) {
+            Ok(socket) => match socket.set_nonblocking(true) {
+                Ok(()) => Some(socket),
+                Err(err) => {
+                    error!(
+                        "Could not make OSC socket non-blocking ({}) (OSC Device {})",
+                        err,
+                        id.unprotect()
+                    );
+                    None
+                }
+            },
+            Err(err) => {
+                error!(
+                    "Could not listen for OSC on {} ({}) (OSC Device {})",
+                    address,
+                    err,
+                    id.unprotect()
+                );
+                None
+            }
+        };
+
+        let routes = config
+            .mappings
+            .into_iter()
+            .filter_map(|mapping| {
+                Some(OSCRoute {
+                    matcher: address_matcher(&mapping.address, &id)?,
+                    aux_id: AuxiliaryId::new_from(mapping.aux_id),
+                    aux_type: mapping.aux_type,
+                })
+            })
+            .collect();
+
+        OSCAuxiliaryDataDevice {
+            tap_matcher: config
+                .tap_address
+                .and_then(|address| address_matcher(&address, &id)),
+            bpm_matcher: config
+                .bpm_address
+                .and_then(|address| address_matcher(&address, &id)),
+            go_matcher: config
+                .go_address
+                .and_then(|address| address_matcher(&address, &id)),
+            go_to_matcher: config
+                .go_to_address
+                .and_then(|address| address_matcher(&address, &id)),
+            id,
+            socket,
+            routes,
+            data_output_buffer: None,
+            tempo_output_buffer: None,
+            cue_output_buffer: None,
+        }
+    }
+
+    fn cue_event(&self, address: &OscAddress, message: &OscMessage) -> Option<CueEvent> {
+        let string_args: Vec<String> = message
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                OscType::String(arg) => Some(arg.clone()),
+                _ => None,
+            })
+            .collect();
+
+        if let Some(go_matcher) = &self.go_matcher {
+            let first_arg = message.args.first().and_then(osc_arg_to_f32);
+            if go_matcher.match_address(address) && first_arg != Some(0.0) {
+                return Some(CueEvent::Go(string_args.first().cloned()));
+            }
+        }
+
+        if let Some(go_to_matcher) = &self.go_to_matcher {
+            if go_to_matcher.match_address(address) {
+                return match string_args.as_slice() {
+                    [list, cue, ..] => Some(CueEvent::GoTo {
+                        list: list.clone(),
+                        cue: cue.clone(),
+                    }),
+                    _ => {
+                        warn!(
+                            "OSC message {} needs a cue list and cue to go to (OSC Device {})",
+                            message.addr,
+                            self.id.unprotect()
+                        );
+                        None
+                    }
+                };
+            }
+        }
+
+        None
+    }
+
+    fn tempo_event(&self, address: &OscAddress, message: &OscMessage) -> Option<TempoEvent> {
+        let first_arg = message.args.first().and_then(osc_arg_to_f32);
+
+        if let Some(tap_matcher) = &self.tap_matcher {
+            if tap_matcher.match_address(address) && first_arg != Some(0.0) {
+                return Some(TempoEvent::Tap(Instant::now()));
+            }
+        }
+
+        if let Some(bpm_matcher) = &self.bpm_matcher {
+            if bpm_matcher.match_address(address) {
+                return first_arg.map(|bpm| TempoEvent::SetBpm(f64::from(bpm)));
+            }
+        }
+
+        None
+    }
+
+    fn handle_packet(&self, packet: OscPacket) {
+        match packet {
+            OscPacket::Message(message) => self.handle_message(message),
+            OscPacket::Bundle(bundle) => {
+                for packet in bundle.content {
+                    self.handle_packet(packet);
+                }
+            }
+        }
+    }
+
+    fn handle_message(&self, message: OscMessage) {
+        let address = match OscAddress::new(message.addr.clone()) {
+            Ok(address) => address,
+            Err(_) => {
+                debug!("Received OSC message with invalid address {}", message.addr);
+                return;
+            }
+        };
+
+        if let Some(event) = self.tempo_event(&address, &message) {
+            if let Some(tempo_output_buffer) = &self.tempo_output_buffer {
+                tempo_output_buffer.send(event).ok();
+            }
+        }
+
+        if let Some(event) = self.cue_event(&address, &message) {
+            if let Some(cue_output_buffer) = &self.cue_output_buffer {
+                cue_output_buffer.send(event).ok();
+            }
+        }
+
+        for route in self.routes.iter() {
+            if !route.matcher.match_address(&address) {
+                continue;
+            }
+
+            match osc_args_to_aux_data(&message.args, &route.aux_type) {
+                Some(data) => {
+                    if let Some(data_output_buffer) = &self.data_output_buffer {
+                        data_output_buffer
+                            .send(AuxDeviceData {
+                                aux_id: route.aux_id.clone(),
+                                data,
+                            })
+                            .ok();
+                    }
+                }
+                None => warn!(
+                    "Cannot convert arguments of OSC message {} to {} (OSC Device {})",
+                    message.addr,
+                    route.aux_type,
+                    self.id.unprotect()
+                ),
+            }
+        }
+    }
+}
+
+impl AuxiliaryDataDevice for OSCAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        let socket = match &self.socket {
+            Some(socket) => socket,
+            None => return,
+        };
+
+        let mut buffer = [0u8; rosc::decoder::MTU];
+        let mut packets: Vec<OscPacket> = vec![];
+
+        loop {
+            match socket.recv_from(&mut buffer) {
+                Ok((size, _)) => match rosc::decoder::decode_udp(&buffer[..size]) {
+                    Ok((_, packet)) => packets.push(packet),
+                    Err(err) => debug!(
+                        "Could not decode OSC packet ({:?}) (OSC Device {})",
+                        err,
+                        self.id.unprotect()
+                    ),
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    error!(
+                        "Could not read from OSC socket ({}) (OSC Device {})",
+                        err,
+                        self.id.unprotect()
+                    );
+                    break;
+                }
+            }
+        }
+
+        for packet in packets {
+            self.handle_packet(packet);
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        warn!(
+            "OSC devices route messages using their address mappings, ignoring request to send to {} (OSC Device {})",
+            aux_id,
+            self.id.unprotect()
+        );
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        _buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        // OSC messages are forwarded as they arrive, frame data isn't needed
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+
+    fn send_tempo_into(&mut self, sender: Sender<TempoEvent>) {
+        self.tempo_output_buffer.replace(sender);
+    }
+
+    fn send_cues_into(&mut self, sender: Sender<CueEvent>) {
+        self.cue_output_buffer.replace(sender);
+    }
+}
+
+fn address_matcher(address: &str, id: &DeviceId) -> Option<Matcher> {
+    match Matcher::new(address) {
+        Ok(matcher) => Some(matcher),
+        Err(err) => {
+            error!(
+                "Invalid OSC address pattern {} ({}) (OSC Device {})",
+                address,
+                err,
+                id.unprotect()
+            );
+            None
+        }
+    }
+}
+
+fn osc_arg_to_f32(arg: &OscType) -> Option<f32> {
+    match arg {
+        OscType::Float(val) => Some(*val),
+        OscType::Double(val) => Some(*val as f32),
+        OscType::Int(val) => Some(*val as f32),
+        OscType::Long(val) => Some(*val as f32),
+        OscType::Bool(val) => Some(f32::from(u8::from(*val))),
+        _ => None,
+    }
+}
+
+fn osc_arg_to_i64(arg: &OscType) -> Option<i64> {
+    match arg {
+        OscType::Int(val) => Some(i64::from(*val)),
+        OscType::Long(val) => Some(*val),
+        OscType::Float(val) => Some(val.round() as i64),
+        OscType::Double(val) => Some(val.round() as i64),
+        OscType::Bool(val) => Some(i64::from(*val)),
+        _ => None,
+    }
+}
+
+/// Values are either a single blob or a list of numbers
+fn osc_args_to_f32s(args: &[OscType]) -> Option<Vec<f32>> {
+    match args {
+        [OscType::Blob(blob)] => packed_f32s_from_bytes(blob),
+        _ => args.iter().map(osc_arg_to_f32).collect(),
+    }
+}
+
+/// Tensor values are sent as the size of each dimension followed by the values
+fn osc_args_to_tensor(args: &[OscType], dimensions: usize) -> Option<(Vec<usize>, Vec<f32>)> {
+    if args.len() < dimensions {
+        return None;
+    }
+
+    let sizes = args[..dimensions]
+        .iter()
+        .map(|arg| match arg {
+            OscType::Int(val) => (*val).try_into().ok(),
+            OscType::Long(val) => (*val).try_into().ok(),
+            _ => None,
+        })
+        .collect::<Option<Vec<usize>>>()?;
+
+    Some((sizes, osc_args_to_f32s(&args[dimensions..])?))
+}
+
+fn osc_args_to_aux_data(
+    args: &[OscType],
+    aux_type: &AuxiliaryDataTypeConsumer,
+) -> Option<AuxiliaryDataType> {
+    match aux_type {
+        AuxiliaryDataTypeConsumer::Empty => None,
+        AuxiliaryDataTypeConsumer::F32 => args
+            .first()
+            .and_then(osc_arg_to_f32)
+            .map(AuxiliaryDataType::F32),
+        AuxiliaryDataTypeConsumer::I32 => args
+            .first()
+            .and_then(osc_arg_to_i64)
+            .map(|val| AuxiliaryDataType::I32(val.clamp(i32::MIN.into(), i32::MAX.into()) as i32)),
+        AuxiliaryDataTypeConsumer::U32 => args
+            .first()
+            .and_then(osc_arg_to_i64)
+            .map(|val| AuxiliaryDataType::U32(val.clamp(0, u32::MAX.into()) as u32)),
+        AuxiliaryDataTypeConsumer::Enum => args
+            .first()
+            .and_then(osc_arg_to_i64)
+            .map(|val| AuxiliaryDataType::Enum(val.clamp(0, u32::MAX.into()) as u32)),
+        AuxiliaryDataTypeConsumer::Bool => match args.first()? {
+            OscType::Bool(val) => Some(AuxiliaryDataType::Bool(*val)),
+            arg => osc_arg_to_f32(arg).map(|val| AuxiliaryDataType::Bool(val != 0.0)),
+        },
+        AuxiliaryDataTypeConsumer::Colour => match args {
+            [OscType::Color(colour)] => Some(AuxiliaryDataType::Colour(AuxColour::Rgb([
+                f32::from(colour.red) / 255.0,
+                f32::from(colour.green) / 255.0,
+                f32::from(colour.blue) / 255.0,
+            ]))),
+            _ => {
+                let rgb: [f32; 3] = osc_args_to_f32s(args)?.try_into().ok()?;
+                Some(AuxiliaryDataType::Colour(AuxColour::Rgb(rgb)))
+            }
+        },
+        AuxiliaryDataTypeConsumer::Vec2 => osc_args_to_f32s(args)?
+            .try_into()
+            .ok()
+            .map(AuxiliaryDataType::Vec2),
+        AuxiliaryDataTypeConsumer::Vec3 => osc_args_to_f32s(args)?
+            .try_into()
+            .ok()
+            .map(AuxiliaryDataType::Vec3),
+        AuxiliaryDataTypeConsumer::Vec4 => osc_args_to_f32s(args)?
+            .try_into()
+            .ok()
+            .map(AuxiliaryDataType::Vec4),
+        AuxiliaryDataTypeConsumer::F32Vec => {
+            let values = osc_args_to_f32s(args)?;
+            let len = values.len();
+            (values, len).try_into().ok().map(AuxiliaryDataType::F32Vec)
+        }
+        AuxiliaryDataTypeConsumer::F32Vec2 => {
+            let (sizes, values) = osc_args_to_tensor(args, 2)?;
+            (values, sizes[0], sizes[1])
+                .try_into()
+                .ok()
+                .map(AuxiliaryDataType::F32Vec2)
+        }
+        AuxiliaryDataTypeConsumer::F32Vec3 => {
+            let (sizes, values) = osc_args_to_tensor(args, 3)?;
+            (values, sizes[0], sizes[1], sizes[2])
+                .try_into()
+                .ok()
+                .map(AuxiliaryDataType::F32Vec3)
+        }
+        AuxiliaryDataTypeConsumer::F32Vec4 => {
+            let (sizes, values) = osc_args_to_tensor(args, 4)?;
+            (values, sizes[0], sizes[1], sizes[2], sizes[3])
+                .try_into()
+                .ok()
+                .map(AuxiliaryDataType::F32Vec4)
+        }
+    }
+}
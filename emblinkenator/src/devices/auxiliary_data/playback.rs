@@ -0,0 +1,210 @@
+use std::{path::Path, thread};
+
+use crossbeam::channel::{bounded, Receiver, TryRecvError};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+
+use crate::{
+    auxiliary_data::recording::{read_recording, RecordedFrame, Recording, RecordingError},
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+};
+
+use super::{AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Deserialize)]
+pub struct PlaybackConfig {
+    // A recording made with `--record-auxiliaries`
+    path: String,
+    #[serde(rename = "loop", default)]
+    looping: bool,
+}
+
+pub struct PlaybackAuxiliaryDataDevice {
+    id: DeviceId,
+    looping: bool,
+    frames: Vec<RecordedFrame>,
+    // Numerator, Denominator
+    recorded_frame_rate: (u32, u32),
+    loading: Option<Receiver<Result<Recording, RecordingError>>>,
+    // Frame number playback started at, recorded frames are relative to it
+    start_frame: Option<u32>,
+    // Index of the next recorded frame to send and how many times playback has looped
+    next_index: usize,
+    loops: u32,
+    next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+}
+
+impl PlaybackAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, config: PlaybackConfig) -> Self {
+        let (sender, receiver) = bounded(1);
+        let path = config.path;
+
+        thread::spawn(move || {
+            sender.send(read_recording(Path::new(&path))).ok();
+        });
+
+        PlaybackAuxiliaryDataDevice {
+            id,
+            looping: config.looping,
+            frames: vec![],
+            recorded_frame_rate: (0, 0),
+            loading: Some(receiver),
+            start_frame: None,
+            next_index: 0,
+            loops: 0,
+            next_frame_data_buffer: None,
+            data_output_buffer: None,
+        }
+    }
+
+    fn receive_loaded_recording(&mut self) {
+        let loading = match &self.loading {
+            Some(loading) => loading,
+            None => return,
+        };
+
+        match loading.try_recv() {
+            Ok(Ok(recording)) => {
+                info!(
+                    "Loaded recording of {} frames at {}/{} (Playback Device {})",
+                    recording
+                        .frames
+                        .last()
+                        .map(|frame| frame.frame + 1)
+                        .unwrap_or(0),
+                    recording.header.frame_numerator,
+                    recording.header.frame_denominator,
+                    self.id.unprotect()
+                );
+                self.recorded_frame_rate = (
+                    recording.header.frame_numerator,
+                    recording.header.frame_denominator,
+                );
+                self.frames = recording.frames;
+                self.loading.take();
+            }
+            Ok(Err(err)) => {
+                error!(
+                    "Could not load recording ({}) (Playback Device {})",
+                    err,
+                    self.id.unprotect()
+                );
+                self.loading.take();
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                self.loading.take();
+            }
+        }
+    }
+
+    /// Returns every update recorded since the last frame, up to and including `frame_data`.
+    fn updates_for_frame(&mut self, frame_data: &FrameData) -> Vec<AuxDeviceData> {
+        let length = match self.frames.last() {
+            Some(last) => last.frame + 1,
+            None => return vec![],
+        };
+
+        // Recordings start from the first frame after they are loaded
+        let start_frame = match self.start_frame {
+            Some(start_frame) => start_frame,
+            None => {
+                if self.recorded_frame_rate
+                    != (frame_data.frame_numerator, frame_data.frame_denominator)
+                {
+                    warn!(
+                        "Recording was made at {}/{} but frames are {}/{}, playback will run at a different speed (Playback Device {})",
+                        self.recorded_frame_rate.0,
+                        self.recorded_frame_rate.1,
+                        frame_data.frame_numerator,
+                        frame_data.frame_denominator,
+                        self.id.unprotect()
+                    );
+                }
+                *self.start_frame.insert(frame_data.frame)
+            }
+        };
+        let mut position = frame_data.frame.saturating_sub(start_frame);
+        if self.looping {
+            // Counting loops rewinds even a single frame recording, where the position never changes
+            let loops = position / length;
+            position %= length;
+            if loops != self.loops {
+                self.next_index = 0;
+                self.loops = loops;
+            }
+        }
+
+        let mut updates: Vec<AuxDeviceData> = vec![];
+        while let Some(recorded) = self.frames.get(self.next_index) {
+            if recorded.frame > position {
+                break;
+            }
+            updates.extend(recorded.updates.iter().map(|(aux_id, data)| AuxDeviceData {
+                aux_id: AuxiliaryId::new_from(aux_id.clone()),
+                data: data.clone(),
+            }));
+            self.next_index += 1;
+        }
+
+        updates
+    }
+}
+
+impl AuxiliaryDataDevice for PlaybackAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        self.receive_loaded_recording();
+
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                let updates = self.updates_for_frame(&next_frame_data);
+                if let Some(data_output_buffer) = &self.data_output_buffer {
+                    for update in updates {
+                        data_output_buffer.send(update).ok();
+                    }
+                }
+            }
+            Err(err) => match err {
+                tokio::sync::broadcast::error::TryRecvError::Empty => {}
+                tokio::sync::broadcast::error::TryRecvError::Closed => {
+                    debug!(
+                        "Playback auxiliary {} had next frame data buffer closed",
+                        self.id.unprotect()
+                    );
+                    self.next_frame_data_buffer.take();
+                }
+                tokio::sync::broadcast::error::TryRecvError::Lagged(num) => warn!(
+                    "Playback auxiliary device {} lagged by {} frames",
+                    self.id.unprotect(),
+                    num
+                ),
+            },
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        warn!(
+            "Playback devices send to the auxiliaries they were recorded from, ignoring request to send to {} (Playback Device {})",
+            aux_id,
+            self.id.unprotect()
+        );
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+}
@@ -0,0 +1,32 @@
+use serde::Deserialize;
+
+/// How often a generator repeats, either freely or synced to a tempo.
+#[derive(Clone, Debug, Deserialize)]
+pub enum GeneratorRate {
+    // Cycles per second
+    Hz(f32),
+    // One cycle every `beats` beats at `bpm`
+    Beats { bpm: f32, beats: f32 },
+}
+
+impl GeneratorRate {
+    /// Length of one cycle in seconds, `None` if the rate doesn't describe a finite cycle.
+    pub fn period(&self) -> Option<f32> {
+        let period = match self {
+            GeneratorRate::Hz(hz) => 1.0 / hz,
+            GeneratorRate::Beats { bpm, beats } => beats * 60.0 / bpm,
+        };
+
+        if period.is_finite() && period > 0.0 {
+            Some(period)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for GeneratorRate {
+    fn default() -> Self {
+        GeneratorRate::Hz(1.0)
+    }
+}
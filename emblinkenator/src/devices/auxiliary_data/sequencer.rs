@@ -0,0 +1,139 @@
+use log::{debug, error, warn};
+use serde::Deserialize;
+
+use crate::{
+    auxiliary_data::AuxiliaryDataType,
+    frame::FrameData,
+    id::{AuxiliaryId, DeviceId},
+};
+
+use super::{rate::GeneratorRate, AuxDeviceData, AuxiliaryDataDevice};
+
+#[derive(Clone, Deserialize)]
+pub struct SequencerConfig {
+    steps: Vec<f32>,
+    // Length of each step
+    #[serde(default)]
+    rate: GeneratorRate,
+    // Slide linearly to the next step rather than jumping
+    #[serde(default)]
+    glide: bool,
+}
+
+pub struct SequencerAuxiliaryDataDevice {
+    id: DeviceId,
+    aux_id: Option<AuxiliaryId>,
+    config: SequencerConfig,
+    step_length: f32,
+    next_frame_data_buffer: Option<tokio::sync::broadcast::Receiver<FrameData>>,
+    data_output_buffer: Option<tokio::sync::broadcast::Sender<AuxDeviceData>>,
+}
+
+impl SequencerAuxiliaryDataDevice {
+    pub fn new(id: DeviceId, aux_id: Option<AuxiliaryId>, config: SequencerConfig) -> Self {
+        let step_length = match config.rate.period() {
+            Some(step_length) => step_length,
+            None => {
+                error!(
+                    "Sequencer rate {:?} is invalid, using 1Hz (Sequencer Device {})",
+                    config.rate,
+                    id.unprotect()
+                );
+                1.0
+            }
+        };
+
+        if config.steps.is_empty() {
+            warn!(
+                "Sequencer has no steps and will not send any values (Sequencer Device {})",
+                id.unprotect()
+            );
+        }
+
+        SequencerAuxiliaryDataDevice {
+            id,
+            aux_id,
+            config,
+            step_length,
+            next_frame_data_buffer: None,
+            data_output_buffer: None,
+        }
+    }
+
+    fn get_value_for_frame(&self, frame_data: &FrameData) -> Option<f32> {
+        let steps = &self.config.steps;
+        if steps.is_empty() {
+            return None;
+        }
+
+        let position = frame_data.precise_seconds_elapsed() / f64::from(self.step_length);
+        let step = position.floor() as usize % steps.len();
+        let value = steps[step];
+
+        if !self.config.glide {
+            return Some(value);
+        }
+
+        let next_value = steps[(step + 1) % steps.len()];
+        let progress = position.fract() as f32;
+        Some(value + (next_value - value) * progress)
+    }
+}
+
+impl AuxiliaryDataDevice for SequencerAuxiliaryDataDevice {
+    fn tick(&mut self) {
+        let next_frame_data_buffer = match self.next_frame_data_buffer.as_mut() {
+            Some(next_frame_data_buffer) => next_frame_data_buffer,
+            None => return,
+        };
+
+        match next_frame_data_buffer.try_recv() {
+            Ok(next_frame_data) => {
+                let value = match self.get_value_for_frame(&next_frame_data) {
+                    Some(value) => value,
+                    None => return,
+                };
+                if let (Some(data_output_buffer), Some(aux_id)) =
+                    (&self.data_output_buffer, &self.aux_id)
+                {
+                    data_output_buffer
+                        .send(AuxDeviceData {
+                            aux_id: aux_id.clone(),
+                            data: AuxiliaryDataType::F32(value),
+                        })
+                        .ok();
+                }
+            }
+            Err(err) => match err {
+                tokio::sync::broadcast::error::TryRecvError::Empty => {}
+                tokio::sync::broadcast::error::TryRecvError::Closed => {
+                    debug!(
+                        "Sequencer auxiliary {} had next frame data buffer closed",
+                        self.id.unprotect()
+                    );
+                    self.next_frame_data_buffer.take();
+                }
+                tokio::sync::broadcast::error::TryRecvError::Lagged(num) => warn!(
+                    "Sequencer auxiliary device {} lagged by {} frames",
+                    self.id.unprotect(),
+                    num
+                ),
+            },
+        }
+    }
+
+    fn send_data_to_aux(&mut self, aux_id: AuxiliaryId) {
+        self.aux_id.replace(aux_id);
+    }
+
+    fn receive_next_frame_data_buffer(
+        &mut self,
+        buffer: tokio::sync::broadcast::Receiver<FrameData>,
+    ) {
+        self.next_frame_data_buffer.replace(buffer);
+    }
+
+    fn send_into_buffer(&mut self, buffer: tokio::sync::broadcast::Sender<AuxDeviceData>) {
+        self.data_output_buffer.replace(buffer);
+    }
+}
@@ -13,7 +13,17 @@ use crate::{
 
 use super::{
     auxiliary_data::{
-        noise::NoiseAuxiliaryDataDevice, AuxiliaryDataDeviceType, ThreadedAuxiliaryDeviceWrapper,
+        audio::{AudioAnalyserConfig, AudioAuxiliaryDataDevice},
+        envelope::{EnvelopeAuxiliaryDataDevice, EnvelopeConfig},
+        image::{ImageAuxiliaryDataDevice, ImageSourceConfig},
+        lfo::{LFOAuxiliaryDataDevice, LFOConfig},
+        midi::{MIDIAuxiliaryDataDevice, MIDIReceiverConfig},
+        mqtt::{MQTTAuxiliaryDataDevice, MQTTReceiverConfig},
+        noise::{NoiseAuxiliaryDataDevice, NoiseConfig},
+        osc::{OSCAuxiliaryDataDevice, OSCReceiverConfig},
+        playback::{PlaybackAuxiliaryDataDevice, PlaybackConfig},
+        sequencer::{SequencerAuxiliaryDataDevice, SequencerConfig},
+        AuxiliaryDataDeviceType, ThreadedAuxiliaryDeviceWrapper,
     },
     led_output::{
         mqtt::{MQTTSender, MQTTSenderConfig},
@@ -35,7 +45,7 @@ pub enum ThreadedDeviceType {
 
 pub enum DeviceType {
     LEDDataOutput(LEDDataOutputDeviceType),
-    Auxiliary(AuxiliaryDataDeviceType),
+    Auxiliary(Box<AuxiliaryDataDeviceType>),
 }
 
 #[derive(Deserialize, Clone)]
@@ -53,14 +63,28 @@ pub enum LEDOutputConfigType {
 
 #[derive(Deserialize, Clone)]
 struct AuxiliaryDataConfig {
-    aux_id: String,
+    // Devices that feed several auxiliaries (e.g. OSC) map to them in their own config
+    aux_id: Option<String>,
     config: AuxiliaryDataConfigType,
 }
 
 #[derive(Deserialize, Clone)]
 #[serde(tag = "type")]
 pub enum AuxiliaryDataConfigType {
-    Noise,
+    Noise(Box<NoiseConfig>),
+    #[serde(rename = "OSC")]
+    Osc(Box<OSCReceiverConfig>),
+    #[serde(rename = "MIDI")]
+    Midi(MIDIReceiverConfig),
+    Audio(Box<AudioAnalyserConfig>),
+    #[serde(rename = "MQTT")]
+    Mqtt(MQTTReceiverConfig),
+    #[serde(rename = "LFO")]
+    Lfo(LFOConfig),
+    Envelope(EnvelopeConfig),
+    Sequencer(SequencerConfig),
+    Image(ImageSourceConfig),
+    Playback(PlaybackConfig),
 }
 
 struct DeviceConfigWithId(DeviceId, DeviceConfigType);
@@ -109,7 +133,7 @@ impl DeviceManager {
                 ThreadedLEDOutputDeviceWrapper::new(led_data_output),
             ),
             DeviceType::Auxiliary(auxiliary) => {
-                ThreadedDeviceType::AuxiliaryData(ThreadedAuxiliaryDeviceWrapper::new(auxiliary))
+                ThreadedDeviceType::AuxiliaryData(ThreadedAuxiliaryDeviceWrapper::new(*auxiliary))
             }
         };
         self.add_device(id, device)
@@ -176,7 +200,7 @@ impl From<DeviceConfigWithId> for DeviceType {
             DeviceConfigType::Auxiliary(auxiliary_device_config) => {
                 let auxiliary_device_config_with_id =
                     AuxiliaryDataConfigWithId(device_id, auxiliary_device_config);
-                DeviceType::Auxiliary(auxiliary_device_config_with_id.into())
+                DeviceType::Auxiliary(Box::new(auxiliary_device_config_with_id.into()))
             }
         }
     }
@@ -219,10 +243,57 @@ impl From<AuxiliaryDataConfigWithId> for AuxiliaryDataDeviceType {
         let auxiliary_device_config = auxiliary_device_config_with_id.1;
 
         match auxiliary_device_config.config {
-            AuxiliaryDataConfigType::Noise => {
+            AuxiliaryDataConfigType::Noise(noise_config) => {
                 AuxiliaryDataDeviceType::Noise(NoiseAuxiliaryDataDevice::new(
                     device_id,
-                    AuxiliaryId::new_from(auxiliary_device_config.aux_id),
+                    auxiliary_device_config.aux_id.map(AuxiliaryId::new_from),
+                    *noise_config,
+                ))
+            }
+            AuxiliaryDataConfigType::Osc(osc_config) => {
+                AuxiliaryDataDeviceType::Osc(OSCAuxiliaryDataDevice::new(device_id, *osc_config))
+            }
+            AuxiliaryDataConfigType::Midi(midi_config) => {
+                AuxiliaryDataDeviceType::Midi(MIDIAuxiliaryDataDevice::new(device_id, midi_config))
+            }
+            AuxiliaryDataConfigType::Audio(audio_config) => AuxiliaryDataDeviceType::Audio(
+                AudioAuxiliaryDataDevice::new(device_id, *audio_config),
+            ),
+            AuxiliaryDataConfigType::Mqtt(mqtt_config) => {
+                AuxiliaryDataDeviceType::Mqtt(MQTTAuxiliaryDataDevice::new(device_id, mqtt_config))
+            }
+            AuxiliaryDataConfigType::Lfo(lfo_config) => {
+                AuxiliaryDataDeviceType::Lfo(LFOAuxiliaryDataDevice::new(
+                    device_id,
+                    auxiliary_device_config.aux_id.map(AuxiliaryId::new_from),
+                    lfo_config,
+                ))
+            }
+            AuxiliaryDataConfigType::Envelope(envelope_config) => {
+                AuxiliaryDataDeviceType::Envelope(EnvelopeAuxiliaryDataDevice::new(
+                    device_id,
+                    auxiliary_device_config.aux_id.map(AuxiliaryId::new_from),
+                    envelope_config,
+                ))
+            }
+            AuxiliaryDataConfigType::Sequencer(sequencer_config) => {
+                AuxiliaryDataDeviceType::Sequencer(SequencerAuxiliaryDataDevice::new(
+                    device_id,
+                    auxiliary_device_config.aux_id.map(AuxiliaryId::new_from),
+                    sequencer_config,
+                ))
+            }
+            AuxiliaryDataConfigType::Image(image_config) => {
+                AuxiliaryDataDeviceType::Image(ImageAuxiliaryDataDevice::new(
+                    device_id,
+                    auxiliary_device_config.aux_id.map(AuxiliaryId::new_from),
+                    image_config,
+                ))
+            }
+            AuxiliaryDataConfigType::Playback(playback_config) => {
+                AuxiliaryDataDeviceType::Playback(PlaybackAuxiliaryDataDevice::new(
+                    device_id,
+                    playback_config,
                 ))
             }
         }
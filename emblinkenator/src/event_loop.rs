@@ -1,6 +1,9 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
-use crossbeam::channel::Receiver;
+use crossbeam::channel::{Receiver, RecvTimeoutError};
 use log::debug;
 
 use crate::{
@@ -9,6 +12,9 @@ use crate::{
     pipeline::{ComputeOutput, EmblinkenatorPipeline, PipelineContext},
 };
 
+// How long to wait for the next frame before checking frames in flight again
+const FRAME_DATA_WAIT: Duration = Duration::from_millis(1);
+
 pub struct GPUEventLoop {
     state: GPUEventLoopState,
     command_queue: Vec<GPUEventLoopQueue>,
@@ -16,14 +22,13 @@ pub struct GPUEventLoop {
     frame_data_buffer: Receiver<FrameData>,
     pipeline_context_buffer: crossbeam::channel::Receiver<PipelineContext>,
     frame_output_buffer: tokio::sync::broadcast::Sender<PipelineFrameOutput>,
-    frame_state: Option<EventLoopFrameState>,
+    frame_state: EventLoopFrameState,
 }
 
 enum GPUEventLoopState {
     Paused,
     BeforeFrame,
     Compute,
-    WaitForGPUIdle,
     ReadDataFromGPU,
     OutputData,
     FrameEnd,
@@ -34,13 +39,18 @@ pub enum GPUEventLoopQueue {
     Exit,
 }
 
-#[derive(Clone)]
 struct EventLoopFrameState {
-    pub last_frame_state: Option<ComputeOutput>,
+    // Frame to compute next, if one has been received
+    pub frame_data: Option<FrameData>,
+    // Frames read back from the GPU this iteration
+    pub outputs: Vec<ComputeOutput>,
 }
 
 #[derive(Debug, Clone)]
 pub struct PipelineFrameOutput {
+    pub frame: u32,
+    // Outputs are held back until this time when rendering ahead
+    pub scheduled_time: Instant,
     pub states: HashMap<String, Vec<LED>>,
 }
 
@@ -58,23 +68,22 @@ impl GPUEventLoop {
             frame_data_buffer,
             pipeline_context_buffer,
             frame_output_buffer,
-            frame_state: None,
+            frame_state: EventLoopFrameState::new(),
         }
     }
 
-    pub async fn tick(&mut self) {
+    /// Frames are computed while earlier frames are still being read back, up to the pipeline's number of frames in flight.
+    pub fn tick(&mut self) {
         self.state = GPUEventLoopState::BeforeFrame;
 
-        // TODO: Any awaited steps here should likely either block directly or be run in a polling mode. May be waiting on upstream WebGPU work.
         'eventloop: loop {
             match self.state {
                 GPUEventLoopState::Paused => self.loop_step_paused(),
                 GPUEventLoopState::BeforeFrame => self.loop_step_before_frame(),
                 GPUEventLoopState::Compute => self.loop_step_compute(),
-                GPUEventLoopState::WaitForGPUIdle => self.loop_step_wait_for_gpu_idle(),
-                GPUEventLoopState::ReadDataFromGPU => self.loop_step_read_data_from_gpu().await,
+                GPUEventLoopState::ReadDataFromGPU => self.loop_step_read_data_from_gpu(),
                 GPUEventLoopState::OutputData => self.loop_step_output_data(),
-                GPUEventLoopState::FrameEnd => self.loop_step_frame_end().await,
+                GPUEventLoopState::FrameEnd => self.loop_step_frame_end(),
             }
 
             for command in &self.command_queue {
@@ -88,62 +97,72 @@ impl GPUEventLoop {
     fn loop_step_paused(&mut self) {}
 
     fn loop_step_before_frame(&mut self) {
-        debug!("Before frame");
+        self.frame_state = EventLoopFrameState::new();
+
+        if self.pipeline.can_compute() {
+            // Only wait for the next frame indefinitely if there's nothing on the GPU to read back in the meantime
+            self.frame_state.frame_data = if self.pipeline.has_frames_in_flight() {
+                match self.frame_data_buffer.recv_timeout(FRAME_DATA_WAIT) {
+                    Ok(frame_data) => Some(frame_data),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => panic!("Frame data buffer closed"),
+                }
+            } else {
+                Some(
+                    self.frame_data_buffer
+                        .recv()
+                        .expect("Frame data buffer closed"),
+                )
+            };
+        } else {
+            // Nothing more can be computed until the oldest frame is read back
+            self.pipeline.wait_for_frames_in_flight();
+        }
 
-        self.frame_state = Some(EventLoopFrameState::new());
-        let pipeline_context = self.pipeline_context_buffer.recv().unwrap();
-        self.pipeline.before_frame(&pipeline_context).unwrap();
+        if self.frame_state.frame_data.is_some() {
+            debug!("Before frame");
+            let pipeline_context = self.pipeline_context_buffer.recv().unwrap();
+            self.pipeline.before_frame(&pipeline_context).unwrap();
+        }
 
         self.next_state();
     }
 
     fn loop_step_compute(&mut self) {
-        let frame_data = self
-            .frame_data_buffer
-            .recv()
-            .expect("Frame data buffer closed");
-        debug!("Compute Frame {}", frame_data.frame);
-
-        self.pipeline.compute_frame(&frame_data).unwrap();
-
-        self.next_state();
-    }
-
-    fn loop_step_wait_for_gpu_idle(&mut self) {
-        debug!("Wait for idle");
-        self.pipeline.poll_device();
+        if let Some(frame_data) = &self.frame_state.frame_data {
+            debug!("Compute Frame {}", frame_data.frame);
+            self.pipeline.compute_frame(frame_data).unwrap();
+        }
 
         self.next_state();
     }
 
-    async fn loop_step_read_data_from_gpu(&mut self) {
-        debug!("Read from GPU");
-        let data = self.pipeline.read_led_states().await;
-        let mut state = self.frame_state.as_ref().unwrap().clone();
-        // TODO: This feels nasty
-        state.last_frame_state = Some(data);
-        self.frame_state.replace(state);
+    fn loop_step_read_data_from_gpu(&mut self) {
+        while let Some(output) = self.pipeline.read_led_states() {
+            debug!("Read frame {} from GPU", output.frame);
+            self.frame_state.outputs.push(output);
+        }
 
         self.next_state();
     }
 
     fn loop_step_output_data(&mut self) {
-        debug!("Output data");
-
-        if let Some(frame_state) = &self.frame_state.as_ref().unwrap().last_frame_state {
-            let output: PipelineFrameOutput = PipelineFrameOutput {
-                states: frame_state.states.clone(),
-            };
-
-            self.frame_output_buffer.send(output).unwrap();
+        for output in self.frame_state.outputs.drain(..) {
+            debug!("Output frame {}", output.frame);
+
+            self.frame_output_buffer
+                .send(PipelineFrameOutput {
+                    frame: output.frame,
+                    scheduled_time: output.scheduled_time,
+                    states: output.states,
+                })
+                .unwrap();
         }
 
         self.next_state();
     }
 
-    async fn loop_step_frame_end<'a>(&mut self) {
-        debug!("Frame end");
-
+    fn loop_step_frame_end(&mut self) {
         self.next_state();
     }
 
@@ -151,8 +170,7 @@ impl GPUEventLoop {
         self.state = match self.state {
             GPUEventLoopState::Paused => GPUEventLoopState::Paused,
             GPUEventLoopState::BeforeFrame => GPUEventLoopState::Compute,
-            GPUEventLoopState::Compute => GPUEventLoopState::WaitForGPUIdle,
-            GPUEventLoopState::WaitForGPUIdle => GPUEventLoopState::ReadDataFromGPU,
+            GPUEventLoopState::Compute => GPUEventLoopState::ReadDataFromGPU,
             GPUEventLoopState::ReadDataFromGPU => GPUEventLoopState::OutputData,
             GPUEventLoopState::OutputData => GPUEventLoopState::FrameEnd,
             GPUEventLoopState::FrameEnd => GPUEventLoopState::BeforeFrame,
@@ -163,7 +181,8 @@ impl GPUEventLoop {
 impl EventLoopFrameState {
     fn new() -> Self {
         EventLoopFrameState {
-            last_frame_state: None,
+            frame_data: None,
+            outputs: vec![],
         }
     }
 }
@@ -1,10 +1,20 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
-use log::{debug, warn};
+use log::{debug, info, warn};
 use parking_lot::RwLock;
-use tokio::time::{self, Instant, Interval};
 
-use crate::state::{ThreadedObject, WantsDeviceState};
+use crate::{
+    state::{ThreadedObject, WantsDeviceState},
+    tempo::{TempoClock, TempoConfig, TempoEvent, TempoPosition},
+    timecode::TimecodeChaser,
+};
+
+// Version of the frame data layout given to shaders
+// Fields are only ever appended, so shaders written for an older version read the start of the buffer
+pub const FRAME_DATA_VERSION: u32 = 2;
 
 #[derive(Clone)]
 pub struct FrameData {
@@ -13,11 +23,21 @@ pub struct FrameData {
     pub frame_denominator: u32,
     pub seconds_elapsed: f32,
     pub whole_seconds_elapsed: u32,
+    // Seconds since the last whole second, stays precise when seconds_elapsed no longer is
+    pub fractional_seconds_elapsed: f32,
+    // Wall-clock time the frame should be shown at
+    pub scheduled_time: Instant,
+    pub tempo: TempoPosition,
 }
 
-struct FrameStats {
-    pub frame_start_time: Instant,
-    pub target_frame_time: u128,
+/// Converts frame numbers to times, frames after a frame rate change carry on from the time the change was made at.
+struct FrameClock {
+    frame_numerator: u32,
+    frame_denominator: u32,
+    // Frame the current frame rate started at, with its elapsed and scheduled times
+    epoch_frame: u32,
+    epoch_elapsed: Duration,
+    epoch_time: Instant,
 }
 
 pub struct FrameTimeKeeper {
@@ -28,38 +48,60 @@ pub struct FrameTimeKeeper {
         RwLock<HashMap<String, tokio::sync::broadcast::Sender<FrameData>>>,
     next_frame_data_non_blocking_senders:
         RwLock<HashMap<String, tokio::sync::broadcast::Sender<FrameData>>>,
-    frame_numerator: u32,
-    frame_denominator: u32,
-    clock_frame: Interval,
+    clock: FrameClock,
+    // Frames counted by the clock, frame data's `frame` comes from the timecode while chasing one
+    frame: u32,
+    tempo: TempoClock,
+    tempo_sender: crossbeam::channel::Sender<TempoEvent>,
+    tempo_receiver: crossbeam::channel::Receiver<TempoEvent>,
+    // (frame_numerator, frame_denominator)
+    frame_rate_sender: crossbeam::channel::Sender<(u32, u32)>,
+    frame_rate_receiver: crossbeam::channel::Receiver<(u32, u32)>,
+    timecode: Option<TimecodeChaser>,
     frame_data: FrameData,
     next_frame_data: FrameData,
-    frame_stats: FrameStats,
-    late_time: u128,
     frame_buffer_size: u128,
+    // Frames ahead of the current frame that blocking receivers are sent
+    render_ahead: u32,
+    // Last frame sent to blocking receivers
+    rendered_frame: Option<u32>,
 }
 
 impl FrameTimeKeeper {
-    pub fn new(frame_numerator: u32, frame_denominator: u32, frame_buffer_size: u128) -> Self {
-        // TODO: This will drift
-        let frame_time: u64 = u64::from(frame_numerator / frame_denominator);
-        let clock_frame = time::interval(time::Duration::from_millis(frame_time));
+    pub fn new(
+        frame_numerator: u32,
+        frame_denominator: u32,
+        frame_buffer_size: u128,
+        render_ahead: u32,
+        tempo_config: &TempoConfig,
+    ) -> Self {
+        let clock = FrameClock::new(frame_numerator, frame_denominator);
+        let (tempo_sender, tempo_receiver) = crossbeam::channel::unbounded();
+        let (frame_rate_sender, frame_rate_receiver) = crossbeam::channel::unbounded();
 
         FrameTimeKeeper {
             frame_data_blocking_senders: RwLock::new(HashMap::new()),
             next_frame_data_blocking_senders: RwLock::new(HashMap::new()),
             frame_data_non_blocking_senders: RwLock::new(HashMap::new()),
             next_frame_data_non_blocking_senders: RwLock::new(HashMap::new()),
-            frame_numerator,
-            frame_denominator,
-            clock_frame,
-            frame_data: FrameData::new(0, frame_numerator, frame_denominator),
-            next_frame_data: FrameData::new(1, frame_numerator, frame_denominator),
-            frame_stats: FrameStats::new(u128::from(frame_time)),
+            frame_data: clock.frame_data(0),
+            next_frame_data: clock.frame_data(1),
+            clock,
+            frame: 0,
+            tempo: TempoClock::new(tempo_config),
+            tempo_sender,
+            tempo_receiver,
+            frame_rate_sender,
+            frame_rate_receiver,
+            timecode: None,
             frame_buffer_size,
-            late_time: 0,
+            render_ahead,
+            rendered_frame: None,
         }
     }
 
+    /// Blocking receivers render frames, so are sent the frame `render_ahead` frames after the current one.
+    /// The first tick also sends the frames from the current one up to then.
     pub fn send_frame_data_to_blocking(
         &self,
         receiver_id: String,
@@ -90,6 +132,62 @@ impl FrameTimeKeeper {
             .insert(receiver_id, buffer);
     }
 
+    /// Changes the frame rate from the frame after the next one, which has already been sent out.
+    /// Time carries on from that frame, so `seconds_elapsed` doesn't jump. Frames already rendered ahead keep the old rate.
+    pub fn set_frame_rate(&mut self, frame_numerator: u32, frame_denominator: u32) {
+        if frame_numerator == 0 || frame_denominator == 0 {
+            warn!(
+                "Ignoring invalid frame rate {}/{}",
+                frame_numerator, frame_denominator
+            );
+            return;
+        }
+
+        info!(
+            "Changing frame rate from {}/{} to {}/{}",
+            self.clock.frame_numerator,
+            self.clock.frame_denominator,
+            frame_numerator,
+            frame_denominator
+        );
+        self.clock
+            .set_frame_rate(self.frame + 1, frame_numerator, frame_denominator);
+    }
+
+    /// Frame rate changes, e.g. from cues, are sent here as `(frame_numerator, frame_denominator)`.
+    pub fn frame_rate_sender(&self) -> crossbeam::channel::Sender<(u32, u32)> {
+        self.frame_rate_sender.clone()
+    }
+
+    /// Tempo sources send tap, BPM and MIDI clock events here.
+    pub fn tempo_sender(&self) -> crossbeam::channel::Sender<TempoEvent> {
+        self.tempo_sender.clone()
+    }
+
+    /// Takes time from an external timecode, frames are still scheduled by the frame clock.
+    pub fn chase_timecode(&mut self, chaser: TimecodeChaser) {
+        self.timecode = Some(chaser);
+    }
+
+    /// When the next frame is due, `tick` does nothing before then.
+    /// Callers sleep until this time without holding the keeper, so other threads can still use it.
+    pub fn next_frame_time(&self) -> Instant {
+        self.next_frame_data.scheduled_time
+    }
+
+    fn get_frame_data(&self, frame: u32) -> FrameData {
+        let mut frame_data = self.clock.frame_data(frame);
+        let timecode_position = self
+            .timecode
+            .as_ref()
+            .and_then(|timecode| timecode.position_at(frame_data.scheduled_time));
+        if let Some(position) = timecode_position {
+            frame_data = frame_data.with_elapsed(Duration::from_secs_f64(position));
+        }
+        frame_data.tempo = self.tempo.position_at(frame_data.scheduled_time);
+        frame_data
+    }
+
     pub fn send_next_frame_data_to_non_blocking(
         &self,
         receiver_id: String,
@@ -103,43 +201,55 @@ impl FrameTimeKeeper {
 
 impl ThreadedObject for FrameTimeKeeper {
     fn tick(&mut self) {
-        pollster::block_on(self.clock_frame.tick());
-
-        let last_frame_start = self.frame_stats.frame_start_time;
-        let target_frame_time = self.frame_stats.target_frame_time;
-        let elapsed_time = Instant::now().duration_since(last_frame_start).as_millis();
-
-        if elapsed_time > target_frame_time {
-            self.late_time += elapsed_time - target_frame_time;
-            debug!(
-                "Frame late by {}ms (Took {}ms)",
-                elapsed_time - target_frame_time,
-                elapsed_time
-            );
-        } else if self.late_time > 0 {
-            match self.late_time.checked_sub(target_frame_time - elapsed_time) {
-                Some(val) => self.late_time = val,
-                None => self.late_time = 0,
-            }
+        // Frames are scheduled from the clock epoch rather than the previous frame, so rounding never accumulates
+        let scheduled_time = self.next_frame_data.scheduled_time;
+        if Instant::now() < scheduled_time {
+            return;
         }
 
-        if self.late_time >= target_frame_time * self.frame_buffer_size {
-            warn!("Running late by {}ms", self.late_time);
+        let late_time = Instant::now().saturating_duration_since(scheduled_time);
+        if !late_time.is_zero() {
+            debug!("Frame late by {:.3}ms", late_time.as_secs_f64() * 1000.0);
         }
 
-        // TODO: This is where a change to framerate would happen
-        self.frame_data = self.next_frame_data.clone();
-        self.next_frame_data = FrameData::new(
-            self.frame_data.frame + 1,
-            self.frame_numerator,
-            self.frame_denominator,
-        );
+        let frame_time =
+            get_frame_offset(1, self.clock.frame_numerator, self.clock.frame_denominator);
+        if late_time.as_nanos() >= frame_time.as_nanos() * self.frame_buffer_size {
+            warn!("Running late by {:.3}ms", late_time.as_secs_f64() * 1000.0);
+        }
 
-        self.frame_stats = FrameStats::new(target_frame_time);
+        for event in self.tempo_receiver.try_iter() {
+            self.tempo.handle_event(event);
+        }
+        while let Ok((frame_numerator, frame_denominator)) = self.frame_rate_receiver.try_recv() {
+            self.set_frame_rate(frame_numerator, frame_denominator);
+        }
+        if let Some(timecode) = self.timecode.as_mut() {
+            timecode.update(Instant::now());
+        }
 
-        for (_, sender) in self.frame_data_blocking_senders.write().iter() {
-            sender.send(self.frame_data.clone()).ok();
+        // Recomputed rather than taken from `next_frame_data` so tempo and timecode changes since the last frame are included
+        self.frame += 1;
+        self.frame_data = self.get_frame_data(self.frame);
+        self.next_frame_data = self.get_frame_data(self.frame + 1);
+
+        // The first tick also sends the frames between the current frame and `render_ahead`, so none are skipped
+        let first_render_frame = match self.rendered_frame {
+            Some(rendered_frame) => rendered_frame + 1,
+            None => self.frame,
+        };
+        let last_render_frame = self.frame + self.render_ahead;
+        for render_frame in first_render_frame..=last_render_frame {
+            let render_frame_data = if render_frame == self.frame {
+                self.frame_data.clone()
+            } else {
+                self.get_frame_data(render_frame)
+            };
+            for (_, sender) in self.frame_data_blocking_senders.write().iter() {
+                sender.send(render_frame_data.clone()).ok();
+            }
         }
+        self.rendered_frame = Some(last_render_frame);
 
         for (_, sender) in self.frame_data_non_blocking_senders.write().iter() {
             sender.send(self.frame_data.clone()).ok();
@@ -167,6 +277,7 @@ impl WantsDeviceState for FrameTimeKeeper {
                 crate::devices::manager::ThreadedDeviceType::AuxiliaryData(aux_device) => {
                     let (sender, receiver) = tokio::sync::broadcast::channel(1);
                     aux_device.receive_next_frame_data_buffer(receiver);
+                    aux_device.send_tempo_into(self.tempo_sender());
                     self.send_next_frame_data_to_non_blocking(device_id.unprotect(), sender);
                 }
             }
@@ -175,38 +286,142 @@ impl WantsDeviceState for FrameTimeKeeper {
 }
 
 impl FrameData {
-    pub fn new(frame: u32, frame_numerator: u32, frame_denominator: u32) -> Self {
-        let seconds_elapsed = get_seconds_elapsed(frame, frame_numerator, frame_denominator);
-        let whole_seconds_elapsed = seconds_elapsed.floor() as u32;
+    pub fn new(
+        frame: u32,
+        frame_numerator: u32,
+        frame_denominator: u32,
+        elapsed: Duration,
+        scheduled_time: Instant,
+    ) -> Self {
+        let whole_seconds_elapsed = elapsed.as_secs() as u32;
+        let fractional_seconds_elapsed = elapsed.subsec_nanos() as f32 / 1_000_000_000.0;
 
         FrameData {
             frame,
             frame_numerator,
             frame_denominator,
-            seconds_elapsed,
+            seconds_elapsed: elapsed.as_secs_f32(),
             whole_seconds_elapsed,
+            fractional_seconds_elapsed,
+            scheduled_time,
+            tempo: TempoPosition::default(),
         }
     }
 
+    /// The same frame as seen by something running on its own timeline, `frame` is counted at this frame's rate.
+    pub fn with_elapsed(&self, elapsed: Duration) -> FrameData {
+        let frame = elapsed.as_nanos() * u128::from(self.frame_denominator)
+            / (u128::from(self.frame_numerator.max(1)) * 1_000_000);
+
+        FrameData {
+            tempo: self.tempo,
+            ..FrameData::new(
+                frame as u32,
+                self.frame_numerator,
+                self.frame_denominator,
+                elapsed,
+                self.scheduled_time,
+            )
+        }
+    }
+
+    /// Seconds elapsed without the precision lost by `seconds_elapsed` over long runs.
+    pub fn precise_seconds_elapsed(&self) -> f64 {
+        f64::from(self.whole_seconds_elapsed) + f64::from(self.fractional_seconds_elapsed)
+    }
+
+    /// Fields in the order given by `field_names`, as written to the GPU frame data buffer.
+    pub fn to_gpu_data(&self) -> Vec<f32> {
+        vec![
+            self.frame as f32,
+            self.frame_numerator as f32,
+            self.frame_denominator as f32,
+            self.seconds_elapsed,
+            self.whole_seconds_elapsed as f32,
+            self.fractional_seconds_elapsed,
+            self.tempo.bpm,
+            self.tempo.beat_phase,
+            self.tempo.beat as f32,
+            self.tempo.bar as f32,
+        ]
+    }
+
     pub fn num_fields() -> usize {
-        5
+        FrameData::field_names().len()
+    }
+
+    /// Names of the fields in the order they are written to the GPU frame data buffer.
+    pub fn field_names() -> Vec<&'static str> {
+        FrameData::field_names_for_version(FRAME_DATA_VERSION)
+    }
+
+    /// The fields a shader written for `version` of the layout can see.
+    pub fn field_names_for_version(version: u32) -> Vec<&'static str> {
+        let mut field_names = vec![
+            "frame",
+            "frame_numerator",
+            "frame_denominator",
+            "seconds_elapsed",
+            "whole_seconds_elapsed",
+        ];
+        if version >= 2 {
+            field_names.extend([
+                "fractional_seconds_elapsed",
+                "bpm",
+                "beat_phase",
+                "beat",
+                "bar",
+            ]);
+        }
+        field_names
     }
 }
 
-impl FrameStats {
-    pub fn new(target_frame_time: u128) -> Self {
-        FrameStats {
-            frame_start_time: Instant::now(),
-            target_frame_time,
+impl FrameClock {
+    fn new(frame_numerator: u32, frame_denominator: u32) -> Self {
+        FrameClock {
+            frame_numerator,
+            frame_denominator,
+            epoch_frame: 0,
+            epoch_elapsed: Duration::ZERO,
+            epoch_time: Instant::now(),
         }
     }
+
+    fn frame_data(&self, frame: u32) -> FrameData {
+        let offset = get_frame_offset(
+            frame.saturating_sub(self.epoch_frame),
+            self.frame_numerator,
+            self.frame_denominator,
+        );
+
+        FrameData::new(
+            frame,
+            self.frame_numerator,
+            self.frame_denominator,
+            self.epoch_elapsed + offset,
+            self.epoch_time + offset,
+        )
+    }
+
+    fn set_frame_rate(&mut self, from_frame: u32, frame_numerator: u32, frame_denominator: u32) {
+        let offset = get_frame_offset(
+            from_frame.saturating_sub(self.epoch_frame),
+            self.frame_numerator,
+            self.frame_denominator,
+        );
+
+        self.epoch_frame = from_frame;
+        self.epoch_elapsed += offset;
+        self.epoch_time += offset;
+        self.frame_numerator = frame_numerator;
+        self.frame_denominator = frame_denominator;
+    }
 }
 
-pub fn get_seconds_elapsed(
-    current_frame: u32,
-    frame_numerator: u32,
-    frame_denominator: u32,
-) -> f32 {
-    // TODO: This will drift
-    (current_frame as f32 * (frame_numerator as f32 / frame_denominator as f32)) / 1000.0
+/// Time from frame 0 to `frame`, frame times are `frame_numerator / frame_denominator` milliseconds.
+pub fn get_frame_offset(frame: u32, frame_numerator: u32, frame_denominator: u32) -> Duration {
+    let nanos = u128::from(frame) * u128::from(frame_numerator) * 1_000_000
+        / u128::from(frame_denominator.max(1));
+    Duration::from_nanos(nanos as u64)
 }
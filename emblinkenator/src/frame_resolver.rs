@@ -1,4 +1,8 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Instant,
+};
 
 use log::{debug, info, warn};
 use parking_lot::RwLock;
@@ -15,12 +19,28 @@ use crate::{
 
 pub type LEDFrame = Vec<LED>;
 
+// Resolved frames waiting for their scheduled time, beyond this the oldest are dropped
+const MAX_SCHEDULED_FRAMES: usize = 64;
+
 pub struct FrameResolver {
     input_data_buffer: Receiver<PipelineFrameOutput>,
     animation_manager: Arc<RwLock<AnimationManager>>,
     world_context: Arc<RwLock<WorldContext>>,
     fixture_to_device: HashMap<FixtureId, DeviceId>,
     device_buffers: HashMap<DeviceId, Sender<LEDFrame>>,
+    // Ordered by scheduled time, a frame replaces any queued frames scheduled at or after it
+    scheduled_frames: VecDeque<ScheduledFrame>,
+    // Master level applied to every LED as frames are sent, 0 - 1
+    brightness: f32,
+    // Sends every LED off, without stopping animations
+    blackout: bool,
+}
+
+/// A resolved frame that is sent to devices once its scheduled time is reached.
+struct ScheduledFrame {
+    frame: u32,
+    scheduled_time: Instant,
+    device_frames: Vec<(DeviceId, LEDFrame)>,
 }
 
 struct FrameIntermediate {
@@ -40,6 +60,9 @@ impl FrameResolver {
             world_context,
             fixture_to_device: HashMap::new(),
             device_buffers: HashMap::new(),
+            scheduled_frames: VecDeque::new(),
+            brightness: 1.0,
+            blackout: false,
         }
     }
 
@@ -47,13 +70,95 @@ impl FrameResolver {
         self.fixture_to_device.insert(fixture_id, device_id);
     }
 
+    pub fn remove_fixture_to_device(&mut self, fixture_id: &FixtureId) {
+        self.fixture_to_device.remove(fixture_id);
+    }
+
+    pub fn get_brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    /// Takes effect from the next frame sent, including frames already rendered ahead.
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.brightness = brightness.clamp(0.0, 1.0);
+    }
+
+    pub fn set_blackout(&mut self, blackout: bool) {
+        if blackout != self.blackout {
+            info!("Blackout {}", if blackout { "on" } else { "off" });
+        }
+        self.blackout = blackout;
+    }
+
     pub fn set_device_buffer(&mut self, device_id: DeviceId, sender: Sender<LEDFrame>) {
         self.device_buffers.insert(device_id, sender);
     }
 }
 
-impl ThreadedObject for FrameResolver {
-    fn tick(&mut self) {
+impl FrameResolver {
+    fn schedule_frame(&mut self, scheduled_frame: ScheduledFrame) {
+        if self.scheduled_frames.len() >= MAX_SCHEDULED_FRAMES {
+            if let Some(dropped) = self.scheduled_frames.pop_front() {
+                warn!(
+                    "Frame resolver has too many frames waiting, dropped frame {}",
+                    dropped.frame
+                );
+            }
+        }
+
+        // Frames are rendered in order, so queued frames scheduled at or after a new frame were rendered
+        // before the timeline was re-anchored (e.g. a frame rate change or a timecode jump) and are out of date
+        while let Some(queued_frame) = self.scheduled_frames.back() {
+            if queued_frame.scheduled_time < scheduled_frame.scheduled_time {
+                break;
+            }
+            let replaced = self.scheduled_frames.pop_back().unwrap();
+            debug!(
+                "Dropped frame {} which frame {} replaced",
+                replaced.frame, scheduled_frame.frame
+            );
+        }
+
+        self.scheduled_frames.push_back(scheduled_frame);
+    }
+
+    /// Sends every frame that is due, frames rendered ahead stay queued until their scheduled time.
+    fn send_due_frames(&mut self) {
+        let now = Instant::now();
+
+        while let Some(scheduled_frame) = self.scheduled_frames.front() {
+            if scheduled_frame.scheduled_time > now {
+                break;
+            }
+
+            let scheduled_frame = self.scheduled_frames.pop_front().unwrap();
+            debug!("Sending frame {}", scheduled_frame.frame);
+
+            for (device_id, mut fixture_data) in scheduled_frame.device_frames {
+                self.apply_master(&mut fixture_data);
+                if let Some(sender) = self.device_buffers.get(&device_id) {
+                    debug!("Sending frame to {}", device_id);
+                    sender.send(fixture_data).ok();
+                } else {
+                    warn!("No data buffer exists for {}", device_id.unprotect())
+                }
+            }
+        }
+    }
+
+    fn apply_master(&self, fixture_data: &mut LEDFrame) {
+        if self.blackout {
+            fixture_data.fill(LED::default());
+        } else if self.brightness < 1.0 {
+            for led in fixture_data.iter_mut() {
+                led.r = (f32::from(led.r) * self.brightness).round() as u8;
+                led.g = (f32::from(led.g) * self.brightness).round() as u8;
+                led.b = (f32::from(led.b) * self.brightness).round() as u8;
+            }
+        }
+    }
+
+    fn resolve_frame(&mut self) {
         let mut intermediate_data: HashMap<AnimationTargetType, FrameIntermediate> = HashMap::new(); // TODO: Map<LayerId, Vec<FrameIntermediate>>
         let mut compute_outputs: Vec<PipelineFrameOutput> = vec![];
 
@@ -67,9 +172,10 @@ impl ThreadedObject for FrameResolver {
             },
         }
 
-        if compute_outputs.is_empty() {
-            return;
-        }
+        let (frame, scheduled_time) = match compute_outputs.last() {
+            Some(compute_output) => (compute_output.frame, compute_output.scheduled_time),
+            None => return,
+        };
 
         debug!(
             "Frame Resolver received {} compute outputs",
@@ -114,6 +220,8 @@ impl ThreadedObject for FrameResolver {
             intermediate_data.keys().len()
         );
 
+        let mut device_frames: Vec<(DeviceId, LEDFrame)> = vec![];
+
         // TODO: Merge/combine values on the same target by priority / merge rules
         for (target, data) in intermediate_data {
             let mut chunks: Vec<(FixtureId, u32)> = vec![];
@@ -164,15 +272,22 @@ impl ThreadedObject for FrameResolver {
                     continue;
                 }
 
-                let device_id = device_id.unwrap();
-                if let Some(sender) = self.device_buffers.get(device_id) {
-                    debug!("Sending frame to {}", device_id);
-                    sender.send(fixture_data).ok();
-                } else {
-                    warn!("No data buffer exists for {}", device_id.unprotect())
-                }
+                device_frames.push((device_id.unwrap().clone(), fixture_data));
             }
         }
+
+        self.schedule_frame(ScheduledFrame {
+            frame,
+            scheduled_time,
+            device_frames,
+        });
+    }
+}
+
+impl ThreadedObject for FrameResolver {
+    fn tick(&mut self) {
+        self.resolve_frame();
+        self.send_due_frames();
     }
 }
 
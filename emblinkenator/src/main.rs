@@ -12,20 +12,18 @@ use std::{
     path::Path,
     sync::Arc,
     thread::{self, yield_now, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use animation::AnimationTargetType;
 use clap::Parser;
 use color_eyre::Report;
 
-use config::{StartupAnimationTargetType, StartupConfig};
+use config::StartupConfig;
 use devices::manager::DeviceManager;
 use event_loop::GPUEventLoop;
 use frame_resolver::FrameResolver;
-use futures::executor::block_on;
-use id::{AnimationId, AuxiliaryId, DeviceId, FixtureId, GroupId, InstallationId};
-use log::debug;
+use id::{AnimationId, AuxiliaryId, DeviceId, FixtureId};
+use log::{debug, error};
 use parking_lot::RwLock;
 use pipeline::build_pipeline;
 use state::ThreadedObject;
@@ -36,8 +34,12 @@ use world::{
 
 use crate::config::EmblinkenatorConfig;
 use crate::{
-    animation::manager::AnimationManager, auxiliary_data::manager::AuxiliaryDataManager,
+    animation::manager::AnimationManager,
+    auxiliary_data::{manager::AuxiliaryDataManager, recording::AuxiliaryRecorder},
+    cue::manager::CueManager,
     frame::FrameTimeKeeper,
+    schedule::scheduler::Scheduler,
+    timecode::TimecodeChaser,
 };
 
 use crate::state::EmblinkenatorState;
@@ -49,6 +51,7 @@ extern crate protected_id_derive;
 mod animation;
 mod auxiliary_data;
 mod config;
+mod cue;
 mod devices;
 mod event_loop;
 mod events;
@@ -57,7 +60,10 @@ mod frame_resolver;
 mod id;
 mod led;
 mod pipeline;
+mod schedule;
 mod state;
+mod tempo;
+mod timecode;
 mod world;
 
 // TODO: Set workgroup_size as override constant (blocked, track https://github.com/gfx-rs/wgpu/issues/1762)
@@ -66,6 +72,9 @@ mod world;
 #[clap(version)]
 struct Cli {
     startup_config: String,
+    /// Record auxiliary data received from devices to this file
+    #[clap(long)]
+    record_auxiliaries: Option<String>,
 }
 
 #[tokio::main]
@@ -103,11 +112,16 @@ async fn main() {
     let world_context_collection = WorldContextCollection::new();
 
     // Applies backpressure to move to next frame in time
-    let frame_time_keeper = FrameTimeKeeper::new(
+    let mut frame_time_keeper = FrameTimeKeeper::new(
         frame_numerator,
         frame_denominator,
         u128::from(emblinkenator_config.frame_buffer_size),
+        emblinkenator_config.render_ahead(),
+        &emblinkenator_config.tempo,
     );
+    if let Some(timecode_config) = &emblinkenator_config.timecode {
+        frame_time_keeper.chase_timecode(TimecodeChaser::start(timecode_config));
+    }
     frame_time_keeper.send_frame_data_to_blocking(
         "event_loop".to_string(),
         event_loop_frame_data_buffer_sender,
@@ -124,14 +138,50 @@ async fn main() {
         frame_resolver_buffer_receiver,
     );
     let device_manager = Arc::new(RwLock::new(DeviceManager::new()));
-    let auxiliary_manager = AuxiliaryDataManager::new();
-    let pipeline = build_pipeline(leds_per_compute_group).await;
+    let mut auxiliary_manager = AuxiliaryDataManager::new();
+    let (auxiliary_manager_frame_data_sender, auxiliary_manager_frame_data_receiver) =
+        tokio::sync::broadcast::channel(1);
+    frame_time_keeper.send_next_frame_data_to_non_blocking(
+        "auxiliary_manager".to_string(),
+        auxiliary_manager_frame_data_sender,
+    );
+    auxiliary_manager.receive_next_frame_data_buffer(auxiliary_manager_frame_data_receiver);
+    if let Some(path) = &argv.record_auxiliaries {
+        let recorder =
+            AuxiliaryRecorder::create(Path::new(path), frame_numerator, frame_denominator)
+                .expect("Unable to create auxiliary recording");
+        auxiliary_manager.record_to(recorder);
+    }
+    let pipeline = build_pipeline(
+        leds_per_compute_group,
+        emblinkenator_config.frame_buffer_size(),
+    )
+    .await;
 
     // Put objects behind RwLock if they're not already
     let frame_time_keeper = Arc::new(RwLock::new(frame_time_keeper));
     let frame_resolver = Arc::new(RwLock::new(frame_resolver));
     let auxiliary_manager = Arc::new(RwLock::new(auxiliary_manager));
 
+    let mut cue_manager = CueManager::new(
+        Arc::clone(&animation_manager),
+        Arc::clone(&auxiliary_manager),
+        Arc::clone(&frame_resolver),
+    );
+    cue_manager.send_frame_rate_changes_to(frame_time_keeper.read().frame_rate_sender());
+    let (cue_manager_frame_data_sender, cue_manager_frame_data_receiver) =
+        tokio::sync::broadcast::channel(1);
+    frame_time_keeper
+        .write()
+        .send_next_frame_data_to_non_blocking(
+            "cue_manager".to_string(),
+            cue_manager_frame_data_sender,
+        );
+    cue_manager.receive_next_frame_data_buffer(cue_manager_frame_data_receiver);
+    let cue_manager = Arc::new(RwLock::new(cue_manager));
+
+    let scheduler = Arc::new(RwLock::new(Scheduler::new(cue_manager.read().cue_sender())));
+
     // State manager
     let mut state = EmblinkenatorState::new(
         Arc::clone(&animation_manager),
@@ -139,6 +189,7 @@ async fn main() {
         Arc::clone(&device_manager),
         Arc::clone(&frame_time_keeper),
         Arc::clone(&frame_resolver),
+        Arc::clone(&cue_manager),
         Arc::clone(&world_context),
     );
     state.send_pipeline_context_to(pipeline_context_buffer_sender);
@@ -155,14 +206,17 @@ async fn main() {
     let config_frame_resolver = Arc::clone(&frame_resolver);
     let config_device_manager = Arc::clone(&device_manager);
     let config_auxiliary_manager = Arc::clone(&auxiliary_manager);
+    let config_cue_manager = Arc::clone(&cue_manager);
+    let config_scheduler = Arc::clone(&scheduler);
 
     // Register objects with work loops
     let threaded_objects: Vec<Arc<RwLock<dyn ThreadedObject>>> = vec![
-        frame_time_keeper,
         frame_resolver,
         state,
         device_manager,
         auxiliary_manager,
+        cue_manager,
+        scheduler,
     ];
     let mut handles: Vec<JoinHandle<()>> = vec![];
 
@@ -182,9 +236,19 @@ async fn main() {
         handles.push(handle);
     }
 
-    // TODO: Remove this once event_loop is no longer async (when polling)
+    // Sleeps until each frame is due without holding the frame time keeper
+    handles.push(thread::spawn(move || 'work: loop {
+        let next_frame_time = frame_time_keeper.read().next_frame_time();
+        thread::sleep(next_frame_time.saturating_duration_since(Instant::now()));
+        frame_time_keeper.write().tick();
+
+        if !running {
+            break 'work;
+        }
+    }));
+
     handles.push(thread::spawn(move || 'work: loop {
-        block_on(event_loop.tick());
+        event_loop.tick();
 
         yield_now();
 
@@ -218,25 +282,34 @@ async fn main() {
         }
 
         for animation in startup_config.animations {
-            let target = match animation.target_id {
-                StartupAnimationTargetType::Fixture(id) => {
-                    AnimationTargetType::Fixture(FixtureId::new_from(id))
-                }
-                StartupAnimationTargetType::Installation(id) => {
-                    AnimationTargetType::Installation(InstallationId::new_from(id))
-                }
-                StartupAnimationTargetType::Group(id) => {
-                    AnimationTargetType::Group(GroupId::new_from(id))
-                }
-            };
-            animation_manager
+            let animation_id = animation_manager
                 .write()
                 .create_animation(
                     AnimationId::new_from(animation.id),
                     animation.shader_id,
-                    target,
+                    animation.target_id.into(),
                 )
                 .unwrap();
+
+            for (name, value) in animation.parameters.unwrap_or_default() {
+                if let Err(err) =
+                    animation_manager
+                        .read()
+                        .set_parameter(&animation_id, &name, value)
+                {
+                    error!(
+                        "Cannot set parameter {} of animation {} ({})",
+                        name, animation_id, err
+                    );
+                }
+            }
+
+            if let Some(time_controls) = animation.time {
+                animation_manager
+                    .read()
+                    .set_time_controls(&animation_id, time_controls)
+                    .unwrap();
+            }
         }
 
         for startup_device in startup_config.devices {
@@ -260,20 +333,27 @@ async fn main() {
         debug!("Adding auxiliaries");
 
         for auxiliary in startup_config.auxiliaries {
-            config_auxiliary_manager
-                .write()
-                .add_auxiliary(
-                    auxiliary.clone().into(),
-                    auxiliary.clone().get_name(),
-                    auxiliary.clone().into(),
-                    auxiliary.clone().into(),
-                )
-                .unwrap_or_else(|_| {
-                    panic!(
-                        "Cannot add auxiliary {}",
-                        Into::<AuxiliaryId>::into(auxiliary)
-                    )
-                });
+            if let Err(err) = config_auxiliary_manager.write().add_auxiliary(
+                auxiliary.clone().into(),
+                auxiliary.clone().get_name(),
+                auxiliary.clone().into(),
+                auxiliary.clone().into(),
+            ) {
+                error!("Cannot add auxiliary ({})", err);
+            }
+        }
+
+        for modulation in startup_config.modulations {
+            if let Err(err) = config_auxiliary_manager.read().add_modulation(
+                AuxiliaryId::new_from(modulation.source.clone()),
+                AuxiliaryId::new_from(modulation.destination.clone()),
+                modulation.depth,
+            ) {
+                error!(
+                    "Cannot modulate auxiliary {} by {} ({})",
+                    modulation.destination, modulation.source, err
+                );
+            }
         }
 
         for (animation_id, aux_ids) in startup_config.animation_auxiliary_sources {
@@ -288,6 +368,16 @@ async fn main() {
                 .set_animation_auxiliary_sources_to(animation_id, aux_ids);
         }
 
+        for cue_list in startup_config.cue_lists {
+            config_cue_manager.write().add_cue_list(cue_list);
+        }
+
+        if let Some(schedule) = startup_config.schedule {
+            if let Err(err) = config_scheduler.write().set_schedule(schedule) {
+                error!("Cannot load schedule ({})", err);
+            }
+        }
+
         debug!("Setup complete");
     }));
 
@@ -1,4 +1,8 @@
-use std::{borrow::Cow, mem};
+use std::{
+    borrow::Cow,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use log::info;
 use wgpu::util::DeviceExt;
@@ -8,11 +12,30 @@ use crate::{frame::FrameData, id::AnimationId};
 pub struct EmblinkenatorComputeDevice {
     device: wgpu::Device,
     queue: wgpu::Queue,
+    // Total number of buffers allocated, for measuring how much the pipeline allocates per frame
+    buffers_created: AtomicUsize,
+    // Whether buffers can be cleared by command encoders, not every adapter supports it
+    can_clear_buffers: bool,
 }
 
 impl EmblinkenatorComputeDevice {
     fn new(device: wgpu::Device, queue: wgpu::Queue) -> EmblinkenatorComputeDevice {
-        EmblinkenatorComputeDevice { device, queue }
+        let can_clear_buffers = device.features().contains(wgpu::Features::CLEAR_COMMANDS);
+
+        EmblinkenatorComputeDevice {
+            device,
+            queue,
+            buffers_created: AtomicUsize::new(0),
+            can_clear_buffers,
+        }
+    }
+
+    pub fn buffers_created(&self) -> usize {
+        self.buffers_created.load(Ordering::Relaxed)
+    }
+
+    pub fn can_clear_buffers(&self) -> bool {
+        self.can_clear_buffers
     }
 
     pub fn create_shader_module(&self, id: AnimationId, shader: String) -> wgpu::ShaderModule {
@@ -23,20 +46,8 @@ impl EmblinkenatorComputeDevice {
             })
     }
 
-    pub fn create_zeros_buffer(&self, num_leds: u32) -> wgpu::Buffer {
-        let zeros: Vec<u32> = vec![0; num_leds as usize * 3];
-
-        self.device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Empty Buffer"),
-                contents: bytemuck::cast_slice(&zeros),
-                usage: wgpu::BufferUsages::STORAGE
-                    | wgpu::BufferUsages::COPY_DST
-                    | wgpu::BufferUsages::COPY_SRC,
-            })
-    }
-
     pub fn create_staging_buffer(&self, id: String, result_size: u64) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
         // Staging buffer -> "Output" from GPU.
         self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(format!("Staging Buffer: {}", id).as_str()),
@@ -47,6 +58,7 @@ impl EmblinkenatorComputeDevice {
     }
 
     pub fn create_storage_buffer(&self, id: String, result_size: u64) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
         // Storage buffer -> GPU internal buffer for shader to write to.
         self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(format!("Storage Buffer: {}", id).as_str()),
@@ -59,6 +71,7 @@ impl EmblinkenatorComputeDevice {
     }
 
     pub fn create_positions_buffer_dest(&self, id: String, num_leds: u32) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
         self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(format!("Positions Data Buffer Src: {}", id).as_str()),
             size: ((num_leds * 3) as usize * mem::size_of::<f32>()) as _,
@@ -68,6 +81,7 @@ impl EmblinkenatorComputeDevice {
     }
 
     pub fn create_frame_data_buffer_dest(&self, id: String) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
         self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(format!("Frame Data Buffer Dest: {}", id).as_str()),
             size: (FrameData::num_fields() * mem::size_of::<f32>()) as _,
@@ -77,6 +91,7 @@ impl EmblinkenatorComputeDevice {
     }
 
     pub fn create_auxiliary_data_buffer_dest(&self, id: String, size: u64) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
         self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(format!("Aux Data Buffer Dest: {}", id).as_str()),
             size,
@@ -85,25 +100,18 @@ impl EmblinkenatorComputeDevice {
         })
     }
 
-    pub fn create_positions_buffer_src(&self, id: String, positions: &[f32]) -> wgpu::Buffer {
-        self.device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(format!("Positions Data Buffer Src: {}", id).as_str()),
-                contents: bytemuck::cast_slice(positions),
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            })
-    }
-
-    pub fn create_frame_data_buffer_src(&self, id: String, frame_data: &[f32]) -> wgpu::Buffer {
-        self.device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(format!("Frame Data Buffer Src: {}", id).as_str()),
-                contents: bytemuck::cast_slice(frame_data),
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            })
+    pub fn create_parameters_buffer(&self, id: String, size: u64) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
+        self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(format!("Parameters Buffer: {}", id).as_str()),
+            size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
     }
 
     pub fn create_auxiliary_data_buffer_src(&self, id: String, aux_data: &[u8]) -> wgpu::Buffer {
+        self.buffers_created.fetch_add(1, Ordering::Relaxed);
         self.device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("Auxiliary Data Buffer Src: {}", id)),
@@ -143,6 +151,7 @@ impl EmblinkenatorComputeDevice {
         compute_bind_group_layout: &wgpu::BindGroupLayout,
         result_bind_group_layout: &wgpu::BindGroupLayout,
         auxiliary_bind_group_layout: &wgpu::BindGroupLayout,
+        parameters_bind_group_layout: &wgpu::BindGroupLayout,
     ) -> wgpu::PipelineLayout {
         self.device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -151,6 +160,7 @@ impl EmblinkenatorComputeDevice {
                     compute_bind_group_layout,
                     result_bind_group_layout,
                     auxiliary_bind_group_layout,
+                    parameters_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             })
@@ -178,6 +188,14 @@ impl EmblinkenatorComputeDevice {
             })
     }
 
+    pub fn storage_buffer_offset_alignment(&self) -> u64 {
+        u64::from(self.device.limits().min_storage_buffer_offset_alignment)
+    }
+
+    pub fn write_buffer(&self, buffer: &wgpu::Buffer, data: &[u8]) {
+        self.queue.write_buffer(buffer, 0, data);
+    }
+
     pub fn do_work(&self, encoder: wgpu::CommandBuffer) {
         self.queue.submit(Some(encoder));
     }
@@ -189,6 +207,11 @@ impl EmblinkenatorComputeDevice {
     pub fn poll_device(&self) {
         self.device.poll(wgpu::Maintain::Wait);
     }
+
+    /// Handles any work the GPU has finished without waiting for the rest.
+    pub fn poll_device_non_blocking(&self) {
+        self.device.poll(wgpu::Maintain::Poll);
+    }
 }
 
 // TODO: Turn this into a builder
@@ -234,7 +257,8 @@ pub async fn build_compute_device() -> EmblinkenatorComputeDevice {
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: None,
-                features: wgpu::Features::empty(),
+                // Used to clear the result buffer each frame if available, otherwise zeros are written to it
+                features: adapter.features() & wgpu::Features::CLEAR_COMMANDS,
                 limits: wgpu::Limits::default(),
             },
             trace_dir.ok().as_ref().map(std::path::Path::new),
@@ -1,9 +1,17 @@
 mod compute_device;
-
-use std::{collections::HashMap, convert::TryInto, mem, u64};
+pub mod prelude;
+mod readback;
+mod stats;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+    time::Instant,
+    u64,
+};
 
 use crate::{
-    animation::{Animation, AnimationTargetType},
+    animation::{time::AnimationClock, Animation, AnimationTargetType},
     auxiliary_data::{
         aux_data_consumer_type_is_compatible, aux_data_to_consumer_type, AuxiliaryData,
         AuxiliaryDataType, AuxiliaryDataTypeConsumer,
@@ -15,17 +23,35 @@ use crate::{
 };
 use compute_device::{build_compute_device, EmblinkenatorComputeDevice};
 use log::{debug, error, info, warn};
+use prelude::{
+    AUXILIARY_GROUP, COMPUTE_GROUP, FRAME_DATA_BINDING, PARAMETERS_BINDING, PARAMETERS_GROUP,
+    POSITIONS_BINDING, RESULT_BINDING, RESULT_GROUP,
+};
+use readback::{InFlightFrame, ResultRegion};
+use stats::PipelineStats;
 use strum::IntoEnumIterator;
 
+// Frames that can be computing or waiting to be read back at once
+const MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+// Smallest auxiliary buffer allocated, so values that grow a little don't reallocate every frame
+const MIN_AUXILIARY_BUFFER_SIZE: u64 = 64;
+
 pub struct EmblinkenatorPipeline {
-    state: EmblinkenatorPipelineState,
     leds_per_compute_group: u32,
     compute_device: EmblinkenatorComputeDevice,
-    frame_data_buffer: wgpu::Buffer,
     compute_shaders: HashMap<AnimationId, PipelineEntry>,
+    // Every animation writes its result into one buffer, so results are read back with a single copy and map
+    result_buffers: Option<PipelineResultBuffers>,
+    // Staging buffers not in use by a frame in flight, all the size of the current result buffer
+    staging_buffers: Vec<wgpu::Buffer>,
+    // Oldest first, frames are read back in the order they were computed
+    frames_in_flight: VecDeque<InFlightFrame>,
+    max_frames_in_flight: usize,
     auxiliary_buffers: HashMap<AuxiliaryId, PipelineAuxiliary>,
     empty_auxiliary_buffers: HashMap<AuxiliaryDataTypeConsumer, PipelineAuxiliary>,
     current_context: Option<PipelineContext>,
+    stats: PipelineStats,
 }
 
 #[derive(Clone, Debug)]
@@ -41,36 +67,52 @@ pub struct PipelineContext {
 struct PipelineEntry {
     id: AnimationId,
     target_id: String,
-    storage_buffer: wgpu::Buffer,
-    staging_buffer: wgpu::Buffer,
     compute_pipeline: wgpu::ComputePipeline,
     compute_bind_group: wgpu::BindGroup,
-    result_bind_group: wgpu::BindGroup,
+    // Each animation has its own time, see `AnimationTimeControls`
+    frame_data_buffer: wgpu::Buffer,
+    animation_clock: AnimationClock,
+    result_bind_group_layout: wgpu::BindGroupLayout,
+    // Set by `pack_results` once the animation has a place in the result buffer
+    result_bind_group: Option<wgpu::BindGroup>,
+    result_offset: u64,
     auxiliary_bind_group_layout: wgpu::BindGroupLayout,
+    parameters_buffer: Option<wgpu::Buffer>,
+    parameters_bind_group: wgpu::BindGroup,
     auxiliary_types: Vec<AuxiliaryDataTypeConsumer>,
+    // Bound in place of auxiliaries that are not mapped, one per auxiliary in the manifest
+    default_auxiliary_buffers: Vec<PipelineAuxiliary>,
     positions_data_buffer: wgpu::Buffer,
     num_leds: u32,
     result_size: u64,
     work_group_count: u32,
 }
 
+// Only one storage buffer is needed however many frames are in flight. A frame's clear, compute passes and copy
+// into its staging buffer are one command buffer, and a queue runs command buffers (and the writes queued before
+// them) in the order they're submitted, so the next frame can't touch the storage buffer until the copy is done.
+// Staging buffers are the only per-frame buffers, as they stay mapped until the CPU reads them.
+struct PipelineResultBuffers {
+    storage_buffer: wgpu::Buffer,
+    size: u64,
+    // Written over the storage buffer each frame on devices that can't clear buffers
+    zeros: Option<Vec<u8>>,
+}
+
 struct PipelineAuxiliary {
     buffer: wgpu::Buffer,
     aux_type: AuxiliaryDataTypeConsumer,
+    // Bytes allocated, auxiliaries that change size may use less than this
     size: u64,
 }
 
 #[derive(Clone, Debug)]
 pub struct ComputeOutput {
+    pub frame: u32,
+    pub scheduled_time: Instant,
     pub states: HashMap<String, Vec<LED>>,
 }
 
-#[derive(Debug, PartialEq)]
-enum EmblinkenatorPipelineState {
-    Idle,
-    Computing,
-}
-
 #[derive(Debug, PartialEq)]
 pub enum EmblinkenatorPipelineError {
     WrongState(String),
@@ -79,9 +121,12 @@ pub enum EmblinkenatorPipelineError {
 }
 
 // TODO: Turn into builder
-pub async fn build_pipeline(leds_per_compute_group: u32) -> EmblinkenatorPipeline {
+pub async fn build_pipeline(
+    leds_per_compute_group: u32,
+    frame_buffer_size: u32,
+) -> EmblinkenatorPipeline {
     let compute_device = build_compute_device().await;
-    EmblinkenatorPipeline::new(leds_per_compute_group, compute_device)
+    EmblinkenatorPipeline::new(leds_per_compute_group, frame_buffer_size, compute_device)
 }
 
 impl PipelineContext {
@@ -99,11 +144,9 @@ impl PipelineContext {
 impl EmblinkenatorPipeline {
     pub fn new(
         leds_per_compute_group: u32,
+        frame_buffer_size: u32,
         compute_device: EmblinkenatorComputeDevice,
     ) -> EmblinkenatorPipeline {
-        let frame_data_buffer =
-            compute_device.create_frame_data_buffer_dest("pipeline".to_string());
-
         let mut empty_auxiliary_buffers = HashMap::new();
 
         for aux_type in AuxiliaryDataTypeConsumer::iter() {
@@ -121,14 +164,17 @@ impl EmblinkenatorPipeline {
         }
 
         EmblinkenatorPipeline {
-            state: EmblinkenatorPipelineState::Idle,
             leds_per_compute_group,
             compute_device,
-            frame_data_buffer,
             compute_shaders: HashMap::new(),
+            result_buffers: None,
+            staging_buffers: vec![],
+            frames_in_flight: VecDeque::new(),
+            max_frames_in_flight: (frame_buffer_size as usize).clamp(1, MAX_FRAMES_IN_FLIGHT),
             auxiliary_buffers: HashMap::new(),
             empty_auxiliary_buffers,
             current_context: None,
+            stats: PipelineStats::new(),
         }
     }
 
@@ -136,13 +182,12 @@ impl EmblinkenatorPipeline {
         &mut self,
         context: &PipelineContext,
     ) -> Result<(), EmblinkenatorPipelineError> {
-        if self.state != EmblinkenatorPipelineState::Idle {
-            return Err(EmblinkenatorPipelineError::WrongState(
-                "Pipeline must be in IDLE state in order to call before_frame".to_string(),
-            ));
-        }
-
         let prev_state = self.current_context.replace(context.clone());
+        // Positions are only uploaded when the world changes, rather than every frame
+        let positions_changed = prev_state
+            .as_ref()
+            .map(|prev_state| prev_state.led_positions != context.led_positions)
+            .unwrap_or(true);
         let mut added_animations: Vec<(AnimationId, Animation)> = vec![];
         let mut removed_animations: Vec<AnimationId> = vec![];
         let mut added_auxiliaries: Vec<(AuxiliaryId, AuxiliaryData)> = vec![];
@@ -172,10 +217,22 @@ impl EmblinkenatorPipeline {
             }
 
             for animation in context.animations.iter() {
-                if !prev_state.animations.contains_key(animation.0) {
-                    // New animation
-                    info!("New animation {}", animation.0);
-                    added_animations.push((animation.0.clone(), animation.1.clone()));
+                match prev_state.animations.get(animation.0) {
+                    Some(previous_animation) => {
+                        // An animation recreated with the same id is reloaded
+                        if previous_animation.get_shader_str() != animation.1.get_shader_str()
+                            || previous_animation.get_target_type() != animation.1.get_target_type()
+                        {
+                            info!("Replaced animation {}", animation.0);
+                            removed_animations.push(animation.0.clone());
+                            added_animations.push((animation.0.clone(), animation.1.clone()));
+                        }
+                    }
+                    None => {
+                        // New animation
+                        info!("New animation {}", animation.0);
+                        added_animations.push((animation.0.clone(), animation.1.clone()));
+                    }
                 }
             }
 
@@ -214,12 +271,11 @@ impl EmblinkenatorPipeline {
             self.load_shaders_to_gpu();
         }
 
-        if !resized_auxiliaries.is_empty() {
-            for resized_auxiliary in resized_auxiliaries.into_iter() {
-                self.remove_auxiliary(&resized_auxiliary.0);
-                added_auxiliaries.push(resized_auxiliary);
-            }
-            self.load_shaders_to_gpu();
+        // Bind groups are created every frame, so a reallocated buffer is picked up by every animation using it
+        for (aux_id, auxiliary) in resized_auxiliaries.into_iter() {
+            let required_size =
+                u64::from(auxiliary.size) * aux_data_to_consumer_type(&auxiliary.data).mem_size();
+            self.grow_auxiliary(&aux_id, required_size);
         }
 
         if !added_auxiliaries.is_empty() {
@@ -230,6 +286,8 @@ impl EmblinkenatorPipeline {
             self.load_shaders_to_gpu();
         }
 
+        let animations_changed = !removed_animations.is_empty() || !added_animations.is_empty();
+
         if !removed_animations.is_empty() {
             for removed_animation in removed_animations.iter() {
                 self.remove_shader(removed_animation);
@@ -262,6 +320,14 @@ impl EmblinkenatorPipeline {
             self.load_shaders_to_gpu();
         }
 
+        if animations_changed {
+            self.pack_results();
+        }
+
+        if animations_changed || positions_changed {
+            self.upload_positions(context);
+        }
+
         Ok(())
     }
 
@@ -271,12 +337,6 @@ impl EmblinkenatorPipeline {
         id: AnimationId,
         animation: Animation,
     ) -> Result<(), EmblinkenatorPipelineError> {
-        if self.state != EmblinkenatorPipelineState::Idle {
-            return Err(EmblinkenatorPipelineError::WrongState(
-                "Pipeline must be in IDLE state to add a new shader".to_string(),
-            ));
-        }
-
         let target_id = match &animation.target {
             AnimationTargetType::Fixture(id) => id.unprotect(),
             AnimationTargetType::Installation(id) => id.unprotect(),
@@ -305,15 +365,10 @@ impl EmblinkenatorPipeline {
             .compute_device
             .create_shader_module(animation.id(), animation.get_shader_str());
 
-        debug!("Create storage buffer");
-        let storage_buffer = self
-            .compute_device
-            .create_storage_buffer(id.unprotect(), result_size);
-
-        debug!("Create staging buffer");
-        let staging_buffer = self
+        debug!("Create frame data buffer");
+        let frame_data_buffer = self
             .compute_device
-            .create_staging_buffer(id.unprotect(), result_size);
+            .create_frame_data_buffer_dest(id.unprotect());
 
         debug!("Create positions data buffer");
         let positions_data_buffer = self
@@ -323,9 +378,21 @@ impl EmblinkenatorPipeline {
         debug!("Create auxiliaries");
         let auxiliaries = animation.get_auxiliaries();
         let mut auxiliary_bind_group_entries: Vec<wgpu::BindGroupLayoutEntry> = vec![];
+        let mut default_auxiliary_buffers: Vec<PipelineAuxiliary> = vec![];
 
         if let Some(auxiliaries) = auxiliaries {
-            for _ in auxiliaries.iter() {
+            for auxiliary in auxiliaries.iter() {
+                let default_value = auxiliary.get_default_value();
+                let default_buffer_vec = default_value.to_data_buffer();
+                default_auxiliary_buffers.push(PipelineAuxiliary {
+                    buffer: self.compute_device.create_auxiliary_data_buffer_src(
+                        format!("{} default {}", id.unprotect(), auxiliary.get_name()),
+                        &default_buffer_vec,
+                    ),
+                    aux_type: aux_data_to_consumer_type(&default_value),
+                    size: default_buffer_vec.len() as u64,
+                });
+
                 auxiliary_bind_group_entries.push(wgpu::BindGroupLayoutEntry {
                     binding: auxiliary_bind_group_entries.len() as u32,
                     visibility: wgpu::ShaderStages::COMPUTE,
@@ -342,7 +409,7 @@ impl EmblinkenatorPipeline {
         let compute_bind_group_entries: Vec<wgpu::BindGroupLayoutEntry> = vec![
             // Frame Info
             wgpu::BindGroupLayoutEntry {
-                binding: 0,
+                binding: FRAME_DATA_BINDING,
                 visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Storage { read_only: true },
@@ -355,7 +422,7 @@ impl EmblinkenatorPipeline {
             },
             // LED Positions
             wgpu::BindGroupLayoutEntry {
-                binding: 1,
+                binding: POSITIONS_BINDING,
                 visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Storage { read_only: true },
@@ -368,7 +435,7 @@ impl EmblinkenatorPipeline {
 
         let result_bind_group_entries: Vec<wgpu::BindGroupLayoutEntry> =
             vec![wgpu::BindGroupLayoutEntry {
-                binding: 0,
+                binding: RESULT_BINDING,
                 visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Storage { read_only: false },
@@ -380,19 +447,44 @@ impl EmblinkenatorPipeline {
 
         let compute_group_entries: Vec<wgpu::BindGroupEntry> = vec![
             wgpu::BindGroupEntry {
-                binding: 0,
-                resource: self.frame_data_buffer.as_entire_binding(),
+                binding: FRAME_DATA_BINDING,
+                resource: frame_data_buffer.as_entire_binding(),
             },
             wgpu::BindGroupEntry {
-                binding: 1,
+                binding: POSITIONS_BINDING,
                 resource: positions_data_buffer.as_entire_binding(),
             },
         ];
 
-        let result_group_entries: Vec<wgpu::BindGroupEntry> = vec![wgpu::BindGroupEntry {
-            binding: 0,
-            resource: storage_buffer.as_entire_binding(),
-        }];
+        debug!("Create parameters buffer");
+        let parameters_size = animation.get_parameters_buffer().len() as u64;
+        let mut parameters_bind_group_entries: Vec<wgpu::BindGroupLayoutEntry> = vec![];
+        let mut parameters_buffer: Option<wgpu::Buffer> = None;
+
+        if !animation.get_parameters().is_empty() {
+            parameters_bind_group_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: PARAMETERS_BINDING,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(parameters_size),
+                },
+                count: None,
+            });
+            parameters_buffer.replace(
+                self.compute_device
+                    .create_parameters_buffer(id.unprotect(), parameters_size),
+            );
+        }
+
+        let parameters_group_entries: Vec<wgpu::BindGroupEntry> = parameters_buffer
+            .iter()
+            .map(|buffer| wgpu::BindGroupEntry {
+                binding: PARAMETERS_BINDING,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect();
 
         let result_bind_group_layout = self.compute_device.create_bind_group_layout(
             format!("Result bind group layout: {}", id.unprotect()).as_str(),
@@ -406,10 +498,14 @@ impl EmblinkenatorPipeline {
             format!("Auxiliary bund group layout: {}", id.unprotect()).as_str(),
             &auxiliary_bind_group_entries,
         );
-        let result_bind_group = self.compute_device.create_bind_group(
-            format!("Result bind group: {}", id.unprotect()).as_str(),
-            &result_bind_group_layout,
-            &result_group_entries,
+        let parameters_bind_group_layout = self.compute_device.create_bind_group_layout(
+            format!("Parameters bind group layout: {}", id.unprotect()).as_str(),
+            &parameters_bind_group_entries,
+        );
+        let parameters_bind_group = self.compute_device.create_bind_group(
+            format!("Parameters bind group: {}", id.unprotect()).as_str(),
+            &parameters_bind_group_layout,
+            &parameters_group_entries,
         );
         let compute_bind_group = self.compute_device.create_bind_group(
             format!("Compute bind group: {}", id.unprotect()).as_str(),
@@ -421,6 +517,7 @@ impl EmblinkenatorPipeline {
             &compute_bind_group_layout,
             &result_bind_group_layout,
             &auxiliary_bind_group_layout,
+            &parameters_bind_group_layout,
         );
         let compute_pipeline = self.compute_device.create_shader_compute_pipeline(
             id.unprotect(),
@@ -433,17 +530,22 @@ impl EmblinkenatorPipeline {
             PipelineEntry {
                 id,
                 target_id,
-                storage_buffer,
-                staging_buffer,
                 compute_pipeline,
                 compute_bind_group,
-                result_bind_group,
+                frame_data_buffer,
+                animation_clock: AnimationClock::new(),
+                result_bind_group_layout,
+                result_bind_group: None,
+                result_offset: 0,
                 auxiliary_bind_group_layout,
+                parameters_buffer,
+                parameters_bind_group,
                 positions_data_buffer,
                 num_leds,
                 work_group_count,
                 result_size,
                 auxiliary_types: animation.get_auxiliary_data_types().unwrap_or_default(),
+                default_auxiliary_buffers,
             },
         );
 
@@ -454,9 +556,89 @@ impl EmblinkenatorPipeline {
         self.compute_shaders.remove(id);
     }
 
+    /// Lays every animation's results out in one buffer and rebinds each animation to its region.
+    fn pack_results(&mut self) {
+        let alignment = self.compute_device.storage_buffer_offset_alignment();
+        let mut size: u64 = 0;
+
+        for shader in self.compute_shaders.values_mut() {
+            shader.result_offset = size;
+            // Each region must start at an offset the device can bind storage buffers at
+            size += shader.result_size.div_ceil(alignment) * alignment;
+        }
+
+        // Frames in flight keep their own staging buffers, which are dropped once read if they're the wrong size
+        self.staging_buffers.clear();
+
+        if size == 0 {
+            self.result_buffers.take();
+            return;
+        }
+
+        debug!(
+            "Packing results of {} animations into {} bytes",
+            self.compute_shaders.len(),
+            size
+        );
+
+        let result_buffers = PipelineResultBuffers {
+            storage_buffer: self
+                .compute_device
+                .create_storage_buffer("results".to_string(), size),
+            size,
+            zeros: if self.compute_device.can_clear_buffers() {
+                None
+            } else {
+                Some(vec![0; size as usize])
+            },
+        };
+
+        for shader in self.compute_shaders.values_mut() {
+            shader
+                .result_bind_group
+                .replace(self.compute_device.create_bind_group(
+                    format!("Result bind group: {}", shader.id.unprotect()).as_str(),
+                    &shader.result_bind_group_layout,
+                    &[wgpu::BindGroupEntry {
+                        binding: RESULT_BINDING,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &result_buffers.storage_buffer,
+                            offset: shader.result_offset,
+                            size: wgpu::BufferSize::new(shader.result_size),
+                        }),
+                    }],
+                ));
+        }
+
+        self.result_buffers.replace(result_buffers);
+    }
+
+    fn upload_positions(&self, context: &PipelineContext) {
+        for shader in self.compute_shaders.values() {
+            let led_positions = match context.led_positions.get(&shader.target_id) {
+                Some(led_positions) => led_positions,
+                None => continue,
+            };
+
+            let mut led_positions_flat: Vec<f32> = led_positions
+                .iter()
+                .take(shader.num_leds as usize)
+                .flat_map(|p| p.flat())
+                .collect();
+
+            normalize_vec(&mut led_positions_flat);
+
+            self.compute_device.write_buffer(
+                &shader.positions_data_buffer,
+                bytemuck::cast_slice(&led_positions_flat),
+            );
+        }
+    }
+
     pub fn add_auxiliary(&mut self, id: AuxiliaryId, auxiliary: AuxiliaryData) {
-        let auxiliary_size =
-            u64::from(auxiliary.size) * aux_data_to_consumer_type(&auxiliary.data).mem_size();
+        let auxiliary_size = auxiliary_buffer_size(
+            u64::from(auxiliary.size) * aux_data_to_consumer_type(&auxiliary.data).mem_size(),
+        );
         let auxiliary_buffer = self
             .compute_device
             .create_auxiliary_data_buffer_dest(id.unprotect(), auxiliary_size);
@@ -473,6 +655,29 @@ impl EmblinkenatorPipeline {
         self.auxiliary_buffers.remove(id);
     }
 
+    /// Reallocates the buffer of an auxiliary if it can't hold `required_size` bytes.
+    /// Buffers never shrink, as values such as FFT bins can change size often.
+    fn grow_auxiliary(&mut self, id: &AuxiliaryId, required_size: u64) {
+        let auxiliary = match self.auxiliary_buffers.get_mut(id) {
+            Some(auxiliary) => auxiliary,
+            None => return,
+        };
+
+        if required_size <= auxiliary.size {
+            return;
+        }
+
+        let new_size = auxiliary_buffer_size(required_size);
+        debug!(
+            "Growing buffer of auxiliary {} from {} to {} bytes",
+            id, auxiliary.size, new_size
+        );
+        auxiliary.buffer = self
+            .compute_device
+            .create_auxiliary_data_buffer_dest(id.unprotect(), new_size);
+        auxiliary.size = new_size;
+    }
+
     pub fn load_shaders_to_gpu(&self) {
         self.compute_device.submit_shader();
         self.compute_device.poll_device();
@@ -482,9 +687,9 @@ impl EmblinkenatorPipeline {
         &mut self,
         frame_data: &FrameData,
     ) -> Result<(), EmblinkenatorPipelineError> {
-        if self.state != EmblinkenatorPipelineState::Idle {
+        if !self.can_compute() {
             return Err(EmblinkenatorPipelineError::WrongState(
-                "Pipeline must be in IDLE state to start frame compute".to_string(),
+                "Pipeline must have a frame read back before computing another".to_string(),
             ));
         }
 
@@ -493,58 +698,73 @@ impl EmblinkenatorPipeline {
                 "Pipeline does not have a context set".to_string(),
             ));
         }
-        let context = self.current_context.as_ref().unwrap();
 
-        let mut command_encoder = self.compute_device.create_compute_command_encoder();
+        // Values can change size after the context was compared in `before_frame`, so buffers are checked against the data being copied
+        let auxiliary_data_buffers: Vec<(AuxiliaryId, Vec<u8>)> = self
+            .current_context
+            .as_ref()
+            .unwrap()
+            .auxiliary_data
+            .iter()
+            .map(|(auxiliary_id, auxiliary)| {
+                (auxiliary_id.clone(), auxiliary.data.to_data_buffer())
+            })
+            .collect();
+        for (auxiliary_id, new_aux_data) in auxiliary_data_buffers.iter() {
+            self.grow_auxiliary(auxiliary_id, new_aux_data.len() as u64);
+        }
 
-        let new_frame_data_vec: Vec<f32> = [
-            frame_data.frame as f32,
-            frame_data.frame_numerator as f32,
-            frame_data.frame_denominator as f32,
-            frame_data.seconds_elapsed,
-            frame_data.whole_seconds_elapsed as f32,
-        ]
-        .to_vec();
+        let context = self.current_context.as_ref().unwrap();
 
-        let frame_data_buffer = self
-            .compute_device
-            .create_frame_data_buffer_src(format!("{}", frame_data.frame), &new_frame_data_vec);
-
-        // Copy frame data
-        command_encoder.copy_buffer_to_buffer(
-            &frame_data_buffer,
-            0,
-            &self.frame_data_buffer,
-            0,
-            (new_frame_data_vec.len() * mem::size_of::<f32>()) as u64,
-        );
+        let started = Instant::now();
+        let mut command_encoder = self.compute_device.create_compute_command_encoder();
 
-        // Copy auxiliary data
-        for (auxiliary_id, auxiliary) in context.auxiliary_data.iter() {
+        // Writes are queued and happen before the work below is submitted
+        for (auxiliary_id, new_aux_data) in auxiliary_data_buffers.iter() {
+            if new_aux_data.is_empty() {
+                continue;
+            }
             if let Some(aux_data_dest) = self.auxiliary_buffers.get(auxiliary_id) {
-                let new_aux_data = auxiliary.data.to_data_buffer();
-                let aux_data_src = self
-                    .compute_device
-                    .create_auxiliary_data_buffer_src(auxiliary_id.unprotect(), &new_aux_data);
-
-                command_encoder.copy_buffer_to_buffer(
-                    &aux_data_src,
-                    0,
-                    &aux_data_dest.buffer,
-                    0,
-                    (new_aux_data.len() * mem::size_of::<u8>()) as u64,
-                )
+                self.compute_device
+                    .write_buffer(&aux_data_dest.buffer, new_aux_data);
             }
         }
 
-        for (_id, shader) in self.compute_shaders.iter() {
-            let led_positions = context.led_positions.get(&shader.target_id);
+        if let Some(result_buffers) = &self.result_buffers {
+            match &result_buffers.zeros {
+                Some(zeros) => self
+                    .compute_device
+                    .write_buffer(&result_buffers.storage_buffer, zeros),
+                None => command_encoder.clear_buffer(&result_buffers.storage_buffer, 0, None),
+            }
+        }
 
-            if led_positions.is_none() {
+        let empty_auxiliary_buffers = &self.empty_auxiliary_buffers;
+        for (_id, shader) in self.compute_shaders.iter_mut() {
+            if !context.led_positions.contains_key(&shader.target_id) {
                 warn!("Shader {} does not have any LED positions", shader.id);
                 continue;
             }
 
+            let animation_frame_data = match context.animations.get(&shader.id) {
+                Some(animation) => shader
+                    .animation_clock
+                    .frame_data(frame_data, animation.get_time_controls()),
+                None => frame_data.clone(),
+            };
+            self.compute_device.write_buffer(
+                &shader.frame_data_buffer,
+                bytemuck::cast_slice(&animation_frame_data.to_gpu_data()),
+            );
+
+            if let (Some(parameters_buffer), Some(animation)) = (
+                shader.parameters_buffer.as_ref(),
+                context.animations.get(&shader.id),
+            ) {
+                self.compute_device
+                    .write_buffer(parameters_buffer, &animation.get_parameters_buffer());
+            }
+
             let mut auxiliary_group_entries: Vec<wgpu::BindGroupEntry> = vec![];
 
             let required_auxiliaries = shader.auxiliary_types.clone(); // TODO: Can we just reference this property directly?
@@ -559,7 +779,7 @@ impl EmblinkenatorPipeline {
                 let mapped_aux_id = mapped_auxiliaries.get(index);
                 if mapped_aux_id.is_none() {
                     debug!(
-                        "Auxiliary {} is not mapped for shader {}, an empty buffer will be created",
+                        "Auxiliary {} is not mapped for shader {}, the default value will be used",
                         index, shader.id
                     );
                     invalid_auxiliaries.push((index as u32, required_aux.clone()));
@@ -569,14 +789,14 @@ impl EmblinkenatorPipeline {
 
                 let aux = self.auxiliary_buffers.get(mapped_aux_id);
                 if aux.is_none() {
-                    error!("Auxiliary {} is mapped for shader {} but does not exist in the current context, an empty buffer will be used", index, shader.id);
+                    error!("Auxiliary {} is mapped for shader {} but does not exist in the current context, the default value will be used", index, shader.id);
                     invalid_auxiliaries.push((index as u32, required_aux.clone()));
                     continue;
                 }
                 let aux = aux.unwrap();
 
                 if !aux_data_consumer_type_is_compatible(&aux.aux_type, required_aux) {
-                    error!("Auxiliary {} is mapped for shader {} but is not the right type, an empty buffer will be used", index, shader.id);
+                    error!("Auxiliary {} is mapped for shader {} but is not the right type, the default value will be used", index, shader.id);
                     invalid_auxiliaries.push((index as u32, required_aux.clone()));
                     continue;
                 }
@@ -588,18 +808,28 @@ impl EmblinkenatorPipeline {
             }
 
             for (index, missing_auxiliary_type) in invalid_auxiliaries {
-                let empty_auxiliary_buffer = self
-                    .empty_auxiliary_buffers
-                    .get(&missing_auxiliary_type)
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "Empty buffer should exist for auxilary type {}",
-                            missing_auxiliary_type
+                let default_auxiliary_buffer = shader
+                    .default_auxiliary_buffers
+                    .get(index as usize)
+                    .filter(|default_auxiliary| {
+                        aux_data_consumer_type_is_compatible(
+                            &default_auxiliary.aux_type,
+                            &missing_auxiliary_type,
                         )
+                    })
+                    .unwrap_or_else(|| {
+                        empty_auxiliary_buffers
+                            .get(&missing_auxiliary_type)
+                            .unwrap_or_else(|| {
+                                panic!(
+                                    "Empty buffer should exist for auxilary type {}",
+                                    missing_auxiliary_type
+                                )
+                            })
                     });
                 auxiliary_group_entries.push(wgpu::BindGroupEntry {
                     binding: index,
-                    resource: empty_auxiliary_buffer.buffer.as_entire_binding(),
+                    resource: default_auxiliary_buffer.buffer.as_entire_binding(),
                 })
             }
 
@@ -609,35 +839,13 @@ impl EmblinkenatorPipeline {
                 &auxiliary_group_entries,
             );
 
-            let mut led_positions_flat: Vec<f32> = led_positions
-                .unwrap()
-                .iter()
-                .flat_map(|p| p.flat())
-                .collect();
-
-            normalize_vec(&mut led_positions_flat);
-
-            let led_positions_buffer = self.compute_device.create_positions_buffer_src(
-                format!("{} {}", shader.id.unprotect(), frame_data.frame).to_string(),
-                &led_positions_flat,
-            );
-            let zeros_buffer = self.compute_device.create_zeros_buffer(shader.num_leds);
-
-            // Write 0s to the result buffer
-            command_encoder.copy_buffer_to_buffer(
-                &zeros_buffer,
-                0,
-                &shader.storage_buffer,
-                0,
-                shader.result_size,
-            );
-            command_encoder.copy_buffer_to_buffer(
-                &led_positions_buffer,
-                0,
-                &shader.positions_data_buffer,
-                0,
-                (led_positions_flat.len() * mem::size_of::<f32>()) as u64,
-            );
+            let result_bind_group = match &shader.result_bind_group {
+                Some(result_bind_group) => result_bind_group,
+                None => {
+                    warn!("Shader {} does not have a result buffer", shader.id);
+                    continue;
+                }
+            };
 
             command_encoder.push_debug_group(
                 format!("Compute pattern state {}", shader.id.unprotect()).as_str(),
@@ -648,82 +856,118 @@ impl EmblinkenatorPipeline {
                     label: Some(&shader.id.unprotect()),
                 });
                 cpass.set_pipeline(&shader.compute_pipeline);
-                cpass.set_bind_group(0, &shader.compute_bind_group, &[]);
-                cpass.set_bind_group(1, &shader.result_bind_group, &[]);
-                cpass.set_bind_group(2, &auxiliaries_bind_group, &[]);
+                cpass.set_bind_group(COMPUTE_GROUP, &shader.compute_bind_group, &[]);
+                cpass.set_bind_group(RESULT_GROUP, result_bind_group, &[]);
+                cpass.set_bind_group(AUXILIARY_GROUP, &auxiliaries_bind_group, &[]);
+                cpass.set_bind_group(PARAMETERS_GROUP, &shader.parameters_bind_group, &[]);
                 cpass.dispatch(shader.work_group_count, 1, 1);
             }
             command_encoder.pop_debug_group();
-            command_encoder.copy_buffer_to_buffer(
-                &shader.storage_buffer,
-                0,
-                &shader.staging_buffer,
-                0,
-                shader.result_size,
-            );
         }
 
+        // Each frame in flight is copied into its own staging buffer so it can be read while later frames compute
+        let staging = match &self.result_buffers {
+            Some(result_buffers) => {
+                let staging_buffer = self.staging_buffers.pop().unwrap_or_else(|| {
+                    self.compute_device
+                        .create_staging_buffer("results".to_string(), result_buffers.size)
+                });
+                command_encoder.copy_buffer_to_buffer(
+                    &result_buffers.storage_buffer,
+                    0,
+                    &staging_buffer,
+                    0,
+                    result_buffers.size,
+                );
+                Some((staging_buffer, result_buffers.size))
+            }
+            None => None,
+        };
+
         // Start compute
         self.compute_device.do_work(command_encoder.finish());
 
-        self.state = EmblinkenatorPipelineState::Computing;
+        // Frames without any animations still produce an (empty) output, so devices keep receiving frames
+        let regions: Vec<ResultRegion> = self
+            .compute_shaders
+            .values()
+            .filter(|shader| shader.result_bind_group.is_some())
+            .map(|shader| ResultRegion {
+                id: shader.id.clone(),
+                offset: shader.result_offset,
+                size: shader.result_size,
+                num_leds: shader.num_leds,
+            })
+            .collect();
+        self.frames_in_flight.push_back(InFlightFrame::new(
+            frame_data.frame,
+            frame_data.scheduled_time,
+            started,
+            staging,
+            regions,
+        ));
 
         Ok(())
     }
 
-    pub fn poll_device(&self) {
+    /// Whether another frame can be computed before the oldest frame in flight is read back.
+    pub fn can_compute(&self) -> bool {
+        self.frames_in_flight.len() < self.max_frames_in_flight
+    }
+
+    pub fn has_frames_in_flight(&self) -> bool {
+        !self.frames_in_flight.is_empty()
+    }
+
+    /// Blocks until the GPU has finished the frames in flight.
+    pub fn wait_for_frames_in_flight(&self) {
         self.compute_device.poll_device();
     }
 
-    pub async fn read_led_states(&mut self) -> ComputeOutput {
-        let mut states: HashMap<String, Vec<LED>> = HashMap::new();
-
-        for (_id, shader) in self.compute_shaders.iter() {
-            let buffer_slice = shader.staging_buffer.slice(..);
-            let buffer_future = buffer_slice.map_async(wgpu::MapMode::Read);
-
-            // Need buffer to be mapped
-            self.poll_device();
-
-            // Awaits until `buffer_future` can be read from
-            if let Ok(()) = buffer_future.await {
-                // Gets contents of buffer
-                let data = buffer_slice.get_mapped_range();
-                // Since contents are got in bytes, this converts these bytes back to f32
-                let result: Vec<f32> = data
-                    .chunks_exact(4)
-                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
-                    .collect();
-
-                let state: Vec<LED> = result.chunks(3).map(LED::from).collect();
-
-                if state.len() == shader.num_leds as usize {
-                    states.insert(shader.id.unprotect(), state);
-                } else {
-                    panic!(
-                        "Shader {} did not return enough LED states. Expected {} Got {}",
-                        shader.id.unprotect(),
-                        shader.num_leds,
-                        state.len()
-                    );
-                }
+    /// Reads back the oldest frame in flight if the GPU has finished it, without waiting.
+    pub fn read_led_states(&mut self) -> Option<ComputeOutput> {
+        self.compute_device.poll_device_non_blocking();
 
-                // With the current interface, we have to make sure all mapped views are
-                // dropped before we unmap the buffer.
-                drop(data);
-                shader.staging_buffer.unmap();
-            } else {
-                // TODO: handle this
-                panic!("Failed to read LED data from GPU")
+        let states = match self.frames_in_flight.front_mut()?.try_read()? {
+            Ok(states) => states,
+            // TODO: handle this
+            Err(_) => panic!("Failed to read LED data from GPU"),
+        };
+
+        let in_flight_frame = self.frames_in_flight.pop_front()?;
+        let frame = in_flight_frame.frame;
+        let scheduled_time = in_flight_frame.scheduled_time;
+        self.stats.frame_finished(
+            in_flight_frame.started,
+            self.compute_device.buffers_created(),
+        );
+
+        // Results may have been repacked while the frame was in flight
+        if let (Some((staging_buffer, staging_size)), Some(result_buffers)) =
+            (in_flight_frame.into_staging_buffer(), &self.result_buffers)
+        {
+            if result_buffers.size == staging_size {
+                self.staging_buffers.push(staging_buffer);
             }
         }
 
-        self.state = EmblinkenatorPipelineState::Idle;
-
-        ComputeOutput { states }
+        Some(ComputeOutput {
+            frame,
+            scheduled_time,
+            states,
+        })
     }
 }
 
+/// Rounds up to a power of two so a growing auxiliary is reallocated a handful of times rather than on every change.
+fn auxiliary_buffer_size(required_size: u64) -> u64 {
+    let aligned_size =
+        (required_size + wgpu::COPY_BUFFER_ALIGNMENT - 1) & !(wgpu::COPY_BUFFER_ALIGNMENT - 1);
+    aligned_size
+        .max(MIN_AUXILIARY_BUFFER_SIZE)
+        .next_power_of_two()
+}
+
 fn normalize_vec(data: &mut [f32]) {
     let max_value = data
         .iter()
@@ -0,0 +1,84 @@
+use crate::{
+    animation::parameters::{parameters_wgsl_struct, ParameterManifest},
+    frame::FrameData,
+};
+
+// Bind group layout used by every animation, see `EmblinkenatorPipeline::add_shader`
+pub const COMPUTE_GROUP: u32 = 0;
+pub const FRAME_DATA_BINDING: u32 = 0;
+pub const POSITIONS_BINDING: u32 = 1;
+pub const RESULT_GROUP: u32 = 1;
+pub const RESULT_BINDING: u32 = 0;
+pub const AUXILIARY_GROUP: u32 = 2;
+pub const PARAMETERS_GROUP: u32 = 3;
+pub const PARAMETERS_BINDING: u32 = 0;
+
+/// Generates the WGSL declarations every animation shader needs in order to talk to the pipeline.
+/// This is prepended to shaders by the animation registry so that shaders only need to contain their effect logic.
+/// `FrameData` only declares the fields in `frame_data_version` of the layout.
+pub fn get_shader_prelude(parameters: &[ParameterManifest], frame_data_version: u32) -> String {
+    let frame_data_fields: String = FrameData::field_names_for_version(frame_data_version)
+        .iter()
+        .map(|field| format!("    {}: f32;\n", field))
+        .collect();
+
+    let parameters_declaration = if parameters.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "
+{parameters_struct}
+[[group({parameters_group}), binding({parameters_binding})]]
+var<uniform> parameters: Parameters;
+",
+            parameters_struct = parameters_wgsl_struct(parameters),
+            parameters_group = PARAMETERS_GROUP,
+            parameters_binding = PARAMETERS_BINDING,
+        )
+    };
+
+    format!(
+        "// ---- Emblinkenator prelude (generated) ----
+struct FrameData {{
+{frame_data_fields}}};
+
+struct LED {{
+    r: f32;
+    g: f32;
+    b: f32;
+}};
+
+struct Result {{
+    leds: [[stride(12)]] array<LED>;
+}};
+
+struct Coord {{
+    x: f32;
+    y: f32;
+    z: f32;
+}};
+
+struct Positions {{
+    data: [[stride(12)]] array<Coord>;
+}};
+
+[[group({compute_group}), binding({frame_data_binding})]]
+var<storage, read> params: FrameData;
+
+[[group({compute_group}), binding({positions_binding})]]
+var<storage, read> positions: Positions;
+
+[[group({result_group}), binding({result_binding})]]
+var<storage, read_write> result: Result;
+{parameters_declaration}// ---- End of Emblinkenator prelude ----
+
+",
+        frame_data_fields = frame_data_fields,
+        compute_group = COMPUTE_GROUP,
+        frame_data_binding = FRAME_DATA_BINDING,
+        positions_binding = POSITIONS_BINDING,
+        result_group = RESULT_GROUP,
+        result_binding = RESULT_BINDING,
+        parameters_declaration = parameters_declaration,
+    )
+}
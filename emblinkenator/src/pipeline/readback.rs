@@ -0,0 +1,112 @@
+use std::{collections::HashMap, convert::TryInto, future::Future, pin::Pin, time::Instant};
+
+use futures::FutureExt;
+
+use crate::{id::AnimationId, led::LED};
+
+/// Where an animation's results are in the result buffer.
+pub struct ResultRegion {
+    pub id: AnimationId,
+    pub offset: u64,
+    pub size: u64,
+    pub num_leds: u32,
+}
+
+/// A frame that has been submitted to the GPU and is waiting for its staging buffer to be mapped.
+pub struct InFlightFrame {
+    pub frame: u32,
+    pub scheduled_time: Instant,
+    pub started: Instant,
+    // Frames computed without any animations have no results to read back
+    staging: Option<StagingReadback>,
+}
+
+struct StagingReadback {
+    buffer: wgpu::Buffer,
+    size: u64,
+    // Layout of the result buffer when the frame was submitted, animations may have changed since
+    regions: Vec<ResultRegion>,
+    mapped: Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>,
+}
+
+impl InFlightFrame {
+    pub fn new(
+        frame: u32,
+        scheduled_time: Instant,
+        started: Instant,
+        staging: Option<(wgpu::Buffer, u64)>,
+        regions: Vec<ResultRegion>,
+    ) -> Self {
+        let staging = staging.map(|(buffer, size)| {
+            let mapped = Box::pin(buffer.slice(..).map_async(wgpu::MapMode::Read));
+            StagingReadback {
+                buffer,
+                size,
+                regions,
+                mapped,
+            }
+        });
+
+        InFlightFrame {
+            frame,
+            scheduled_time,
+            started,
+            staging,
+        }
+    }
+
+    /// Reads the LED states once the staging buffer is mapped, `None` if the GPU hasn't finished yet.
+    /// The device must be polled for the mapping to complete.
+    pub fn try_read(
+        &mut self,
+    ) -> Option<Result<HashMap<String, Vec<LED>>, wgpu::BufferAsyncError>> {
+        let mut states: HashMap<String, Vec<LED>> = HashMap::new();
+        let staging = match self.staging.as_mut() {
+            Some(staging) => staging,
+            None => return Some(Ok(states)),
+        };
+
+        if let Err(err) = staging.mapped.as_mut().now_or_never()? {
+            return Some(Err(err));
+        }
+
+        let buffer_slice = staging.buffer.slice(..);
+        // Gets contents of buffer
+        let data = buffer_slice.get_mapped_range();
+
+        for region in staging.regions.iter() {
+            let start = region.offset as usize;
+            let end = start + region.size as usize;
+            // Since contents are got in bytes, this converts these bytes back to f32
+            let result: Vec<f32> = data[start..end]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect();
+
+            let state: Vec<LED> = result.chunks(3).map(LED::from).collect();
+
+            if state.len() == region.num_leds as usize {
+                states.insert(region.id.unprotect(), state);
+            } else {
+                panic!(
+                    "Shader {} did not return enough LED states. Expected {} Got {}",
+                    region.id.unprotect(),
+                    region.num_leds,
+                    state.len()
+                );
+            }
+        }
+
+        // With the current interface, we have to make sure all mapped views are
+        // dropped before we unmap the buffer.
+        drop(data);
+        staging.buffer.unmap();
+
+        Some(Ok(states))
+    }
+
+    /// The staging buffer and its size, for reuse by a later frame once this one has been read.
+    pub fn into_staging_buffer(self) -> Option<(wgpu::Buffer, u64)> {
+        self.staging.map(|staging| (staging.buffer, staging.size))
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+// Number of frames averaged over for each report
+const STATS_REPORT_INTERVAL: u32 = 300;
+
+/// Measures how long frames take from being computed to being read back, and how many buffers they allocate.
+pub struct PipelineStats {
+    frames: u32,
+    total_frame_time: Duration,
+    buffers_created_at_last_report: usize,
+}
+
+impl PipelineStats {
+    pub fn new() -> Self {
+        PipelineStats {
+            frames: 0,
+            total_frame_time: Duration::ZERO,
+            buffers_created_at_last_report: 0,
+        }
+    }
+
+    pub fn frame_finished(&mut self, frame_start: Instant, buffers_created: usize) {
+        self.total_frame_time += frame_start.elapsed();
+        self.frames += 1;
+
+        if self.frames < STATS_REPORT_INTERVAL {
+            return;
+        }
+
+        debug!(
+            "Pipeline took {:.2}ms per frame and allocated {} buffers over the last {} frames",
+            self.total_frame_time.as_secs_f64() * 1000.0 / f64::from(self.frames),
+            buffers_created - self.buffers_created_at_last_report,
+            self.frames
+        );
+
+        self.frames = 0;
+        self.total_frame_time = Duration::ZERO;
+        self.buffers_created_at_last_report = buffers_created;
+    }
+}
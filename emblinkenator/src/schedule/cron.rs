@@ -0,0 +1,240 @@
+use std::fmt::{self, Display};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+
+#[derive(Debug)]
+pub enum CronError {
+    WrongNumberOfFields(usize),
+    InvalidField(String),
+}
+
+impl Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CronError::WrongNumberOfFields(fields) => write!(f, "Found {} fields", fields),
+            CronError::InvalidField(field) => write!(f, "Invalid field {}", field),
+        }
+    }
+}
+
+/// Values a field matches, as bits of a mask.
+#[derive(Clone, Copy)]
+struct CronField {
+    allowed: u64,
+    // Whether the field was anything other than `*`
+    restricted: bool,
+}
+
+/// Days matched by the day of month, month and day of week fields of a cron expression.
+#[derive(Clone)]
+pub struct CronDays {
+    days_of_month: CronField,
+    months: CronField,
+    days_of_week: CronField,
+}
+
+/// A cron expression, "minute hour day-of-month month day-of-week".
+#[derive(Clone)]
+pub struct CronExpression {
+    minutes: CronField,
+    hours: CronField,
+    days: CronDays,
+}
+
+impl CronField {
+    /// Parses lists of `*`, `n` or `n-m`, each optionally stepped by `/s`, e.g. `*/15` or `1-5,0`.
+    fn parse(field: &str, min: u32, max: u32) -> Result<Self, CronError> {
+        let invalid = || CronError::InvalidField(field.to_string());
+        let mut allowed = 0u64;
+
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+                None => (part, 1),
+            };
+            if step == 0 {
+                return Err(invalid());
+            }
+
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once('-') {
+                (
+                    start.parse::<u32>().map_err(|_| invalid())?,
+                    end.parse::<u32>().map_err(|_| invalid())?,
+                )
+            } else {
+                let value = range.parse::<u32>().map_err(|_| invalid())?;
+                // `n/s` starts at n and runs to the end of the range
+                if part.contains('/') {
+                    (value, max)
+                } else {
+                    (value, value)
+                }
+            };
+            if start < min || end > max || start > end {
+                return Err(invalid());
+            }
+
+            for value in (start..=end).step_by(step as usize) {
+                allowed |= 1 << value;
+            }
+        }
+
+        Ok(CronField {
+            allowed,
+            restricted: field != "*",
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.allowed & (1 << value) != 0
+    }
+}
+
+impl CronDays {
+    /// Parses "day-of-month month day-of-week".
+    pub fn parse(days: &str) -> Result<Self, CronError> {
+        match days.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [days_of_month, months, days_of_week] => {
+                CronDays::from_fields(days_of_month, months, days_of_week)
+            }
+            fields => Err(CronError::WrongNumberOfFields(fields.len())),
+        }
+    }
+
+    fn from_fields(
+        days_of_month: &str,
+        months: &str,
+        days_of_week: &str,
+    ) -> Result<Self, CronError> {
+        let mut days_of_week = CronField::parse(days_of_week, 0, 7)?;
+        // Sunday is both 0 and 7
+        if days_of_week.matches(7) {
+            days_of_week.allowed |= 1;
+        }
+
+        Ok(CronDays {
+            days_of_month: CronField::parse(days_of_month, 1, 31)?,
+            months: CronField::parse(months, 1, 12)?,
+            days_of_week,
+        })
+    }
+
+    pub fn matches(&self, date: &NaiveDate) -> bool {
+        if !self.months.matches(date.month()) {
+            return false;
+        }
+
+        let day_of_month = self.days_of_month.matches(date.day());
+        let day_of_week = self
+            .days_of_week
+            .matches(date.weekday().num_days_from_sunday());
+
+        // As in cron, restricting both fields matches days that match either
+        match (self.days_of_month.restricted, self.days_of_week.restricted) {
+            (true, true) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+}
+
+impl CronExpression {
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        match expression
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .as_slice()
+        {
+            [minutes, hours, days_of_month, months, days_of_week] => Ok(CronExpression {
+                minutes: CronField::parse(minutes, 0, 59)?,
+                hours: CronField::parse(hours, 0, 23)?,
+                days: CronDays::from_fields(days_of_month, months, days_of_week)?,
+            }),
+            fields => Err(CronError::WrongNumberOfFields(fields.len())),
+        }
+    }
+
+    pub fn matches(&self, minute: &NaiveDateTime) -> bool {
+        self.minutes.matches(minute.minute())
+            && self.hours.matches(minute.hour())
+            && self.days.matches(&minute.date())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minute(date: &str, time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn matches(expression: &str, date: &str, time: &str) -> bool {
+        CronExpression::parse(expression)
+            .unwrap_or_else(|err| panic!("Cannot parse {} ({})", expression, err))
+            .matches(&minute(date, time))
+    }
+
+    #[test]
+    fn steps_start_from_the_range() {
+        assert!(matches("*/15 * * * *", "2024-06-03", "12:00"));
+        assert!(matches("*/15 * * * *", "2024-06-03", "12:45"));
+        assert!(!matches("*/15 * * * *", "2024-06-03", "12:10"));
+        assert!(matches("5/20 * * * *", "2024-06-03", "12:45"));
+        assert!(!matches("5/20 * * * *", "2024-06-03", "12:00"));
+    }
+
+    #[test]
+    fn ranges_and_lists() {
+        assert!(matches("0 9-17/4 * * *", "2024-06-03", "13:00"));
+        assert!(!matches("0 9-17/4 * * *", "2024-06-03", "15:00"));
+        assert!(matches("0 8,20 * * *", "2024-06-03", "20:00"));
+        assert!(!matches("0 8,20 * * *", "2024-06-03", "08:01"));
+        // 2024-06-03 is a Monday and 2024-06-01 a Saturday
+        assert!(matches("0 12 * * 1-5", "2024-06-03", "12:00"));
+        assert!(!matches("0 12 * * 1-5", "2024-06-01", "12:00"));
+    }
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        // 2024-06-23 is a Sunday
+        assert!(matches("0 0 * * 0", "2024-06-23", "00:00"));
+        assert!(matches("0 0 * * 7", "2024-06-23", "00:00"));
+        assert!(matches("0 0 * * 5-7", "2024-06-23", "00:00"));
+        assert!(!matches("0 0 * * 7", "2024-06-22", "00:00"));
+    }
+
+    #[test]
+    fn restricting_both_days_matches_either() {
+        // The 1st of the month, or any Monday
+        assert!(matches("0 12 1 * 1", "2024-06-01", "12:00"));
+        assert!(matches("0 12 1 * 1", "2024-06-03", "12:00"));
+        assert!(!matches("0 12 1 * 1", "2024-06-04", "12:00"));
+        // With either left as `*`, only the other one counts
+        assert!(!matches("0 12 1 * *", "2024-06-03", "12:00"));
+        assert!(!matches("0 12 * * 1", "2024-06-01", "12:00"));
+        // Months still have to match
+        assert!(!matches("0 12 1 7 1", "2024-06-03", "12:00"));
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(
+                CronExpression::parse(expression).is_err(),
+                "{} should not parse",
+                expression
+            );
+        }
+        assert!(CronDays::parse("* * * *").is_err());
+    }
+}
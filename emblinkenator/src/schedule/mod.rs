@@ -0,0 +1,351 @@
+use std::fmt::{self, Display};
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime, Timelike};
+use serde::Deserialize;
+
+use crate::cue::CueAction;
+
+use self::{
+    cron::{CronDays, CronError, CronExpression},
+    sun::{sun_event_time, SunEvent},
+};
+
+pub mod cron;
+pub mod scheduler;
+pub mod sun;
+
+/// Rules that run actions at wall-clock times, for installations that run unattended.
+#[derive(Deserialize)]
+pub struct ScheduleConfig {
+    // Location sunrise and sunset are calculated for, in degrees, north and east are positive
+    pub latitude: f64,
+    pub longitude: f64,
+    pub rules: Vec<ScheduleRuleConfig>,
+    #[serde(default)]
+    pub holidays: Vec<HolidayConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct ScheduleRuleConfig {
+    pub name: String,
+    pub at: ScheduleTimeConfig,
+    // Seconds that F32 auxiliaries, parameters and brightness take to fade to the rule's values
+    #[serde(default)]
+    pub fade: f64,
+    pub actions: Vec<CueAction>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+pub enum ScheduleTimeConfig {
+    // "minute hour day-of-month month day-of-week", e.g. "30 23 * * 1-5"
+    Cron {
+        expression: String,
+    },
+    // Minutes after sunrise, negative for before, on days matching "day-of-month month day-of-week"
+    Sunrise {
+        #[serde(default)]
+        offset: f64,
+        #[serde(default = "every_day")]
+        days: String,
+    },
+    Sunset {
+        #[serde(default)]
+        offset: f64,
+        #[serde(default = "every_day")]
+        days: String,
+    },
+}
+
+fn every_day() -> String {
+    "* * *".to_string()
+}
+
+/// Dates that run their own rules instead of the usual ones.
+#[derive(Deserialize)]
+pub struct HolidayConfig {
+    pub name: String,
+    // "2024-12-31", "12-25" for every year, or ranges of either such as "12-24..12-26"
+    pub dates: Vec<String>,
+    pub rules: Vec<ScheduleRuleConfig>,
+}
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    InvalidCron(String, CronError),
+    InvalidDate(String),
+}
+
+impl Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::InvalidCron(expression, err) => {
+                write!(f, "{} in \"{}\"", err, expression)
+            }
+            ScheduleError::InvalidDate(dates) => write!(f, "Invalid dates \"{}\"", dates),
+        }
+    }
+}
+
+pub enum ScheduleTime {
+    Cron(CronExpression),
+    Sun {
+        event: SunEvent,
+        offset: Duration,
+        days: CronDays,
+    },
+}
+
+pub struct ScheduleRule {
+    // Position in the schedule, unique across the usual and holiday rules
+    pub index: usize,
+    pub name: String,
+    pub time: ScheduleTime,
+    pub fade: f64,
+    pub actions: Vec<CueAction>,
+}
+
+enum HolidayDates {
+    Dates(NaiveDate, NaiveDate),
+    // (month, day) to (month, day) of every year, which may run over new year
+    Yearly((u32, u32), (u32, u32)),
+}
+
+pub struct Holiday {
+    pub name: String,
+    dates: Vec<HolidayDates>,
+    pub rules: Vec<ScheduleRule>,
+}
+
+pub struct Schedule {
+    latitude: f64,
+    longitude: f64,
+    rules: Vec<ScheduleRule>,
+    holidays: Vec<Holiday>,
+}
+
+impl ScheduleRule {
+    fn from_config(config: ScheduleRuleConfig, index: usize) -> Result<Self, ScheduleError> {
+        let time = match config.at {
+            ScheduleTimeConfig::Cron { expression } => ScheduleTime::Cron(
+                CronExpression::parse(&expression)
+                    .map_err(|err| ScheduleError::InvalidCron(expression, err))?,
+            ),
+            ScheduleTimeConfig::Sunrise { offset, days } => {
+                ScheduleRule::sun_time(SunEvent::Sunrise, offset, days)?
+            }
+            ScheduleTimeConfig::Sunset { offset, days } => {
+                ScheduleRule::sun_time(SunEvent::Sunset, offset, days)?
+            }
+        };
+
+        Ok(ScheduleRule {
+            index,
+            name: config.name,
+            time,
+            fade: config.fade,
+            actions: config.actions,
+        })
+    }
+
+    fn sun_time(event: SunEvent, offset: f64, days: String) -> Result<ScheduleTime, ScheduleError> {
+        Ok(ScheduleTime::Sun {
+            event,
+            offset: Duration::seconds((offset * 60.0).round() as i64),
+            days: CronDays::parse(&days).map_err(|err| ScheduleError::InvalidCron(days, err))?,
+        })
+    }
+}
+
+impl HolidayDates {
+    fn parse(dates: &str) -> Result<Self, ScheduleError> {
+        let (start, end) = dates.split_once("..").unwrap_or((dates, dates));
+
+        if let (Ok(start), Ok(end)) = (
+            NaiveDate::parse_from_str(start, "%Y-%m-%d"),
+            NaiveDate::parse_from_str(end, "%Y-%m-%d"),
+        ) {
+            return Ok(HolidayDates::Dates(start, end));
+        }
+
+        match (HolidayDates::month_day(start), HolidayDates::month_day(end)) {
+            (Some(start), Some(end)) => Ok(HolidayDates::Yearly(start, end)),
+            _ => Err(ScheduleError::InvalidDate(dates.to_string())),
+        }
+    }
+
+    fn month_day(date: &str) -> Option<(u32, u32)> {
+        let (month, day) = date.split_once('-')?;
+        let (month, day) = (month.parse::<u32>().ok()?, day.parse::<u32>().ok()?);
+        // Checked against a leap year so 02-29 is allowed
+        NaiveDate::from_ymd_opt(2000, month, day)?;
+        Some((month, day))
+    }
+
+    fn contains(&self, date: &NaiveDate) -> bool {
+        match self {
+            HolidayDates::Dates(start, end) => start <= date && date <= end,
+            HolidayDates::Yearly(start, end) => {
+                let month_day = (date.month(), date.day());
+                if start <= end {
+                    *start <= month_day && month_day <= *end
+                } else {
+                    *start <= month_day || month_day <= *end
+                }
+            }
+        }
+    }
+}
+
+impl Schedule {
+    pub fn from_config(config: ScheduleConfig) -> Result<Self, ScheduleError> {
+        let mut index = 0;
+        let mut next_index = || {
+            index += 1;
+            index
+        };
+
+        let mut rules = vec![];
+        for rule in config.rules {
+            rules.push(ScheduleRule::from_config(rule, next_index())?);
+        }
+
+        let mut holidays = vec![];
+        for holiday in config.holidays {
+            let mut dates = vec![];
+            for date in holiday.dates {
+                dates.push(HolidayDates::parse(&date)?);
+            }
+
+            let mut rules = vec![];
+            for rule in holiday.rules {
+                rules.push(ScheduleRule::from_config(rule, next_index())?);
+            }
+
+            holidays.push(Holiday {
+                name: holiday.name,
+                dates,
+                rules,
+            });
+        }
+
+        Ok(Schedule {
+            latitude: config.latitude,
+            longitude: config.longitude,
+            rules,
+            holidays,
+        })
+    }
+
+    /// The first holiday that `date` falls in.
+    pub fn holiday_on(&self, date: &NaiveDate) -> Option<&Holiday> {
+        self.holidays
+            .iter()
+            .find(|holiday| holiday.dates.iter().any(|dates| dates.contains(date)))
+    }
+
+    fn rules_on(&self, date: &NaiveDate) -> &[ScheduleRule] {
+        match self.holiday_on(date) {
+            Some(holiday) => &holiday.rules,
+            None => &self.rules,
+        }
+    }
+
+    /// Rules that run at a local minute.
+    pub fn rules_at(&self, minute: &NaiveDateTime) -> Vec<&ScheduleRule> {
+        let date = minute.date();
+        let mut rules: Vec<&ScheduleRule> = self
+            .rules_on(&date)
+            .iter()
+            .filter(|rule| matches!(&rule.time, ScheduleTime::Cron(cron) if cron.matches(minute)))
+            .collect();
+
+        // Offsets can move sunrise and sunset into the day before or after
+        for day in [date.pred(), date, date.succ()] {
+            for rule in self.rules_on(&day) {
+                if let ScheduleTime::Sun {
+                    event,
+                    offset,
+                    days,
+                } = &rule.time
+                {
+                    if days.matches(&day)
+                        && self.sun_rule_time(*event, *offset, day) == Some(*minute)
+                    {
+                        rules.push(rule);
+                    }
+                }
+            }
+        }
+
+        rules
+    }
+
+    /// Local minute a sunrise or sunset rule runs at on `date`.
+    fn sun_rule_time(
+        &self,
+        event: SunEvent,
+        offset: Duration,
+        date: NaiveDate,
+    ) -> Option<NaiveDateTime> {
+        let time = sun_event_time(event, date, self.latitude, self.longitude)?;
+        (time.with_timezone(&Local).naive_local() + offset)
+            .with_second(0)?
+            .with_nanosecond(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn contains(dates: &str, date_in: &str) -> bool {
+        HolidayDates::parse(dates)
+            .unwrap_or_else(|err| panic!("Cannot parse {} ({})", dates, err))
+            .contains(&date(date_in))
+    }
+
+    #[test]
+    fn dates_match_a_day_or_range() {
+        assert!(contains("2024-12-31", "2024-12-31"));
+        assert!(!contains("2024-12-31", "2025-12-31"));
+        assert!(contains("2024-12-24..2025-01-02", "2025-01-01"));
+        assert!(!contains("2024-12-24..2025-01-02", "2025-01-03"));
+    }
+
+    #[test]
+    fn yearly_dates_match_every_year() {
+        assert!(contains("12-25", "2024-12-25"));
+        assert!(contains("12-25", "2031-12-25"));
+        assert!(!contains("12-25", "2024-12-26"));
+        assert!(contains("02-29", "2024-02-29"));
+        assert!(contains("07-01..08-31", "2024-08-15"));
+        assert!(!contains("07-01..08-31", "2024-09-01"));
+    }
+
+    #[test]
+    fn yearly_ranges_wrap_over_new_year() {
+        assert!(contains("12-24..01-02", "2024-12-24"));
+        assert!(contains("12-24..01-02", "2024-12-31"));
+        assert!(contains("12-24..01-02", "2025-01-01"));
+        assert!(contains("12-24..01-02", "2025-01-02"));
+        assert!(!contains("12-24..01-02", "2025-01-03"));
+        assert!(!contains("12-24..01-02", "2024-12-23"));
+        assert!(!contains("12-24..01-02", "2024-06-01"));
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        for dates in ["13-01", "02-30", "12-25..", "2024-02-30", "christmas"] {
+            assert!(
+                HolidayDates::parse(dates).is_err(),
+                "{} should not parse",
+                dates
+            );
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use chrono::{Duration, Local, NaiveDateTime, Timelike};
+use crossbeam::channel::Sender;
+use log::{error, info};
+
+use crate::{cue::CueEvent, state::ThreadedObject};
+
+use super::{Schedule, ScheduleConfig, ScheduleError, ScheduleRule};
+
+// Days looked back over when a schedule starts, to restore what its rules last set
+const CATCH_UP_DAYS: i64 = 7;
+// Clock changes further back than this are treated as the clock being corrected, and the schedule catches up again
+const MAX_CLOCK_GOING_BACK_HOURS: i64 = 2;
+
+/// Runs schedule rules through the cue manager as local time passes them.
+pub struct Scheduler {
+    schedule: Option<Schedule>,
+    cue_sender: Sender<CueEvent>,
+    // Last local minute rules were run for
+    last_checked: Option<NaiveDateTime>,
+}
+
+impl Scheduler {
+    pub fn new(cue_sender: Sender<CueEvent>) -> Self {
+        Scheduler {
+            schedule: None,
+            cue_sender,
+            last_checked: None,
+        }
+    }
+
+    pub fn set_schedule(&mut self, config: ScheduleConfig) -> Result<(), ScheduleError> {
+        self.schedule = Some(Schedule::from_config(config)?);
+        self.last_checked = None;
+        Ok(())
+    }
+
+    /// Runs the last time each rule ran over the past few days, in order, so an installation
+    /// started overnight is still dark.
+    fn catch_up(&self, schedule: &Schedule, now: NaiveDateTime) {
+        let mut last_ran: HashMap<usize, NaiveDateTime> = HashMap::new();
+        let mut minute = now - Duration::days(CATCH_UP_DAYS);
+        while minute <= now {
+            for rule in schedule.rules_at(&minute) {
+                last_ran.insert(rule.index, minute);
+            }
+            minute += Duration::minutes(1);
+        }
+
+        let mut rules: Vec<(NaiveDateTime, &ScheduleRule)> = schedule
+            .rules
+            .iter()
+            .chain(schedule.holidays.iter().flat_map(|holiday| &holiday.rules))
+            .filter_map(|rule| last_ran.get(&rule.index).map(|minute| (*minute, rule)))
+            .collect();
+        rules.sort_by_key(|(minute, rule)| (*minute, rule.index));
+
+        for (_, rule) in rules {
+            self.run_rule(rule);
+        }
+    }
+
+    fn run_rule(&self, rule: &ScheduleRule) {
+        info!("Running scheduled rule {}", rule.name);
+        if let Err(err) = self.cue_sender.send(CueEvent::Apply {
+            name: rule.name.clone(),
+            actions: rule.actions.clone(),
+            fade: rule.fade,
+        }) {
+            error!("Cannot run scheduled rule {} ({:?})", rule.name, err);
+        }
+    }
+}
+
+impl ThreadedObject for Scheduler {
+    fn tick(&mut self) {
+        let schedule = match &self.schedule {
+            Some(schedule) => schedule,
+            None => return,
+        };
+
+        let now = Local::now().naive_local();
+        let now = match now.with_second(0).and_then(|now| now.with_nanosecond(0)) {
+            Some(now) => now,
+            None => return,
+        };
+
+        match self.last_checked {
+            None => self.catch_up(schedule, now),
+            Some(last_checked) if now > last_checked => {
+                let mut minute = last_checked + Duration::minutes(1);
+                while minute <= now {
+                    if minute.hour() == 0 && minute.minute() == 0 {
+                        if let Some(holiday) = schedule.holiday_on(&minute.date()) {
+                            info!("Running holiday rules for {}", holiday.name);
+                        }
+                    }
+                    for rule in schedule.rules_at(&minute) {
+                        self.run_rule(rule);
+                    }
+                    minute += Duration::minutes(1);
+                }
+            }
+            Some(last_checked)
+                if now < last_checked - Duration::hours(MAX_CLOCK_GOING_BACK_HOURS) =>
+            {
+                self.catch_up(schedule, now)
+            }
+            // Within the same minute, or the clocks went back, so wait to reach the last minute again
+            Some(_) => return,
+        }
+
+        self.last_checked = Some(now);
+    }
+}
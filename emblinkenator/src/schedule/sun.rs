@@ -0,0 +1,116 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+// Julian day of 2000-01-01 12:00 UTC
+const J2000: f64 = 2451545.0;
+// Julian day of the Unix epoch
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+// Altitude of the sun's centre at sunrise and sunset, allowing for refraction and the size of the sun
+const SUNRISE_ALTITUDE_DEGREES: f64 = -0.833;
+const EARTH_AXIAL_TILT_DEGREES: f64 = 23.4397;
+
+#[derive(Clone, Copy, Debug)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// Time of sunrise or sunset on `date` at a location, `None` when the sun doesn't rise or set that day.
+/// Uses the sunrise equation, which is accurate to within a minute or two away from the poles.
+pub fn sun_event_time(
+    event: SunEvent,
+    date: NaiveDate,
+    latitude: f64,
+    longitude: f64,
+) -> Option<DateTime<Utc>> {
+    let days_since_j2000 = (date - NaiveDate::from_ymd(2000, 1, 1)).num_days() as f64;
+    // Solar noon, as a number of days since J2000
+    let mean_solar_noon = days_since_j2000 - longitude / 360.0;
+
+    let mean_anomaly = (357.5291 + 0.98560028 * mean_solar_noon).rem_euclid(360.0);
+    let mean_anomaly_radians = mean_anomaly.to_radians();
+    let equation_of_centre = 1.9148 * mean_anomaly_radians.sin()
+        + 0.02 * (2.0 * mean_anomaly_radians).sin()
+        + 0.0003 * (3.0 * mean_anomaly_radians).sin();
+    let ecliptic_longitude =
+        (mean_anomaly + equation_of_centre + 180.0 + 102.9372).rem_euclid(360.0);
+    let ecliptic_longitude_radians = ecliptic_longitude.to_radians();
+
+    let solar_transit = J2000 + mean_solar_noon + 0.0053 * mean_anomaly_radians.sin()
+        - 0.0069 * (2.0 * ecliptic_longitude_radians).sin();
+
+    let declination_sin =
+        ecliptic_longitude_radians.sin() * EARTH_AXIAL_TILT_DEGREES.to_radians().sin();
+    let declination_cos = declination_sin.asin().cos();
+    let latitude_radians = latitude.to_radians();
+    let hour_angle_cos = (SUNRISE_ALTITUDE_DEGREES.to_radians().sin()
+        - latitude_radians.sin() * declination_sin)
+        / (latitude_radians.cos() * declination_cos);
+
+    // The sun stays below or above the horizon all day
+    if !(-1.0..=1.0).contains(&hour_angle_cos) {
+        return None;
+    }
+    let hour_angle_days = hour_angle_cos.acos().to_degrees() / 360.0;
+
+    let julian_day = match event {
+        SunEvent::Sunrise => solar_transit - hour_angle_days,
+        SunEvent::Sunset => solar_transit + hour_angle_days,
+    };
+    let unix_seconds = (julian_day - UNIX_EPOCH_JULIAN_DAY) * 86400.0;
+
+    Utc.timestamp_opt(unix_seconds.round() as i64, 0).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: (f64, f64) = (51.5074, -0.1278);
+    const TROMSO: (f64, f64) = (69.6492, 18.9553);
+
+    fn assert_near(time: Option<DateTime<Utc>>, expected: &str) {
+        let time = time.expect("Sun should rise and set");
+        let expected = Utc.datetime_from_str(expected, "%Y-%m-%d %H:%M").unwrap();
+        let difference = (time - expected).num_seconds().abs();
+        assert!(difference <= 120, "Expected {} but was {}", expected, time);
+    }
+
+    #[test]
+    fn sunrise_and_sunset_in_london() {
+        let (latitude, longitude) = LONDON;
+
+        // 04:43 and 21:21 BST
+        let midsummer = NaiveDate::from_ymd(2024, 6, 21);
+        assert_near(
+            sun_event_time(SunEvent::Sunrise, midsummer, latitude, longitude),
+            "2024-06-21 03:43",
+        );
+        assert_near(
+            sun_event_time(SunEvent::Sunset, midsummer, latitude, longitude),
+            "2024-06-21 20:21",
+        );
+
+        let midwinter = NaiveDate::from_ymd(2024, 12, 21);
+        assert_near(
+            sun_event_time(SunEvent::Sunrise, midwinter, latitude, longitude),
+            "2024-12-21 08:04",
+        );
+        assert_near(
+            sun_event_time(SunEvent::Sunset, midwinter, latitude, longitude),
+            "2024-12-21 15:53",
+        );
+    }
+
+    #[test]
+    fn no_sunrise_or_sunset_within_the_arctic_circle() {
+        let (latitude, longitude) = TROMSO;
+
+        for date in [
+            NaiveDate::from_ymd(2024, 6, 21),
+            NaiveDate::from_ymd(2024, 12, 21),
+        ] {
+            assert!(sun_event_time(SunEvent::Sunrise, date, latitude, longitude).is_none());
+            assert!(sun_event_time(SunEvent::Sunset, date, latitude, longitude).is_none());
+        }
+    }
+}
@@ -7,6 +7,7 @@ use parking_lot::RwLock;
 use crate::{
     animation::manager::AnimationManager,
     auxiliary_data::manager::AuxiliaryDataManager,
+    cue::manager::CueManager,
     devices::manager::{DeviceManager, DeviceManagerEvent, ThreadedDeviceType},
     frame::FrameTimeKeeper,
     frame_resolver::FrameResolver,
@@ -41,6 +42,7 @@ impl EmblinkenatorState {
         device_manager: Arc<RwLock<DeviceManager>>,
         frame_time_keeper: Arc<RwLock<FrameTimeKeeper>>,
         frame_resolver: Arc<RwLock<FrameResolver>>,
+        cue_manager: Arc<RwLock<CueManager>>,
         world_context: Arc<RwLock<WorldContext>>,
     ) -> EmblinkenatorState {
         let device_manager_events = device_manager.write().subscribe_to_events();
@@ -50,7 +52,12 @@ impl EmblinkenatorState {
             device_manager,
             world_context,
             pipeline_context_subscribers: vec![],
-            wants_device_state: vec![auxiliary_data_manager, frame_time_keeper, frame_resolver],
+            wants_device_state: vec![
+                auxiliary_data_manager,
+                frame_time_keeper,
+                frame_resolver,
+                cue_manager,
+            ],
             device_manager_events,
         }
     }
@@ -0,0 +1,218 @@
+use std::{collections::VecDeque, time::Instant};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+
+// Taps further apart than this start a new tempo
+const MAX_TAP_INTERVAL_SECONDS: f64 = 2.0;
+// Number of taps averaged over
+const TAP_HISTORY: usize = 8;
+const MIDI_CLOCK_PULSES_PER_BEAT: u32 = 24;
+const MIN_BPM: f64 = 20.0;
+const MAX_BPM: f64 = 999.0;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TempoConfig {
+    // Tempo used until a tempo source changes it
+    #[serde(default = "default_bpm")]
+    pub bpm: f64,
+    #[serde(default = "default_beats_per_bar")]
+    pub beats_per_bar: u32,
+}
+
+/// Changes to the tempo, sent by tempo sources to the frame time keeper.
+// TODO: Follow an Ableton Link session as a tempo source
+#[derive(Clone, Copy, Debug)]
+pub enum TempoEvent {
+    // Sets the tempo without changing the current beat
+    SetBpm(f64),
+    // A beat tapped by hand, the tempo follows the average time between taps
+    Tap(Instant),
+    // MIDI timing clock, sent 24 times per beat
+    ClockPulse(Instant),
+    // MIDI start, the next clock pulse is the first beat
+    Start(Instant),
+    // MIDI continue, beats carry on from where they stopped
+    Continue(Instant),
+    // MIDI stop, beats stop advancing
+    Stop(Instant),
+}
+
+/// Counts beats at the current tempo.
+/// Beats are anchored to a point in time that moves whenever the tempo changes, so the beat count never jumps.
+pub struct TempoClock {
+    bpm: f64,
+    beats_per_bar: u32,
+    running: bool,
+    anchor_time: Instant,
+    anchor_beats: f64,
+    taps: VecDeque<Instant>,
+    pulses: VecDeque<Instant>,
+    // Pulses since the last MIDI start, once a MIDI clock is being followed
+    pulse_count: Option<u32>,
+}
+
+/// Beat position of a frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TempoPosition {
+    pub bpm: f32,
+    // Progress through the current beat, 0 - 1
+    pub beat_phase: f32,
+    pub beat: u32,
+    pub bar: u32,
+}
+
+impl TempoClock {
+    pub fn new(config: &TempoConfig) -> Self {
+        TempoClock {
+            bpm: config.bpm.clamp(MIN_BPM, MAX_BPM),
+            beats_per_bar: config.beats_per_bar.max(1),
+            running: true,
+            anchor_time: Instant::now(),
+            anchor_beats: 0.0,
+            taps: VecDeque::new(),
+            pulses: VecDeque::new(),
+            pulse_count: None,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: TempoEvent) {
+        match event {
+            TempoEvent::SetBpm(bpm) => self.set_bpm(Instant::now(), bpm),
+            TempoEvent::Tap(time) => self.tap(time),
+            TempoEvent::ClockPulse(time) => self.clock_pulse(time),
+            TempoEvent::Start(time) => {
+                self.reanchor(time, 0.0);
+                self.running = true;
+                self.pulse_count = Some(0);
+                self.pulses.clear();
+            }
+            TempoEvent::Continue(time) => {
+                self.reanchor(time, self.beats_at(time));
+                self.running = true;
+            }
+            TempoEvent::Stop(time) => {
+                self.reanchor(time, self.beats_at(time));
+                self.running = false;
+            }
+        }
+    }
+
+    pub fn position_at(&self, time: Instant) -> TempoPosition {
+        let beats = self.beats_at(time).max(0.0);
+        let beat = beats.floor() as u32;
+
+        TempoPosition {
+            bpm: self.bpm as f32,
+            beat_phase: beats.fract() as f32,
+            beat,
+            bar: beat / self.beats_per_bar,
+        }
+    }
+
+    fn beats_at(&self, time: Instant) -> f64 {
+        if !self.running {
+            return self.anchor_beats;
+        }
+
+        let seconds = if time >= self.anchor_time {
+            time.duration_since(self.anchor_time).as_secs_f64()
+        } else {
+            -self.anchor_time.duration_since(time).as_secs_f64()
+        };
+        self.anchor_beats + seconds * self.bpm / 60.0
+    }
+
+    fn reanchor(&mut self, time: Instant, beats: f64) {
+        self.anchor_time = time;
+        self.anchor_beats = beats;
+    }
+
+    fn set_bpm(&mut self, time: Instant, bpm: f64) {
+        if !bpm.is_finite() || bpm <= 0.0 {
+            return;
+        }
+
+        self.reanchor(time, self.beats_at(time));
+        self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+    }
+
+    fn tap(&mut self, time: Instant) {
+        if let Some(last_tap) = self.taps.back() {
+            if time.saturating_duration_since(*last_tap).as_secs_f64() > MAX_TAP_INTERVAL_SECONDS {
+                self.taps.clear();
+            }
+        }
+        self.taps.push_back(time);
+        if self.taps.len() > TAP_HISTORY {
+            self.taps.pop_front();
+        }
+
+        if let (Some(first_tap), Some(last_tap)) = (self.taps.front(), self.taps.back()) {
+            let span = last_tap.saturating_duration_since(*first_tap).as_secs_f64();
+            if span > 0.0 {
+                let bpm = 60.0 * (self.taps.len() - 1) as f64 / span;
+                self.set_bpm(time, bpm);
+                info!("Tapped tempo of {:.1} BPM", self.bpm);
+            }
+        }
+
+        // Taps land on the nearest beat, which moves the phase by up to half a beat (backwards for an early tap)
+        // Rounding never takes the whole beat count below the beat the tap fell in, so `beat` never goes backwards
+        self.reanchor(time, self.beats_at(time).round());
+    }
+
+    fn clock_pulse(&mut self, time: Instant) {
+        // A clock that was already running when it was first heard carries on from the current beat
+        let pulse_count = match self.pulse_count {
+            Some(pulse_count) => pulse_count,
+            None => (self.beats_at(time).max(0.0) * f64::from(MIDI_CLOCK_PULSES_PER_BEAT)).round()
+                as u32,
+        };
+
+        self.pulses.push_back(time);
+        if self.pulses.len() > MIDI_CLOCK_PULSES_PER_BEAT as usize + 1 {
+            self.pulses.pop_front();
+        }
+
+        if let (Some(first_pulse), Some(last_pulse)) = (self.pulses.front(), self.pulses.back()) {
+            let span = last_pulse
+                .saturating_duration_since(*first_pulse)
+                .as_secs_f64();
+            if span > 0.0 {
+                let bpm = 60.0 * (self.pulses.len() - 1) as f64
+                    / (f64::from(MIDI_CLOCK_PULSES_PER_BEAT) * span);
+                if (bpm - self.bpm).abs() >= 0.1 {
+                    debug!("MIDI clock tempo is {:.1} BPM", bpm);
+                }
+                self.bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+            }
+        }
+
+        // Every pulse is a known position, so the beat count follows the clock exactly
+        if self.running {
+            self.reanchor(
+                time,
+                f64::from(pulse_count) / f64::from(MIDI_CLOCK_PULSES_PER_BEAT),
+            );
+            self.pulse_count = Some(pulse_count + 1);
+        }
+    }
+}
+
+impl Default for TempoConfig {
+    fn default() -> Self {
+        TempoConfig {
+            bpm: default_bpm(),
+            beats_per_bar: default_beats_per_bar(),
+        }
+    }
+}
+
+fn default_bpm() -> f64 {
+    120.0
+}
+
+fn default_beats_per_bar() -> u32 {
+    4
+}
@@ -0,0 +1,110 @@
+use std::{net::UdpSocket, thread, time::Instant};
+
+use crossbeam::channel::Sender;
+use log::{debug, error, warn};
+
+use super::{Timecode, TimecodeEvent, TimecodeRate};
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+// OpTimeCode, sent low byte first
+const OP_TIME_CODE: [u8; 2] = [0x00, 0x97];
+const TIME_CODE_PACKET_SIZE: usize = 19;
+
+/// Reads the timecode from an ArtTimeCode packet, other Art-Net packets are ignored.
+fn parse_packet(packet: &[u8], received: Instant) -> Option<TimecodeEvent> {
+    if packet.len() < TIME_CODE_PACKET_SIZE
+        || &packet[0..8] != ARTNET_ID
+        || packet[8..10] != OP_TIME_CODE
+    {
+        return None;
+    }
+
+    let timecode = Timecode {
+        frames: packet[14],
+        seconds: packet[15],
+        minutes: packet[16],
+        hours: packet[17],
+        rate: TimecodeRate::from_code(packet[18]),
+    };
+
+    Some(TimecodeEvent::new(timecode, 0.0, received))
+}
+
+pub fn start(host: &str, port: u16, sender: Sender<TimecodeEvent>) {
+    let address = format!("{}:{}", host, port);
+    let socket = match UdpSocket::bind(&address) {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!(
+                "Could not listen for Art-Net timecode on {} ({})",
+                address, err
+            );
+            return;
+        }
+    };
+
+    // Receiving blocks, so is done on its own thread
+    thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+
+        loop {
+            let size = match socket.recv(&mut buffer) {
+                Ok(size) => size,
+                Err(err) => {
+                    error!(
+                        "Could not receive Art-Net timecode on {} ({})",
+                        address, err
+                    );
+                    break;
+                }
+            };
+
+            if let Some(event) = parse_packet(&buffer[..size], Instant::now()) {
+                debug!("Received Art-Net timecode {}", event.timecode);
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+
+        warn!("Stopped listening for Art-Net timecode on {}", address);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time_code_packet(opcode: [u8; 2]) -> Vec<u8> {
+        let mut packet = ARTNET_ID.to_vec();
+        packet.extend(opcode);
+        // Protocol version 14 and filler
+        packet.extend([0, 14, 0, 0]);
+        // 01:02:03:04 at 30fps drop frame
+        packet.extend([4, 3, 2, 1, 2]);
+        packet
+    }
+
+    #[test]
+    fn reads_time_code_packets() {
+        let event = parse_packet(&time_code_packet(OP_TIME_CODE), Instant::now()).unwrap();
+
+        assert_eq!(
+            event.timecode,
+            Timecode {
+                hours: 1,
+                minutes: 2,
+                seconds: 3,
+                frames: 4,
+                rate: TimecodeRate::DropFrame2997,
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_other_packets() {
+        // OpDmx
+        assert!(parse_packet(&time_code_packet([0x00, 0x50]), Instant::now()).is_none());
+        assert!(parse_packet(&time_code_packet(OP_TIME_CODE)[..18], Instant::now()).is_none());
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::Sender;
+use log::{debug, error, warn};
+
+use super::{Timecode, TimecodeEvent, TimecodeRate};
+
+const CAPTURE_COMMAND: &str = "arecord";
+const LTC_FRAME_BITS: u32 = 80;
+// Bits 64 - 79 of every frame, used to find where frames start
+const SYNC_WORD: u128 = 0xBFFC;
+// Signal has to cross this level to count as a transition, so noise around zero is ignored
+const HYSTERESIS: f32 = 0.02;
+// Intervals shorter than this fraction of a bit are half a bit
+const HALF_BIT_THRESHOLD: f64 = 0.75;
+// Fraction of each measured bit that the bit period estimate moves towards
+const BIT_PERIOD_GAIN: f64 = 0.05;
+// Range of bit rates followed, allowing for timecode played at other speeds
+const MIN_BITS_PER_SECOND: f64 = 960.0;
+const MAX_BITS_PER_SECOND: f64 = 4800.0;
+
+/// Decodes the biphase mark code of linear timecode.
+/// Every bit starts with a transition, and 1s have another transition half way through.
+struct LTCDecoder {
+    sample_rate: f64,
+    positive: bool,
+    samples_since_transition: u32,
+    // Samples per bit, follows the incoming timecode
+    bit_period: f64,
+    // First half of a 1 has been seen
+    half_bit: Option<u32>,
+    // Last 80 bits received, oldest first from bit 0
+    bits: u128,
+}
+
+impl LTCDecoder {
+    fn new(sample_rate: u32) -> Self {
+        let sample_rate = f64::from(sample_rate);
+        LTCDecoder {
+            sample_rate,
+            positive: false,
+            samples_since_transition: 0,
+            bit_period: sample_rate / 2000.0,
+            half_bit: None,
+            bits: 0,
+        }
+    }
+
+    /// `time` is when the sample was captured.
+    fn push(&mut self, sample: f32, time: Instant) -> Option<TimecodeEvent> {
+        self.samples_since_transition = self.samples_since_transition.saturating_add(1);
+
+        let positive = if sample > HYSTERESIS {
+            true
+        } else if sample < -HYSTERESIS {
+            false
+        } else {
+            self.positive
+        };
+        if positive == self.positive {
+            return None;
+        }
+        self.positive = positive;
+
+        let interval = self.samples_since_transition;
+        self.samples_since_transition = 0;
+
+        if f64::from(interval) < self.bit_period * HALF_BIT_THRESHOLD {
+            match self.half_bit.take() {
+                Some(first_half) => self.push_bit(1, first_half + interval, time),
+                None => {
+                    self.half_bit = Some(interval);
+                    None
+                }
+            }
+        } else {
+            // A lone half bit means a transition was missed, it's dropped so decoding can recover
+            self.half_bit = None;
+            self.push_bit(0, interval, time)
+        }
+    }
+
+    fn push_bit(&mut self, bit: u128, samples: u32, time: Instant) -> Option<TimecodeEvent> {
+        let bit_period = self.bit_period + (f64::from(samples) - self.bit_period) * BIT_PERIOD_GAIN;
+        self.bit_period = bit_period.clamp(
+            self.sample_rate / MAX_BITS_PER_SECOND,
+            self.sample_rate / MIN_BITS_PER_SECOND,
+        );
+
+        self.bits = (self.bits >> 1) | (bit << (LTC_FRAME_BITS - 1));
+        if self.field(64, 16) != SYNC_WORD {
+            return None;
+        }
+
+        let drop_frame = self.field(10, 1) == 1;
+        let timecode = Timecode {
+            frames: (self.field(0, 4) + self.field(8, 2) * 10) as u8,
+            seconds: (self.field(16, 4) + self.field(24, 3) * 10) as u8,
+            minutes: (self.field(32, 4) + self.field(40, 3) * 10) as u8,
+            hours: (self.field(48, 4) + self.field(56, 2) * 10) as u8,
+            rate: self.rate(drop_frame),
+        };
+
+        // The sync word ends the frame, so it arrives a frame after the timecode started
+        Some(TimecodeEvent::new(timecode, 1.0, time))
+    }
+
+    fn field(&self, start: u32, length: u32) -> u128 {
+        (self.bits >> start) & ((1 << length) - 1)
+    }
+
+    /// LTC has no rate field, so it is estimated from how fast bits are arriving.
+    fn rate(&self, drop_frame: bool) -> TimecodeRate {
+        if drop_frame {
+            return TimecodeRate::DropFrame2997;
+        }
+
+        let frames_per_second = self.sample_rate / (self.bit_period * f64::from(LTC_FRAME_BITS));
+        if frames_per_second < 24.5 {
+            TimecodeRate::Film24
+        } else if frames_per_second < 27.5 {
+            TimecodeRate::Ebu25
+        } else {
+            TimecodeRate::Smpte30
+        }
+    }
+}
+
+pub fn start(device: Option<&str>, sample_rate: u32, sender: Sender<TimecodeEvent>) {
+    let mut args: Vec<String> = vec![];
+    if let Some(device) = device {
+        args.push("-D".to_string());
+        args.push(device.to_string());
+    }
+    args.extend(
+        ["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r"]
+            .iter()
+            .map(|arg| arg.to_string()),
+    );
+    args.push(sample_rate.to_string());
+
+    let mut child = match Command::new(CAPTURE_COMMAND)
+        .args(&args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            error!("Could not start LTC capture {} ({})", CAPTURE_COMMAND, err);
+            return;
+        }
+    };
+
+    let mut stdout = match child.stdout.take() {
+        Some(stdout) => stdout,
+        None => return,
+    };
+
+    // Reads from the capture process block, so are done on their own thread
+    thread::spawn(move || {
+        let mut decoder = LTCDecoder::new(sample_rate);
+        let sample_seconds = 1.0 / f64::from(sample_rate);
+        let mut buffer = [0u8; 4096];
+        let mut leftover: Option<u8> = None;
+
+        loop {
+            let size = match stdout.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => size,
+                Err(err) => {
+                    error!(
+                        "Could not read from LTC capture {} ({})",
+                        CAPTURE_COMMAND, err
+                    );
+                    break;
+                }
+            };
+            // Treats the last sample read as captured now, earlier samples were captured before it
+            let read_time = Instant::now();
+
+            let mut bytes: Vec<u8> = leftover.take().into_iter().collect();
+            bytes.extend_from_slice(&buffer[..size]);
+            if bytes.len() % 2 == 1 {
+                leftover = bytes.pop();
+            }
+
+            let num_samples = bytes.len() / 2;
+            for (i, sample) in bytes.chunks_exact(2).enumerate() {
+                let sample = f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32768.0;
+                let age = Duration::from_secs_f64((num_samples - 1 - i) as f64 * sample_seconds);
+                let time = read_time.checked_sub(age).unwrap_or(read_time);
+
+                if let Some(event) = decoder.push(sample, time) {
+                    debug!("Received LTC {}", event.timecode);
+                    if sender.send(event).is_err() {
+                        child.kill().ok();
+                        child.wait().ok();
+                        return;
+                    }
+                }
+            }
+        }
+
+        warn!("LTC capture {} has stopped", CAPTURE_COMMAND);
+        child.wait().ok();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+    // 25fps is 2000 bits per second
+    const SAMPLES_PER_BIT: usize = 24;
+
+    /// The 80 bits of an LTC frame, from bit 0.
+    fn frame_bits(timecode: &Timecode) -> Vec<u8> {
+        let mut bits = vec![0u8; LTC_FRAME_BITS as usize];
+        let mut set_field = |start: usize, length: usize, value: u8| {
+            for bit in 0..length {
+                bits[start + bit] = (value >> bit) & 1;
+            }
+        };
+        set_field(0, 4, timecode.frames % 10);
+        set_field(8, 2, timecode.frames / 10);
+        set_field(16, 4, timecode.seconds % 10);
+        set_field(24, 3, timecode.seconds / 10);
+        set_field(32, 4, timecode.minutes % 10);
+        set_field(40, 3, timecode.minutes / 10);
+        set_field(48, 4, timecode.hours % 10);
+        set_field(56, 2, timecode.hours / 10);
+        for bit in 0..16 {
+            bits[64 + bit] = ((SYNC_WORD >> bit) & 1) as u8;
+        }
+        bits
+    }
+
+    /// Biphase mark code, the level changes at the start of every bit and half way through a 1.
+    fn biphase_samples(bits: &[u8]) -> Vec<f32> {
+        let mut level = 0.5;
+        let mut samples = vec![];
+        for bit in bits {
+            level = -level;
+            for sample in 0..SAMPLES_PER_BIT {
+                if *bit == 1 && sample == SAMPLES_PER_BIT / 2 {
+                    level = -level;
+                }
+                samples.push(level);
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn decodes_biphase_frames() {
+        let timecodes: Vec<Timecode> = (4..7)
+            .map(|frames| Timecode {
+                hours: 1,
+                minutes: 2,
+                seconds: 3,
+                frames,
+                rate: TimecodeRate::Ebu25,
+            })
+            .collect();
+        let mut bits: Vec<u8> = timecodes.iter().flat_map(frame_bits).collect();
+        // A bit ends at the transition starting the next one, so the last frame needs a following bit
+        bits.push(0);
+
+        let mut decoder = LTCDecoder::new(SAMPLE_RATE);
+        let time = Instant::now();
+        let events: Vec<TimecodeEvent> = biphase_samples(&bits)
+            .into_iter()
+            .filter_map(|sample| decoder.push(sample, time))
+            .collect();
+
+        let decoded: Vec<Timecode> = events.iter().map(|event| event.timecode).collect();
+        assert_eq!(decoded, timecodes);
+        // The sync word is the end of the frame
+        assert!((events[0].position - (timecodes[0].seconds() + 0.04)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+mod artnet;
+mod ltc;
+mod mtc;
+
+// Differences from the chased position larger than this many timecode frames are a jump in the timeline, smaller ones are jitter
+const RELOCATE_FRAMES: f64 = 2.0;
+// Timecode has dropped out after this many frames without an update, MTC only completes a timecode every two frames
+const DROPOUT_FRAMES: f64 = 4.0;
+// Fraction of the difference from the chased position corrected each time timecode is received
+const CHASE_GAIN: f64 = 0.1;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TimecodeConfig {
+    pub source: TimecodeSource,
+    // Seconds to keep running for after timecode stops, so short dropouts aren't visible
+    #[serde(default = "default_freewheel")]
+    pub freewheel: f64,
+    // Seconds added to the timecode, e.g. -36000 for a timeline that starts at 10:00:00:00
+    #[serde(default)]
+    pub offset: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type")]
+pub enum TimecodeSource {
+    // MIDI timecode from a raw MIDI device, e.g. /dev/snd/midiC1D0
+    Mtc {
+        device: String,
+    },
+    // Art-Net ArtTimeCode packets
+    ArtNet {
+        #[serde(default = "default_artnet_host")]
+        host: String,
+        #[serde(default = "default_artnet_port")]
+        port: u16,
+    },
+    // Linear timecode decoded from an audio input, captured using `arecord`
+    Ltc {
+        device: Option<String>,
+        #[serde(default = "default_ltc_sample_rate")]
+        sample_rate: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimecodeRate {
+    Film24,
+    Ebu25,
+    DropFrame2997,
+    Smpte30,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: TimecodeRate,
+}
+
+/// A timecode as it was received, sources compensate for any delay built into their format.
+#[derive(Clone, Copy, Debug)]
+pub struct TimecodeEvent {
+    pub timecode: Timecode,
+    // Seconds into the timeline at `received`
+    pub position: f64,
+    pub received: Instant,
+}
+
+#[derive(Debug, PartialEq)]
+enum ChaseState {
+    Waiting,
+    Chasing,
+    Freewheeling,
+    Stopped,
+}
+
+/// Follows an external timecode, running freely between updates and for a while after it stops.
+pub struct TimecodeChaser {
+    receiver: Receiver<TimecodeEvent>,
+    freewheel: Duration,
+    offset: f64,
+    state: ChaseState,
+    // Timeline position at a point in time, positions after it are extrapolated
+    anchor: Option<(Instant, f64)>,
+    last_event: Option<TimecodeEvent>,
+}
+
+impl TimecodeRate {
+    pub fn frames_per_second(&self) -> u32 {
+        match self {
+            TimecodeRate::Film24 => 24,
+            TimecodeRate::Ebu25 => 25,
+            TimecodeRate::DropFrame2997 | TimecodeRate::Smpte30 => 30,
+        }
+    }
+
+    /// Real time each frame lasts for.
+    pub fn frame_seconds(&self) -> f64 {
+        match self {
+            TimecodeRate::DropFrame2997 => 1001.0 / 30000.0,
+            _ => 1.0 / f64::from(self.frames_per_second()),
+        }
+    }
+
+    /// Rate used by the type field of Art-Net timecode and the hours byte of MTC.
+    fn from_code(code: u8) -> TimecodeRate {
+        match code & 0x03 {
+            0 => TimecodeRate::Film24,
+            1 => TimecodeRate::Ebu25,
+            2 => TimecodeRate::DropFrame2997,
+            _ => TimecodeRate::Smpte30,
+        }
+    }
+}
+
+impl Timecode {
+    /// Seconds since 00:00:00:00, drop frame timecode skips frame numbers so that this stays in step with real time.
+    pub fn seconds(&self) -> f64 {
+        let frames_per_second = u64::from(self.rate.frames_per_second());
+        let total_minutes = u64::from(self.hours) * 60 + u64::from(self.minutes);
+        let mut frames = (total_minutes * 60 + u64::from(self.seconds)) * frames_per_second
+            + u64::from(self.frames);
+
+        if self.rate == TimecodeRate::DropFrame2997 {
+            // Frames 0 and 1 are dropped every minute, except every tenth minute
+            frames -= 2 * (total_minutes - total_minutes / 10);
+        }
+
+        frames as f64 * self.rate.frame_seconds()
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = match self.rate {
+            TimecodeRate::DropFrame2997 => ';',
+            _ => ':',
+        };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+impl TimecodeEvent {
+    /// `latency_frames` is how many frames after its timecode started a timecode is received.
+    fn new(timecode: Timecode, latency_frames: f64, received: Instant) -> Self {
+        TimecodeEvent {
+            timecode,
+            position: timecode.seconds() + latency_frames * timecode.rate.frame_seconds(),
+            received,
+        }
+    }
+}
+
+impl TimecodeChaser {
+    /// Starts reading timecode from the configured source.
+    pub fn start(config: &TimecodeConfig) -> Self {
+        let (sender, receiver) = unbounded();
+        start_source(&config.source, sender);
+
+        TimecodeChaser {
+            receiver,
+            freewheel: Duration::from_secs_f64(config.freewheel.max(0.0)),
+            offset: config.offset,
+            state: ChaseState::Waiting,
+            anchor: None,
+            last_event: None,
+        }
+    }
+
+    /// Takes in any timecode received since the last update.
+    pub fn update(&mut self, now: Instant) {
+        let events: Vec<TimecodeEvent> = self.receiver.try_iter().collect();
+        for event in events {
+            self.chase(event);
+        }
+
+        if let Some(last_event) = self.last_event {
+            let since_last_event = now.saturating_duration_since(last_event.received);
+            let rate_frame_seconds = last_event.timecode.rate.frame_seconds();
+
+            if since_last_event > self.freewheel {
+                if self.state != ChaseState::Stopped {
+                    warn!(
+                        "Timecode stopped, holding at {:.3}s",
+                        self.position_at(now).unwrap_or(0.0)
+                    );
+                    self.state = ChaseState::Stopped;
+                }
+            } else if since_last_event.as_secs_f64() > DROPOUT_FRAMES * rate_frame_seconds
+                && self.state == ChaseState::Chasing
+            {
+                info!("Timecode dropped out, freewheeling");
+                self.state = ChaseState::Freewheeling;
+            }
+        }
+    }
+
+    /// Timeline position in seconds at `time`, `None` until timecode has been received.
+    pub fn position_at(&self, time: Instant) -> Option<f64> {
+        let (anchor_time, anchor_position) = self.anchor?;
+
+        // Time stops moving once freewheeling has run out
+        let time = match self.last_event {
+            Some(last_event) => time.min(last_event.received + self.freewheel),
+            None => time,
+        };
+        let seconds = if time >= anchor_time {
+            time.duration_since(anchor_time).as_secs_f64()
+        } else {
+            -anchor_time.duration_since(time).as_secs_f64()
+        };
+
+        Some((anchor_position + seconds + self.offset).max(0.0))
+    }
+
+    fn chase(&mut self, event: TimecodeEvent) {
+        let predicted = self
+            .anchor
+            .filter(|_| self.state == ChaseState::Chasing || self.state == ChaseState::Freewheeling)
+            .map(|(anchor_time, anchor_position)| {
+                anchor_position
+                    + event
+                        .received
+                        .saturating_duration_since(anchor_time)
+                        .as_secs_f64()
+            });
+
+        let position = match predicted {
+            Some(predicted)
+                if (event.position - predicted).abs()
+                    <= RELOCATE_FRAMES * event.timecode.rate.frame_seconds() =>
+            {
+                predicted + (event.position - predicted) * CHASE_GAIN
+            }
+            _ => {
+                info!("Chasing timecode from {}", event.timecode);
+                event.position
+            }
+        };
+
+        self.anchor = Some((event.received, position));
+        self.last_event = Some(event);
+        self.state = ChaseState::Chasing;
+    }
+}
+
+fn start_source(source: &TimecodeSource, sender: Sender<TimecodeEvent>) {
+    match source {
+        TimecodeSource::Mtc { device } => mtc::start(device, sender),
+        TimecodeSource::ArtNet { host, port } => artnet::start(host, *port, sender),
+        TimecodeSource::Ltc {
+            device,
+            sample_rate,
+        } => ltc::start(device.as_deref(), *sample_rate, sender),
+    }
+}
+
+fn default_freewheel() -> f64 {
+    1.0
+}
+
+fn default_artnet_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_artnet_port() -> u16 {
+    6454
+}
+
+fn default_ltc_sample_rate() -> u32 {
+    48000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timecode(hours: u8, minutes: u8, seconds: u8, frames: u8, rate: TimecodeRate) -> Timecode {
+        Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            rate,
+        }
+    }
+
+    #[test]
+    fn non_drop_frame_counts_every_frame() {
+        assert_eq!(timecode(1, 0, 0, 0, TimecodeRate::Ebu25).seconds(), 3600.0);
+        assert_eq!(timecode(0, 0, 1, 12, TimecodeRate::Film24).seconds(), 1.5);
+    }
+
+    #[test]
+    fn drop_frame_skips_two_frames_every_minute() {
+        let frame_seconds = TimecodeRate::DropFrame2997.frame_seconds();
+        let before = timecode(0, 0, 59, 29, TimecodeRate::DropFrame2997).seconds();
+        let after = timecode(0, 1, 0, 2, TimecodeRate::DropFrame2997).seconds();
+
+        assert!((after - before - frame_seconds).abs() < 1e-9);
+    }
+
+    #[test]
+    fn drop_frame_keeps_every_tenth_minute() {
+        let frame_seconds = TimecodeRate::DropFrame2997.frame_seconds();
+        let before = timecode(0, 9, 59, 29, TimecodeRate::DropFrame2997).seconds();
+        let boundary = timecode(0, 10, 0, 0, TimecodeRate::DropFrame2997).seconds();
+        let after = timecode(0, 10, 0, 1, TimecodeRate::DropFrame2997).seconds();
+
+        assert!((boundary - before - frame_seconds).abs() < 1e-9);
+        assert!((after - boundary - frame_seconds).abs() < 1e-9);
+        // 18000 frames less the 18 dropped over the first nine minutes
+        assert!((boundary - 17982.0 * frame_seconds).abs() < 1e-9);
+    }
+
+    #[test]
+    fn drop_frame_stays_in_step_with_real_time() {
+        let hour = timecode(1, 0, 0, 0, TimecodeRate::DropFrame2997).seconds();
+
+        // 108 frames are dropped each hour, which leaves it 3.6ms short
+        assert!((hour - 107892.0 * 1001.0 / 30000.0).abs() < 1e-9);
+        assert!((hour - 3600.0).abs() < 0.005);
+    }
+}
@@ -0,0 +1,219 @@
+use std::{fs::File, io::Read, thread, time::Instant};
+
+use crossbeam::channel::Sender;
+use log::{debug, error, warn};
+
+use super::{Timecode, TimecodeEvent, TimecodeRate};
+
+// A full timecode is spread over 8 quarter frames, which take two frames to send
+const QUARTER_FRAME_LATENCY_FRAMES: f64 = 2.0;
+
+/// Assembles MIDI timecode from quarter frame and full frame messages.
+#[derive(Default)]
+struct MTCParser {
+    // Data bytes of the system exclusive message being read
+    sysex: Option<Vec<u8>>,
+    awaiting_quarter_frame: bool,
+    // Nibbles from quarter frames 0 - 7, and which have been received
+    pieces: [u8; 8],
+    received_pieces: u8,
+}
+
+impl MTCParser {
+    fn push(&mut self, byte: u8, received: Instant) -> Option<TimecodeEvent> {
+        if byte >= 0xF8 {
+            // Real-time messages may appear anywhere
+            return None;
+        }
+
+        if self.awaiting_quarter_frame && byte & 0x80 == 0 {
+            self.awaiting_quarter_frame = false;
+            return self.quarter_frame(byte, received);
+        }
+        self.awaiting_quarter_frame = byte == 0xF1;
+
+        match byte {
+            0xF0 => {
+                self.sysex = Some(vec![]);
+                None
+            }
+            0xF7 => {
+                let sysex = self.sysex.take()?;
+                full_frame(&sysex, received)
+            }
+            byte if byte & 0x80 != 0 => {
+                self.sysex = None;
+                None
+            }
+            byte => {
+                if let Some(sysex) = self.sysex.as_mut() {
+                    sysex.push(byte);
+                }
+                None
+            }
+        }
+    }
+
+    fn quarter_frame(&mut self, data: u8, received: Instant) -> Option<TimecodeEvent> {
+        let piece = usize::from(data >> 4) & 0x07;
+        self.pieces[piece] = data & 0x0F;
+        self.received_pieces |= 1 << piece;
+
+        // Timecode is complete once the last piece arrives, if every piece has been seen
+        if piece != 7 || self.received_pieces != 0xFF {
+            return None;
+        }
+        self.received_pieces = 0;
+
+        let pieces = self.pieces;
+        let timecode = Timecode {
+            frames: pieces[0] | (pieces[1] & 0x01) << 4,
+            seconds: pieces[2] | (pieces[3] & 0x03) << 4,
+            minutes: pieces[4] | (pieces[5] & 0x03) << 4,
+            hours: pieces[6] | (pieces[7] & 0x01) << 4,
+            rate: TimecodeRate::from_code(pieces[7] >> 1),
+        };
+
+        Some(TimecodeEvent::new(
+            timecode,
+            QUARTER_FRAME_LATENCY_FRAMES,
+            received,
+        ))
+    }
+}
+
+/// Full frame messages are sent when the timeline jumps, e.g. F0 7F 7F 01 01 hh mm ss ff F7.
+fn full_frame(sysex: &[u8], received: Instant) -> Option<TimecodeEvent> {
+    match sysex {
+        [0x7F, _, 0x01, 0x01, hours, minutes, seconds, frames] => {
+            let timecode = Timecode {
+                hours: hours & 0x1F,
+                minutes: *minutes,
+                seconds: *seconds,
+                frames: *frames,
+                rate: TimecodeRate::from_code(hours >> 5),
+            };
+            Some(TimecodeEvent::new(timecode, 0.0, received))
+        }
+        _ => None,
+    }
+}
+
+pub fn start(device: &str, sender: Sender<TimecodeEvent>) {
+    let mut file = match File::open(device) {
+        Ok(file) => file,
+        Err(err) => {
+            error!("Could not open MTC device {} ({})", device, err);
+            return;
+        }
+    };
+    let device = device.to_string();
+
+    // Reads from a raw MIDI device block, so are done on their own thread
+    thread::spawn(move || {
+        let mut parser = MTCParser::default();
+        let mut buffer = [0u8; 256];
+
+        loop {
+            let size = match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => size,
+                Err(err) => {
+                    error!("Could not read from MTC device {} ({})", device, err);
+                    break;
+                }
+            };
+            let received = Instant::now();
+
+            for byte in &buffer[..size] {
+                if let Some(event) = parser.push(*byte, received) {
+                    debug!("Received MTC {}", event.timecode);
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+
+        warn!("MTC device {} has closed", device);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 01:02:03:04 at 25fps, as pieces 0 - 7 of quarter frame messages
+    const QUARTER_FRAMES: [u8; 8] = [0x04, 0x10, 0x23, 0x30, 0x42, 0x50, 0x61, 0x72];
+
+    fn push_all(parser: &mut MTCParser, bytes: &[u8], received: Instant) -> Vec<TimecodeEvent> {
+        bytes
+            .iter()
+            .filter_map(|byte| parser.push(*byte, received))
+            .collect()
+    }
+
+    fn quarter_frames(pieces: &[u8]) -> Vec<u8> {
+        pieces.iter().flat_map(|piece| [0xF1, *piece]).collect()
+    }
+
+    #[test]
+    fn assembles_quarter_frames() {
+        let mut parser = MTCParser::default();
+        let received = Instant::now();
+
+        let events = push_all(&mut parser, &quarter_frames(&QUARTER_FRAMES), received);
+
+        assert_eq!(events.len(), 1);
+        let expected = Timecode {
+            hours: 1,
+            minutes: 2,
+            seconds: 3,
+            frames: 4,
+            rate: TimecodeRate::Ebu25,
+        };
+        assert_eq!(events[0].timecode, expected);
+        // The last piece arrives two frames after the timecode it completes
+        assert!((events[0].position - (expected.seconds() + 0.08)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn waits_for_every_quarter_frame() {
+        let mut parser = MTCParser::default();
+        let received = Instant::now();
+
+        // Started listening part way through a timecode
+        let events = push_all(&mut parser, &quarter_frames(&QUARTER_FRAMES[4..]), received);
+        assert!(events.is_empty());
+
+        let events = push_all(&mut parser, &quarter_frames(&QUARTER_FRAMES), received);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].timecode.frames, 4);
+    }
+
+    #[test]
+    fn reads_full_frames() {
+        let mut parser = MTCParser::default();
+        let received = Instant::now();
+
+        // 30fps drop frame, with a timing clock in the middle
+        let events = push_all(
+            &mut parser,
+            &[
+                0xF0, 0x7F, 0x7F, 0x01, 0xF8, 0x01, 0x41, 0x02, 0x03, 0x04, 0xF7,
+            ],
+            received,
+        );
+
+        assert_eq!(events.len(), 1);
+        let expected = Timecode {
+            hours: 1,
+            minutes: 2,
+            seconds: 3,
+            frames: 4,
+            rate: TimecodeRate::DropFrame2997,
+        };
+        assert_eq!(events[0].timecode, expected);
+        assert_eq!(events[0].position, expected.seconds());
+    }
+}
@@ -5,7 +5,7 @@ pub mod fixture;
 pub mod group;
 pub mod installation;
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
 pub struct Coord {
     pub x: f32,
     pub y: f32,
@@ -0,0 +1,71 @@
+// A block of LEDs running along a fixture, showing the colour, integer, boolean and enum auxiliary types
+
+struct Speed {
+    val: f32;
+};
+
+struct Colour {
+    rgb: vec3<f32>;
+};
+
+struct Width {
+    val: u32;
+};
+
+// Bools are 0u or 1u
+struct Reverse {
+    val: u32;
+};
+
+// Index of the selected option, 0 is Hard and 1 is Fade
+struct Tail {
+    val: u32;
+};
+
+[[group(2), binding(0)]]
+var<storage, read> speed: Speed;
+[[group(2), binding(1)]]
+var<storage, read> colour: Colour;
+[[group(2), binding(2)]]
+var<storage, read> width: Width;
+[[group(2), binding(3)]]
+var<storage, read> reverse: Reverse;
+[[group(2), binding(4)]]
+var<storage, read> tail: Tail;
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    var num_leds = arrayLength(&result.leds);
+    var index: u32 = global_id.x;
+    var end: u32 = min(index + 64u, num_leds);
+
+    var head = u32(params.seconds_elapsed * speed.val) % num_leds;
+    var block_width = max(width.val, 1u);
+
+    loop {
+        var led = index;
+        if (reverse.val == 1u) {
+            led = num_leds - 1u - index;
+        }
+
+        // LEDs behind the head, wrapping around the end of the fixture
+        var distance = (head + num_leds - led) % num_leds;
+        var level = 0.0;
+        if (distance < block_width) {
+            level = 1.0;
+            if (tail.val == 1u) {
+                level = 1.0 - f32(distance) / f32(block_width);
+            }
+        }
+
+        result.leds[index].r = colour.rgb.r * level;
+        result.leds[index].g = colour.rgb.g * level;
+        result.leds[index].b = colour.rgb.b * level;
+
+        index = index + 1u;
+
+        if (index >= end) {
+            break;
+        }
+    }
+}
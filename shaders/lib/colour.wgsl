@@ -0,0 +1,16 @@
+// Colour space conversions. All components are in the range 0.0 - 1.0.
+
+fn colour_hsv_to_rgb(hsv: vec3<f32>) -> vec3<f32> {
+    let k: vec4<f32> = vec4<f32>(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+    let p: vec3<f32> = abs(fract(vec3<f32>(hsv.x, hsv.x, hsv.x) + k.xyz) * 6.0 - k.www);
+    return hsv.z * mix(k.xxx, clamp(p - k.xxx, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0)), hsv.y);
+}
+
+fn colour_rgb_to_hsv(rgb: vec3<f32>) -> vec3<f32> {
+    let k: vec4<f32> = vec4<f32>(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+    let p: vec4<f32> = mix(vec4<f32>(rgb.bg, k.wz), vec4<f32>(rgb.gb, k.xy), step(rgb.b, rgb.g));
+    let q: vec4<f32> = mix(vec4<f32>(p.xyw, rgb.r), vec4<f32>(rgb.r, p.yzx), step(p.x, rgb.r));
+    let d: f32 = q.x - min(q.w, q.y);
+    let e: f32 = 1.0e-10;
+    return vec3<f32>(abs(q.z + (q.w - q.y) / (6.0 * d + e)), d / (q.x + e), q.x);
+}
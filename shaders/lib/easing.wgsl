@@ -0,0 +1,47 @@
+// Easing curves, each maps 0.0 - 1.0 onto 0.0 - 1.0.
+
+fn ease_in_quad(t: f32) -> f32 {
+    return t * t;
+}
+
+fn ease_out_quad(t: f32) -> f32 {
+    return 1.0 - (1.0 - t) * (1.0 - t);
+}
+
+fn ease_in_out_quad(t: f32) -> f32 {
+    if (t < 0.5) {
+        return 2.0 * t * t;
+    }
+    let u: f32 = -2.0 * t + 2.0;
+    return 1.0 - u * u / 2.0;
+}
+
+fn ease_in_cubic(t: f32) -> f32 {
+    return t * t * t;
+}
+
+fn ease_out_cubic(t: f32) -> f32 {
+    let u: f32 = 1.0 - t;
+    return 1.0 - u * u * u;
+}
+
+fn ease_in_out_cubic(t: f32) -> f32 {
+    if (t < 0.5) {
+        return 4.0 * t * t * t;
+    }
+    let u: f32 = -2.0 * t + 2.0;
+    return 1.0 - u * u * u / 2.0;
+}
+
+fn ease_in_out_sine(t: f32) -> f32 {
+    return -(cos(3.14159265 * t) - 1.0) / 2.0;
+}
+
+// Goes 0.0 -> 1.0 -> 0.0 over one period
+fn ease_triangle(t: f32) -> f32 {
+    let u: f32 = fract(t) * 2.0;
+    if (u > 1.0) {
+        return 2.0 - u;
+    }
+    return u;
+}
@@ -0,0 +1,52 @@
+// Coherent noise helpers. Values are in the range 0.0 - 1.0.
+
+fn noise_hash(p: vec3<f32>) -> f32 {
+    var q: vec3<f32> = fract(p * 0.3183099 + vec3<f32>(0.1, 0.1, 0.1));
+    q = q * 17.0;
+    return fract(q.x * q.y * q.z * (q.x + q.y + q.z));
+}
+
+fn noise_value(p: vec3<f32>) -> f32 {
+    let i: vec3<f32> = floor(p);
+    let f: vec3<f32> = fract(p);
+    let u: vec3<f32> = f * f * (vec3<f32>(3.0, 3.0, 3.0) - 2.0 * f);
+
+    let c000: f32 = noise_hash(i + vec3<f32>(0.0, 0.0, 0.0));
+    let c100: f32 = noise_hash(i + vec3<f32>(1.0, 0.0, 0.0));
+    let c010: f32 = noise_hash(i + vec3<f32>(0.0, 1.0, 0.0));
+    let c110: f32 = noise_hash(i + vec3<f32>(1.0, 1.0, 0.0));
+    let c001: f32 = noise_hash(i + vec3<f32>(0.0, 0.0, 1.0));
+    let c101: f32 = noise_hash(i + vec3<f32>(1.0, 0.0, 1.0));
+    let c011: f32 = noise_hash(i + vec3<f32>(0.0, 1.0, 1.0));
+    let c111: f32 = noise_hash(i + vec3<f32>(1.0, 1.0, 1.0));
+
+    let x00: f32 = mix(c000, c100, u.x);
+    let x10: f32 = mix(c010, c110, u.x);
+    let x01: f32 = mix(c001, c101, u.x);
+    let x11: f32 = mix(c011, c111, u.x);
+
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z);
+}
+
+// Fractal (octave summed) value noise
+fn noise_fbm(p: vec3<f32>, octaves: u32) -> f32 {
+    var total: f32 = 0.0;
+    var amplitude: f32 = 0.5;
+    var normaliser: f32 = 0.0;
+    var position: vec3<f32> = p;
+    var octave: u32 = 0u;
+
+    loop {
+        if (octave >= octaves) {
+            break;
+        }
+
+        total = total + noise_value(position) * amplitude;
+        normaliser = normaliser + amplitude;
+        amplitude = amplitude * 0.5;
+        position = position * 2.0;
+        octave = octave + 1u;
+    }
+
+    return total / max(normaliser, 0.0001);
+}
@@ -0,0 +1,65 @@
+// Plays an Image auxiliary device onto LEDs by their normalised x and y positions
+
+struct ImageData {
+    width: u32;
+    height: u32;
+    channels: u32;
+    pixels: array<f32>;
+};
+
+[[group(2), binding(0)]]
+var<storage, read> image: ImageData;
+
+fn get_pixel(x: u32, y: u32) -> vec3<f32> {
+    // Pixels are stored column by column, see `image_to_aux_data`
+    let start = (x * image.height + y) * image.channels;
+    return vec3<f32>(image.pixels[start], image.pixels[start + 1u], image.pixels[start + 2u]);
+}
+
+// Bilinear sample, uv of (0, 0) is the top left of the image
+fn sample_image(uv: vec2<f32>) -> vec3<f32> {
+    let size = vec2<f32>(f32(image.width), f32(image.height));
+    let texel = clamp(uv * size - 0.5, vec2<f32>(0.0), size - 1.0);
+    let base = floor(texel);
+    let weight = texel - base;
+
+    let x0 = u32(base.x);
+    let y0 = u32(base.y);
+    let x1 = min(x0 + 1u, image.width - 1u);
+    let y1 = min(y0 + 1u, image.height - 1u);
+
+    let top = mix(get_pixel(x0, y0), get_pixel(x1, y0), weight.x);
+    let bottom = mix(get_pixel(x0, y1), get_pixel(x1, y1), weight.x);
+    return mix(top, bottom, weight.y);
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] global_id: vec3<u32>) {
+    var index: u32 = global_id.x;
+    var end: u32 = min(index + 64u, arrayLength(&result.leds));
+
+    loop {
+        if (index >= end) {
+            break;
+        }
+
+        var colour = vec3<f32>(0.0);
+
+        // Nothing is drawn until the device has loaded an image
+        if (image.width > 0u && image.height > 0u && image.channels >= 3u) {
+            let position = positions.data[index];
+            // LED y increases upwards, image rows go downwards
+            let uv = (vec2<f32>(position.x, 1.0 - position.y) - parameters.offset) / parameters.scale;
+
+            if (all(uv >= vec2<f32>(0.0)) && all(uv <= vec2<f32>(1.0))) {
+                colour = sample_image(uv) * parameters.brightness;
+            }
+        }
+
+        result.leds[index].r = colour.r;
+        result.leds[index].g = colour.g;
+        result.leds[index].b = colour.b;
+
+        index = index + 1u;
+    }
+}
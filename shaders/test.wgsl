@@ -1,27 +1,3 @@
-struct FrameData {
-    frame: f32;
-    frame_numerator: f32;
-    frame_denominator: f32;
-    seconds_elapsed: f32;
-    whole_seconds_elapsed: f32;
-};
-
-struct LED {
-    r: f32;
-    g: f32;
-    b: f32;
-};
-
-struct Result {
-    leds: [[stride(12)]] array<LED>;
-};
-
-struct Coord {
-    x: f32;
-    y: f32;
-    z: f32;
-};
-
 struct StepValue {
     val: f32;
 };
@@ -30,19 +6,6 @@ struct RGBValue {
     val: f32;
 };
 
-struct Positions {
-    data: [[stride(12)]] array<Coord>;
-};
-
-[[group(0), binding(0)]]
-var<storage, read> params: FrameData;
-
-[[group(0), binding(1)]]
-var<storage, read> positions: Positions;
-
-[[group(1), binding(0)]]
-var<storage, read_write> result: Result;
-
 [[group(2), binding(0)]]
 var<storage, read> step_per_sec: StepValue;
 [[group(2), binding(1)]]